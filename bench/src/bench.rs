@@ -1,25 +1,20 @@
 use std::time::Duration;
 
 use criterion::{
-    criterion_group, criterion_main, Bencher, Benchmark, Criterion, Throughput,
+    criterion_group, criterion_main, Bencher, Criterion, Throughput,
 };
 
-const CORPUS_HTML: &'static [u8] = include_bytes!("../../data/html");
-const CORPUS_URLS_10K: &'static [u8] = include_bytes!("../../data/urls.10K");
-const CORPUS_FIREWORKS: &'static [u8] =
-    include_bytes!("../../data/fireworks.jpeg");
-const CORPUS_PAPER_100K: &'static [u8] =
-    include_bytes!("../../data/paper-100k.pdf");
-const CORPUS_HTML_X_4: &'static [u8] = include_bytes!("../../data/html_x_4");
-const CORPUS_ALICE29: &'static [u8] = include_bytes!("../../data/alice29.txt");
-const CORPUS_ASYOULIK: &'static [u8] =
-    include_bytes!("../../data/asyoulik.txt");
-const CORPUS_LCET10: &'static [u8] = include_bytes!("../../data/lcet10.txt");
-const CORPUS_PLRABN12: &'static [u8] =
-    include_bytes!("../../data/plrabn12.txt");
-const CORPUS_GEOPROTO: &'static [u8] =
-    include_bytes!("../../data/geo.protodata");
-const CORPUS_KPPKN: &'static [u8] = include_bytes!("../../data/kppkn.gtb");
+const CORPUS_HTML: &[u8] = include_bytes!("../../data/html");
+const CORPUS_URLS_10K: &[u8] = include_bytes!("../../data/urls.10K");
+const CORPUS_FIREWORKS: &[u8] = include_bytes!("../../data/fireworks.jpeg");
+const CORPUS_PAPER_100K: &[u8] = include_bytes!("../../data/paper-100k.pdf");
+const CORPUS_HTML_X_4: &[u8] = include_bytes!("../../data/html_x_4");
+const CORPUS_ALICE29: &[u8] = include_bytes!("../../data/alice29.txt");
+const CORPUS_ASYOULIK: &[u8] = include_bytes!("../../data/asyoulik.txt");
+const CORPUS_LCET10: &[u8] = include_bytes!("../../data/lcet10.txt");
+const CORPUS_PLRABN12: &[u8] = include_bytes!("../../data/plrabn12.txt");
+const CORPUS_GEOPROTO: &[u8] = include_bytes!("../../data/geo.protodata");
+const CORPUS_KPPKN: &[u8] = include_bytes!("../../data/kppkn.gtb");
 
 macro_rules! compress {
     ($c:expr, $comp:expr, $group:expr, $name:expr, $corpus:expr) => {
@@ -72,13 +67,7 @@ fn all(c: &mut Criterion) {
 }
 
 fn rust(c: &mut Criterion) {
-    fn compress(input: &[u8], output: &mut [u8]) -> snap::Result<usize> {
-        snap::raw::Encoder::new().compress(input, output)
-    }
-
-    fn decompress(input: &[u8], output: &mut [u8]) -> snap::Result<usize> {
-        snap::raw::Decoder::new().decompress(input, output)
-    }
+    use snap::raw::{compress, decompress};
 
     compress!(c, compress, "snap", "zflat00_html", CORPUS_HTML);
     compress!(c, compress, "snap", "zflat01_urls", CORPUS_URLS_10K);
@@ -92,6 +81,10 @@ fn rust(c: &mut Criterion) {
     compress!(c, compress, "snap", "zflat09_txt4", CORPUS_PLRABN12);
     compress!(c, compress, "snap", "zflat10_pb", CORPUS_GEOPROTO);
     compress!(c, compress, "snap", "zflat11_gaviota", CORPUS_KPPKN);
+    compress_random_reuse(c);
+    compress_zeros(c);
+    compress_frame_pieces(c);
+    crc32c(c);
 
     decompress!(c, decompress, "snap", "uflat00_html", CORPUS_HTML);
     decompress!(c, decompress, "snap", "uflat01_urls", CORPUS_URLS_10K);
@@ -114,6 +107,124 @@ fn rust(c: &mut Criterion) {
     decompress!(c, decompress, "snap", "uflat11_gaviota", CORPUS_KPPKN);
 }
 
+/// Compresses many incompressible 64KB blocks with a single reused encoder.
+///
+/// Every block fails to compress and is stored as is, so this measures the
+/// overhead of that path, including setting up the hash table for each block.
+fn compress_random_reuse(c: &mut Criterion) {
+    const BLOCK_SIZE: usize = 1 << 16;
+    const BLOCKS: usize = 16;
+
+    let corpus = random_bytes(BLOCK_SIZE * BLOCKS);
+    let mut enc = snap::raw::Encoder::new();
+    let mut dst = vec![0; snap::raw::max_compress_len(BLOCK_SIZE)];
+    let blocks = corpus.clone();
+    define(c, "snap", "compress/zflat12_random_reuse", &corpus, move |b| {
+        b.iter(|| {
+            for block in blocks.chunks(BLOCK_SIZE) {
+                enc.compress_or_store(block, &mut dst).unwrap();
+            }
+        });
+    });
+}
+
+/// Compresses a buffer of zeros, e.g., an untouched page of a sparse file.
+fn compress_zeros(c: &mut Criterion) {
+    let corpus = vec![0; 1 << 20];
+    let mut dst = vec![0; snap::raw::max_compress_len(corpus.len())];
+    let input = corpus.clone();
+    define(c, "snap", "compress/zflat13_zeros", &corpus, move |b| {
+        b.iter(|| {
+            snap::raw::compress(&input, &mut dst).unwrap();
+        });
+    });
+}
+
+/// Compresses a corpus into a `Vec` in the frame format, 4KB at a time, once
+/// through `write::FrameEncoder` and once through `write::VecEncoder`.
+fn compress_frame_pieces(c: &mut Criterion) {
+    use std::io::Write;
+
+    const PIECE_SIZE: usize = 1 << 12;
+
+    define(c, "snap", "frame/zflat05_html4_writer", CORPUS_HTML_X_4, |b| {
+        b.iter(|| {
+            let mut wtr = snap::write::FrameEncoder::new(vec![]);
+            for piece in CORPUS_HTML_X_4.chunks(PIECE_SIZE) {
+                wtr.write_all(piece).unwrap();
+            }
+            wtr.into_inner().unwrap()
+        });
+    });
+    define(c, "snap", "frame/zflat05_html4_vec", CORPUS_HTML_X_4, |b| {
+        b.iter(|| {
+            let mut enc = snap::write::VecEncoder::new();
+            for piece in CORPUS_HTML_X_4.chunks(PIECE_SIZE) {
+                enc.extend_from_slice(piece);
+            }
+            enc.finish()
+        });
+    });
+}
+
+/// Computes the masked CRC32C checksum of a few corpora, with the fastest
+/// algorithm available (PCLMULQDQ on most x86_64 CPUs), with the CRC32
+/// instruction of SSE 4.2 and with the portable slicing by 16 algorithm.
+/// A large buffer of random bytes is included, since that's where carry-less
+/// multiplication is expected to shine.
+fn crc32c(c: &mut Criterion) {
+    use snap::crc32::{Crc32c, CrcAlgo};
+
+    let corpora = [
+        ("html", CORPUS_HTML.to_vec()),
+        ("jpg", CORPUS_FIREWORKS.to_vec()),
+        ("txt1", CORPUS_ALICE29.to_vec()),
+        ("pb", CORPUS_GEOPROTO.to_vec()),
+        ("random4m", random_bytes(4 << 20)),
+    ];
+    let algos = [
+        ("auto", CrcAlgo::Auto),
+        ("slice16", CrcAlgo::Slice16),
+        ("sse42", CrcAlgo::Sse42),
+        ("pclmulqdq", CrcAlgo::Pclmulqdq),
+    ];
+    for (name, corpus) in corpora {
+        let masked = |corpus: &[u8], algo: CrcAlgo| {
+            let mut crc = Crc32c::with_algorithm(algo);
+            crc.update(corpus);
+            crc.finalize_masked()
+        };
+        for (algo_name, algo) in algos {
+            assert_eq!(
+                masked(&corpus, CrcAlgo::Slice16),
+                masked(&corpus, algo),
+                "{} checksum of {} differs",
+                algo_name,
+                name
+            );
+            let bench_name = format!("crc32c/{}_{}", algo_name, name);
+            let corpus = corpus.clone();
+            define(c, "snap", &bench_name, &corpus.clone(), move |b| {
+                b.iter(|| masked(&corpus, algo));
+            });
+        }
+    }
+}
+
+/// Returns `len` pseudo-random bytes, generated with xorshift so that the
+/// same bytes are used for every run.
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        })
+        .collect()
+}
+
 #[cfg(feature = "cpp")]
 fn cpp(c: &mut Criterion) {
     use snappy_cpp::{compress, decompress};
@@ -160,12 +271,14 @@ fn define(
     bench: impl FnMut(&mut Bencher) + 'static,
 ) {
     let tput = Throughput::Bytes(corpus.len() as u64);
-    let benchmark = Benchmark::new(bench_name, bench)
+    let mut group = c.benchmark_group(group_name);
+    group
         .throughput(tput)
         .sample_size(50)
         .warm_up_time(Duration::from_millis(500))
         .measurement_time(Duration::from_secs(3));
-    c.bench(group_name, benchmark);
+    group.bench_function(bench_name, bench);
+    group.finish();
 }
 
 criterion_group!(g, all);
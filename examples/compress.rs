@@ -1,5 +1,3 @@
-use snap;
-
 use std::io;
 
 fn main() {
@@ -1,9 +1,9 @@
 use std::convert::TryInto;
-use std::io;
 
 /// Read a u16 in little endian format from the beginning of the given slice.
 /// This panics if the slice has length less than 2.
 pub fn read_u16_le(slice: &[u8]) -> u16 {
+    debug_assert!(slice.len() >= 2, "need 2 bytes, got {}", slice.len());
     u16::from_le_bytes(slice[..2].try_into().unwrap())
 }
 
@@ -11,23 +11,19 @@ pub fn read_u16_le(slice: &[u8]) -> u16 {
 /// to 0) in little endian format from the beginning of the given slice. This
 /// panics if the slice has length less than 3.
 pub fn read_u24_le(slice: &[u8]) -> u32 {
+    debug_assert!(slice.len() >= 3, "need 3 bytes, got {}", slice.len());
+    // Slicing first checks the bounds once, up front.
+    let slice = &slice[..3];
     slice[0] as u32 | (slice[1] as u32) << 8 | (slice[2] as u32) << 16
 }
 
 /// Read a u32 in little endian format from the beginning of the given slice.
 /// This panics if the slice has length less than 4.
 pub fn read_u32_le(slice: &[u8]) -> u32 {
+    debug_assert!(slice.len() >= 4, "need 4 bytes, got {}", slice.len());
     u32::from_le_bytes(slice[..4].try_into().unwrap())
 }
 
-/// Like read_u32_le, but from an io::Read implementation. If io::Read does
-/// not yield at least 4 bytes, then this returns an unexpected EOF error.
-pub fn io_read_u32_le<R: io::Read>(mut rdr: R) -> io::Result<u32> {
-    let mut buf = [0; 4];
-    rdr.read_exact(&mut buf)?;
-    Ok(u32::from_le_bytes(buf))
-}
-
 /// Write a u16 in little endian format to the beginning of the given slice.
 /// This panics if the slice has length less than 2.
 pub fn write_u16_le(n: u16, slice: &mut [u8]) {
@@ -41,6 +37,7 @@ pub fn write_u16_le(n: u16, slice: &mut [u8]) {
 /// in little endian format to the beginning of the given slice. This panics
 /// if the slice has length less than 3.
 pub fn write_u24_le(n: u32, slice: &mut [u8]) {
+    assert!(slice.len() >= 3);
     slice[0] = n as u8;
     slice[1] = (n >> 8) as u8;
     slice[2] = (n >> 16) as u8;
@@ -116,3 +113,93 @@ pub unsafe fn loadu_u64_le(data: *const u8) -> u64 {
 pub unsafe fn loadu_u64_ne(data: *const u8) -> u64 {
     (data as *const u64).read_unaligned()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_width_min_max() {
+        assert_eq!(0, read_u16_le(&[0, 0]));
+        assert_eq!(0xFFFF, read_u16_le(&[0xFF, 0xFF]));
+        assert_eq!(0x0201, read_u16_le(&[1, 2, 0xFF]));
+
+        assert_eq!(0, read_u24_le(&[0, 0, 0]));
+        assert_eq!(0xFFFFFF, read_u24_le(&[0xFF, 0xFF, 0xFF]));
+        assert_eq!(0x030201, read_u24_le(&[1, 2, 3, 0xFF]));
+
+        assert_eq!(0, read_u32_le(&[0, 0, 0, 0]));
+        assert_eq!(0xFFFFFFFF, read_u32_le(&[0xFF, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(0x04030201, read_u32_le(&[1, 2, 3, 4, 0xFF]));
+    }
+
+    #[test]
+    fn fixed_width_roundtrip() {
+        let mut buf = [0; 4];
+        for &n in &[0, 1, 0xFF, 0x100, 0xFFFF] {
+            write_u16_le(n, &mut buf);
+            assert_eq!(n, read_u16_le(&buf));
+        }
+        for &n in &[0, 1, 0xFFFF, 0x10000, 0xFFFFFF] {
+            write_u24_le(n, &mut buf);
+            assert_eq!(n, read_u24_le(&buf));
+        }
+        // The most significant 8 bits are ignored when writing a u24.
+        write_u24_le(0xABFFFFFF, &mut buf);
+        assert_eq!(0xFFFFFF, read_u24_le(&buf));
+        for &n in &[0, 1, 0xFFFFFF, 0x1000000, 0xFFFFFFFF] {
+            write_u32_le(n, &mut buf);
+            assert_eq!(n, read_u32_le(&buf));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_u16_le_too_short() {
+        read_u16_le(&[0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_u24_le_too_short() {
+        read_u24_le(&[0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_u32_le_too_short() {
+        read_u32_le(&[0, 0, 0]);
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        let mut buf = [0; 10];
+        for &(n, len) in &[
+            (0, 1),
+            (0x7F, 1),
+            (0x80, 2),
+            (0x3FFF, 2),
+            (0x4000, 3),
+            (u32::MAX as u64, 5),
+            (u64::MAX, 10),
+        ] {
+            assert_eq!(len, write_varu64(&mut buf, n));
+            assert_eq!((n, len), read_varu64(&buf[..len]));
+            // Trailing bytes are ignored.
+            assert_eq!((n, len), read_varu64(&buf));
+        }
+    }
+
+    #[test]
+    fn varint_invalid() {
+        // Empty input.
+        assert_eq!((0, 0), read_varu64(&[]));
+        // The continuation bit is set on the last byte.
+        assert_eq!((0, 0), read_varu64(&[0x80]));
+        assert_eq!((0, 0), read_varu64(&[0xFF, 0xFF]));
+        // Too many bytes to fit in a u64.
+        let mut long = [0x80; 11];
+        long[10] = 1;
+        assert_eq!((0, 0), read_varu64(&long));
+    }
+}
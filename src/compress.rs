@@ -1,4 +1,6 @@
+use std::cmp;
 use std::fmt;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr;
 
@@ -23,6 +25,26 @@ const INPUT_MARGIN: usize = 16 - 1;
 /// Anything smaller than this gets emitted as a literal.
 const MIN_NON_LITERAL_BLOCK_SIZE: usize = 1 + 1 + INPUT_MARGIN;
 
+/// The maximum number of bytes at the end of a dictionary that may be
+/// referenced by `Encoder::compress_with_dict`.
+///
+/// This is half of a block, which guarantees that the dictionary and the
+/// first block of input together still fit in a single block's hash table.
+const MAX_DICT_SIZE: usize = MAX_BLOCK_SIZE / 2;
+
+/// The default initial value of the skip counter used when searching for
+/// matches. See `Encoder::set_search_skip`.
+pub(crate) const DEFAULT_SEARCH_SKIP: u32 = 32;
+
+/// The biggest initial value of the skip counter that may be set with
+/// `Encoder::set_search_skip`. With this value, the search starts by
+/// probing only every 32nd position.
+const MAX_SEARCH_SKIP: u32 = 1 << 10;
+
+/// The default value of `Encoder::store_divisor`. Compressed bytes must be
+/// smaller than 7/8ths of their input to be worth keeping.
+pub(crate) const DEFAULT_STORE_DIVISOR: usize = 8;
+
 /// Nice names for the various Snappy tags.
 enum Tag {
     Literal = 0b00,
@@ -38,20 +60,96 @@ enum Tag {
 /// Returns the maximum compressed size given the uncompressed size.
 ///
 /// If the uncompressed size exceeds the maximum allowable size then this
-/// returns 0.
-pub fn max_compress_len(input_len: usize) -> usize {
+/// returns 0. Since that's easy to mistake for a buffer size, prefer
+/// [`checked_max_compress_len`](fn.checked_max_compress_len.html), which
+/// returns `None` instead.
+///
+/// Calling this function without using its result is almost certainly a bug,
+/// and is reported by the `unused_must_use` lint:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+///
+/// snap::raw::max_compress_len(1024);
+/// ```
+///
+/// Since this is a `const fn`, it can also be used to size buffers at compile
+/// time:
+///
+/// ```
+/// const BUF_LEN: usize = snap::raw::max_compress_len(1 << 16);
+/// let buf = [0u8; BUF_LEN];
+/// assert_eq!(76490, buf.len());
+/// ```
+#[must_use]
+pub const fn max_compress_len(input_len: usize) -> usize {
+    match checked_max_compress_len(input_len) {
+        Some(max) => max,
+        None => 0,
+    }
+}
+
+/// Returns the maximum compressed size given the uncompressed size, or
+/// `None` if the uncompressed size exceeds the maximum allowable size.
+///
+/// `None` is also returned when the maximum compressed size itself would
+/// exceed the maximum allowable size, since such an input can't be
+/// compressed either. This is otherwise identical to `max_compress_len`.
+///
+/// # Example
+///
+/// ```
+/// use snap::raw::checked_max_compress_len;
+///
+/// assert_eq!(Some(76490), checked_max_compress_len(1 << 16));
+/// assert_eq!(None, checked_max_compress_len(u32::MAX as usize));
+/// ```
+#[must_use]
+pub const fn checked_max_compress_len(input_len: usize) -> Option<usize> {
     let input_len = input_len as u64;
     if input_len > MAX_INPUT_SIZE {
-        return 0;
+        return None;
     }
     let max = 32 + input_len + (input_len / 6);
     if max > MAX_INPUT_SIZE {
-        0
+        None
     } else {
-        max as usize
+        Some(max as usize)
     }
 }
 
+/// Compresses all bytes in `input` into `output` using the raw Snappy format.
+///
+/// This is a convenience function for one-shot compression. It is equivalent
+/// to `Encoder::new().compress(input, output)`, and so it has the same
+/// requirements and returns the same errors. When compressing many inputs,
+/// reusing a single [`Encoder`](struct.Encoder.html) is more efficient, since
+/// it avoids allocating a new hash table on every call.
+///
+/// # Example
+///
+/// ```
+/// let input = b"Hello, Hello, Hello, Hello!";
+/// let mut output = vec![0; snap::raw::max_compress_len(input.len())];
+/// let n = snap::raw::compress(input, &mut output).unwrap();
+/// output.truncate(n);
+/// assert!(output.len() < input.len());
+/// ```
+#[must_use = "the compressed length is needed to truncate the output"]
+pub fn compress(input: &[u8], output: &mut [u8]) -> Result<usize> {
+    Encoder::new().compress(input, output)
+}
+
+/// The outcome of `Encoder::compress_or_store`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompressOutcome {
+    /// The input compressed well, and this many compressed bytes were
+    /// written to the output buffer.
+    Compressed(usize),
+    /// The input didn't compress well, and should be stored as is instead.
+    Store,
+}
+
 /// Encoder is a raw encoder for compressing bytes in the Snappy format.
 ///
 /// Thie encoder does not use the Snappy frame format and simply compresses the
@@ -67,6 +165,15 @@ pub fn max_compress_len(input_len: usize) -> usize {
 pub struct Encoder {
     small: [u16; SMALL_TABLE_SIZE],
     big: Vec<u16>,
+    /// Scratch space for joining a dictionary with the first block of input.
+    /// Only used by `compress_with_dict`.
+    dict_buf: Vec<u8>,
+    /// The initial value of the skip counter when searching for matches.
+    search_skip: u32,
+    /// `compress_or_store` says to store its input unless compressing it
+    /// saves at least `1 / store_divisor` of its size. This isn't exposed
+    /// publicly, but frame encoders may adjust it for individual chunks.
+    pub(crate) store_divisor: usize,
 }
 
 impl fmt::Debug for Encoder {
@@ -75,10 +182,45 @@ impl fmt::Debug for Encoder {
     }
 }
 
+impl Default for Encoder {
+    fn default() -> Encoder {
+        Encoder::new()
+    }
+}
+
 impl Encoder {
     /// Return a new encoder that can be used for compressing bytes.
     pub fn new() -> Encoder {
-        Encoder { small: [0; SMALL_TABLE_SIZE], big: vec![] }
+        Encoder {
+            small: [0; SMALL_TABLE_SIZE],
+            big: vec![],
+            dict_buf: vec![],
+            search_skip: DEFAULT_SEARCH_SKIP,
+            store_divisor: DEFAULT_STORE_DIVISOR,
+        }
+    }
+
+    /// Set how densely this encoder searches for matches in bytes that don't
+    /// seem to compress.
+    ///
+    /// While searching for the next match, the encoder probes positions in
+    /// the input, and it probes them further and further apart the longer
+    /// it goes without finding one. This makes incompressible data compress
+    /// quickly, at the cost of missing some matches. Every probe increments
+    /// a counter, which starts at `initial_skip`, and the distance between
+    /// probes is the counter divided by 32.
+    ///
+    /// Thus, a lower value searches more densely, which compresses better
+    /// but more slowly. For example, `1` probes every position 31 more times
+    /// before starting to skip ahead. A higher value skips ahead sooner,
+    /// which compresses faster but worse. The value is clamped to the range
+    /// `1..=1024`. The default is `32`, which matches the reference Snappy
+    /// implementation.
+    ///
+    /// The compressed bytes are always valid Snappy, regardless of this
+    /// setting.
+    pub fn set_search_skip(&mut self, initial_skip: u32) {
+        self.search_skip = initial_skip.clamp(1, MAX_SEARCH_SKIP);
     }
 
     /// Compresses all bytes in `input` into `output`.
@@ -96,26 +238,13 @@ impl Encoder {
     ///
     /// * The total number of bytes to compress exceeds `2^32 - 1`.
     /// * `output` has length less than `max_compress_len(input.len())`.
+    #[must_use = "the compressed length is needed to truncate the output"]
     pub fn compress(
         &mut self,
-        mut input: &[u8],
+        input: &[u8],
         output: &mut [u8],
     ) -> Result<usize> {
-        match max_compress_len(input.len()) {
-            0 => {
-                return Err(Error::TooBig {
-                    given: input.len() as u64,
-                    max: MAX_INPUT_SIZE,
-                });
-            }
-            min if output.len() < min => {
-                return Err(Error::BufferTooSmall {
-                    given: output.len() as u64,
-                    min: min as u64,
-                });
-            }
-            _ => {}
-        }
+        check_output_len(input, output)?;
         // Handle an edge case specially.
         if input.is_empty() {
             // Encodes a varint of 0, denoting the total size of uncompressed
@@ -125,12 +254,233 @@ impl Encoder {
         }
         // Write the Snappy header, which is just the total number of
         // uncompressed bytes.
+        let d = bytes::write_varu64(output, input.len() as u64);
+        Ok(self.compress_blocks(input, output, d))
+    }
+
+    /// Compresses all bytes in `input` into `output`, starting at `offset`.
+    ///
+    /// This is just like `compress`, except the compressed bytes are written
+    /// to `output[offset..]`, and the bytes before `offset` are left
+    /// untouched. This is useful for assembling a larger buffer, e.g., a
+    /// chunk with a header, without compressing into a temporary buffer
+    /// first.
+    ///
+    /// `output.len() - offset` must be at least
+    /// `max_compress_len(input.len())`.
+    ///
+    /// On success, this returns the number of bytes written to `output`,
+    /// which doesn't include `offset`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `compress` does when given `output[offset..]`. If `offset` is greater
+    /// than `output.len()`, then it is treated as if no space is left in
+    /// `output`.
+    #[must_use = "the compressed length is needed to truncate the output"]
+    pub fn compress_at(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        offset: usize,
+    ) -> Result<usize> {
+        let output = output.get_mut(offset..).unwrap_or_default();
+        self.compress(input, output)
+    }
+
+    /// Compresses all bytes in `input` into `output`, such that the
+    /// compressed bytes are never more than 10 bytes bigger than `input`.
+    ///
+    /// This is just like `compress`, except when compression would produce
+    /// more bytes than storing `input` as a single literal, e.g., for
+    /// adversarial or random inputs. In that case, `input` is written as a
+    /// single literal instead, which takes at most `input.len() + 10` bytes
+    /// (5 for the header and 5 for the literal's tag). The bytes written are
+    /// always valid Snappy, and decompress to `input` as usual.
+    ///
+    /// The requirements on `output` and the errors returned are the same as
+    /// for `compress`.
+    #[must_use = "the compressed length is needed to truncate the output"]
+    pub fn compress_bounded(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize> {
+        let n = self.compress(input, output)?;
+        if n <= literal_compress_len(input.len()) {
+            return Ok(n);
+        }
+        Ok(compress_literal(input, output))
+    }
+
+    /// Compresses all bytes in `input` into `output`, unless doing so doesn't
+    /// save enough space to be worth it.
+    ///
+    /// If the compressed bytes are at least 7/8ths the size of `input`, then
+    /// `CompressOutcome::Store` is returned. In that case, callers should
+    /// store (or send) `input` as is, which avoids both the cost of
+    /// decompressing it later and the cost of copying `output`. This is the
+    /// same rule that the Snappy frame format uses to decide whether a chunk
+    /// should be stored uncompressed.
+    ///
+    /// Otherwise, `CompressOutcome::Compressed(n)` is returned, where `n` is
+    /// the number of bytes written to `output`.
+    ///
+    /// Since the raw Snappy format has no way of marking bytes as stored,
+    /// callers are responsible for recording which outcome occurred.
+    ///
+    /// The requirements on `output` and the errors returned are the same as
+    /// for `compress`. The contents of `output` are unspecified when `Store`
+    /// is returned.
+    #[must_use = "the compressed length is needed to truncate the output"]
+    pub fn compress_or_store(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<CompressOutcome> {
+        let n = self.compress(input, output)?;
+        if n >= input.len() - (input.len() / self.store_divisor) {
+            Ok(CompressOutcome::Store)
+        } else {
+            Ok(CompressOutcome::Compressed(n))
+        }
+    }
+
+    /// Compresses all bytes in `input` into `output`, permitting copies that
+    /// refer to bytes at the end of `dict`.
+    ///
+    /// This is useful when compressing many small inputs that share a lot of
+    /// content with some known sequence of bytes, e.g., similar records. In
+    /// that case, using that sequence as a dictionary can improve the
+    /// compression ratio considerably.
+    ///
+    /// Only the last 32 KiB of `dict` are ever referenced. Copies that refer
+    /// to the dictionary may start anywhere in the first block of `input`,
+    /// which is the first 64 KiB minus the length of the referenced part of
+    /// `dict` (so always at least 32 KiB). Later blocks are compressed on
+    /// their own, as with `compress`.
+    ///
+    /// The requirements on `output` and the errors returned are the same as
+    /// for `compress`.
+    ///
+    /// # Interoperability
+    ///
+    /// This is an extension of the Snappy format. The bytes produced can only
+    /// be decompressed by a decoder that is given the same `dict`, such as
+    /// `Decoder::decompress_with_dict`. Conceptually, copies are resolved as
+    /// if the decompressed bytes were preceded by `dict`. Other Snappy
+    /// decoders will reject the compressed bytes or, worse, decompress them
+    /// incorrectly. When `dict` is empty, this is equivalent to `compress`.
+    #[must_use = "the compressed length is needed to truncate the output"]
+    pub fn compress_with_dict(
+        &mut self,
+        dict: &[u8],
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize> {
+        if dict.is_empty() || input.is_empty() {
+            return self.compress(input, output);
+        }
+        check_output_len(input, output)?;
+        let dict = &dict[dict.len().saturating_sub(MAX_DICT_SIZE)..];
         let mut d = bytes::write_varu64(output, input.len() as u64);
+
+        // The first block is compressed together with the dictionary so that
+        // copies may refer back into it. Since the dictionary and the block
+        // fit in a single block, the offsets of such copies always fit in a
+        // copy 2 operation.
+        let first_len = cmp::min(input.len(), MAX_BLOCK_SIZE - dict.len());
+        let (first, rest) = input.split_at(first_len);
+        let mut buf = mem::take(&mut self.dict_buf);
+        buf.clear();
+        buf.extend_from_slice(dict);
+        buf.extend_from_slice(first);
+
+        let mut block = Block::new(&buf, output, d);
+        block.s = dict.len();
+        block.next_emit = dict.len();
+        if first.len() < MIN_NON_LITERAL_BLOCK_SIZE {
+            let lit_end = block.src.len();
+            unsafe {
+                // SAFETY: next_emit is the length of the dictionary, which is
+                // in bounds, and the end is the length of the block (also in
+                // bounds).
+                block.emit_literal(lit_end);
+            }
+        } else {
+            let search_skip = self.search_skip;
+            let mut table = self.block_table(buf.len());
+            // Seed the table with every position in the dictionary, such that
+            // the most recent occurrence of each 4 byte sequence wins.
+            for i in 0..dict.len() {
+                let h = table.hash(bytes::read_u32_le(&buf[i..]));
+                table[h] = i as u16;
+            }
+            block.compress(table, search_skip);
+        }
+        d = block.d;
+        self.dict_buf = buf;
+        Ok(self.compress_blocks(rest, output, d))
+    }
+
+    /// Compresses all bytes in `input` into a freshly allocated `Vec`.
+    ///
+    /// This is just like the `compress` method, except it allocates a `Vec`
+    /// with the right size for you. (This is intended to be a convenience
+    /// method.)
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `compress` does.
+    #[must_use = "the compressed bytes are only returned"]
+    pub fn compress_vec(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = vec![0; max_compress_len(input.len())];
+        let n = self.compress(input, &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Compresses all bytes in `input` into `scratch`, and returns it.
+    ///
+    /// This is just like the `compress_vec` method, except it reuses the
+    /// allocation of `scratch` instead of allocating a new `Vec`. Any
+    /// existing contents of `scratch` are discarded. Threading the returned
+    /// `Vec` through subsequent calls avoids allocating for each input.
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `compress` does.
+    #[must_use = "the compressed bytes are only returned"]
+    pub fn compress_into(
+        &mut self,
+        input: &[u8],
+        mut scratch: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        scratch.clear();
+        scratch.resize(max_compress_len(input.len()), 0);
+        let n = self.compress(input, &mut scratch)?;
+        scratch.truncate(n);
+        Ok(scratch)
+    }
+}
+
+impl Encoder {
+    /// Compresses `input` one block at a time, writing the compressed bytes
+    /// to `output` starting at position `d`. This returns the position in
+    /// `output` immediately following the last compressed byte written.
+    ///
+    /// This does not write a header. The caller must ensure `output` is big
+    /// enough.
+    fn compress_blocks(
+        &mut self,
+        mut input: &[u8],
+        output: &mut [u8],
+        mut d: usize,
+    ) -> usize {
         while !input.is_empty() {
             // Find the next block.
             let mut src = input;
             if src.len() > MAX_BLOCK_SIZE {
-                src = &src[..MAX_BLOCK_SIZE as usize];
+                src = &src[..MAX_BLOCK_SIZE];
             }
             input = &input[src.len()..];
 
@@ -144,31 +494,107 @@ impl Encoder {
                     // the length of the block (in bounds).
                     block.emit_literal(lit_end);
                 }
+            } else if is_run(block.src) {
+                // Blocks made of a single repeated byte, e.g., zeroed pages,
+                // don't need the hash table at all. This also skips the cost
+                // of clearing it.
+                block.compress_run();
             } else {
+                let search_skip = self.search_skip;
                 let table = self.block_table(block.src.len());
-                block.compress(table);
+                block.compress(table, search_skip);
             }
             d = block.d;
         }
-        Ok(d)
+        d
     }
+}
 
-    /// Compresses all bytes in `input` into a freshly allocated `Vec`.
-    ///
-    /// This is just like the `compress` method, except it allocates a `Vec`
-    /// with the right size for you. (This is intended to be a convenience
-    /// method.)
-    ///
-    /// This method returns an error under the same circumstances that
-    /// `compress` does.
-    pub fn compress_vec(&mut self, input: &[u8]) -> Result<Vec<u8>> {
-        let mut buf = vec![0; max_compress_len(input.len())];
-        let n = self.compress(input, &mut buf)?;
-        buf.truncate(n);
-        Ok(buf)
+/// Returns true if `src` is not empty and every byte in it is the same.
+fn is_run(src: &[u8]) -> bool {
+    let first = match src.first() {
+        None => return false,
+        Some(&first) => first,
+    };
+    // Comparing a chunk at a time lets this compile down to wide loads, while
+    // still bailing out quickly on blocks that aren't runs.
+    let run = [first; 32];
+    let mut chunks = src.chunks_exact(run.len());
+    chunks.all(|chunk| chunk == run)
+        && chunks.remainder().iter().all(|&b| b == first)
+}
+
+/// Returns an error if `input` is too big to compress or if `output` is too
+/// small to hold the maximum possible compressed size of `input`.
+fn check_output_len(input: &[u8], output: &[u8]) -> Result<()> {
+    match max_compress_len(input.len()) {
+        0 => Err(Error::TooBig {
+            given: input.len() as u64,
+            max: MAX_INPUT_SIZE,
+        }),
+        min if output.len() < min => Err(Error::BufferTooSmall {
+            given: output.len() as u64,
+            min: min as u64,
+        }),
+        _ => Ok(()),
     }
 }
 
+/// Returns the number of bytes that `compress_literal` writes for an input of
+/// `input_len` bytes.
+fn literal_compress_len(input_len: usize) -> usize {
+    let header_len = match input_len as u64 {
+        n if n < 1 << 7 => 1,
+        n if n < 1 << 14 => 2,
+        n if n < 1 << 21 => 3,
+        n if n < 1 << 28 => 4,
+        _ => 5,
+    };
+    let tag_len = match input_len.saturating_sub(1) {
+        0..=59 => 1,
+        60..=0xFF => 2,
+        0x100..=0xFFFF => 3,
+        0x1_0000..=0xFF_FFFF => 4,
+        _ => 5,
+    };
+    header_len + if input_len == 0 { 0 } else { tag_len } + input_len
+}
+
+/// Writes `input` to `output` in the raw Snappy format as a single literal,
+/// without any compression, and returns the number of bytes written.
+///
+/// The caller must ensure that `input` is at most `MAX_INPUT_SIZE` bytes and
+/// that `output` has room for `literal_compress_len(input.len())` bytes.
+fn compress_literal(input: &[u8], output: &mut [u8]) -> usize {
+    let mut d = bytes::write_varu64(output, input.len() as u64);
+    if input.is_empty() {
+        return d;
+    }
+    let n = input.len() - 1;
+    if n <= 59 {
+        output[d] = ((n as u8) << 2) | (Tag::Literal as u8);
+        d += 1;
+    } else if n < 1 << 8 {
+        output[d] = (60 << 2) | (Tag::Literal as u8);
+        output[d + 1] = n as u8;
+        d += 2;
+    } else if n < 1 << 16 {
+        output[d] = (61 << 2) | (Tag::Literal as u8);
+        bytes::write_u16_le(n as u16, &mut output[d + 1..]);
+        d += 3;
+    } else if n < 1 << 24 {
+        output[d] = (62 << 2) | (Tag::Literal as u8);
+        bytes::write_u24_le(n as u32, &mut output[d + 1..]);
+        d += 4;
+    } else {
+        output[d] = (63 << 2) | (Tag::Literal as u8);
+        bytes::write_u32_le(n as u32, &mut output[d + 1..]);
+        d += 5;
+    }
+    output[d..d + input.len()].copy_from_slice(input);
+    d + input.len()
+}
+
 struct Block<'s, 'd> {
     src: &'s [u8],
     s: usize,
@@ -181,18 +607,26 @@ struct Block<'s, 'd> {
 impl<'s, 'd> Block<'s, 'd> {
     #[inline(always)]
     fn new(src: &'s [u8], dst: &'d mut [u8], d: usize) -> Block<'s, 'd> {
-        Block {
-            src: src,
-            s: 0,
-            s_limit: src.len(),
-            dst: dst,
-            d: d,
-            next_emit: 0,
+        Block { src, s: 0, s_limit: src.len(), dst, d, next_emit: 0 }
+    }
+
+    #[inline(always)]
+    fn compress(&mut self, table: BlockTable<'_>, search_skip: u32) {
+        // Only a skip counter below 32 needs its probe distance rounded up
+        // to 1, so check for that once here instead of on every probe.
+        if search_skip < 32 {
+            self.compress_search::<true>(table, search_skip)
+        } else {
+            self.compress_search::<false>(table, search_skip)
         }
     }
 
     #[inline(always)]
-    fn compress(&mut self, mut table: BlockTable<'_>) {
+    fn compress_search<const DENSE: bool>(
+        &mut self,
+        mut table: BlockTable<'_>,
+        search_skip: u32,
+    ) {
         debug_assert!(!table.is_empty());
         debug_assert!(self.src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
 
@@ -201,12 +635,15 @@ impl<'s, 'd> Block<'s, 'd> {
         let mut next_hash =
             table.hash(bytes::read_u32_le(&self.src[self.s..]));
         loop {
-            let mut skip = 32;
+            let mut skip = search_skip as usize;
             let mut candidate;
             let mut s_next = self.s;
             loop {
                 self.s = s_next;
-                let bytes_between_hash_lookups = skip >> 5;
+                // A skip counter below 32 (only possible when the search
+                // skip is set lower than the default) still makes progress.
+                let bytes_between_hash_lookups =
+                    if DENSE { cmp::max(skip >> 5, 1) } else { skip >> 5 };
                 s_next = self.s + bytes_between_hash_lookups;
                 skip += bytes_between_hash_lookups;
                 if s_next > self.s_limit {
@@ -316,18 +753,36 @@ impl<'s, 'd> Block<'s, 'd> {
         }
     }
 
+    /// Compresses a block made of a single repeated byte, as a literal of
+    /// that byte followed by copies of it with an offset of 1.
+    ///
+    /// This produces the same bytes as `compress`, without any hash lookups.
+    #[inline(always)]
+    fn compress_run(&mut self) {
+        debug_assert!(self.src.len() >= MIN_NON_LITERAL_BLOCK_SIZE);
+        debug_assert!(is_run(self.src));
+        unsafe {
+            // SAFETY: next_emit is zero and the block is not empty, so the
+            // literal is in bounds.
+            self.emit_literal(1);
+        }
+        self.emit_copy(1, self.src.len() - 1);
+        self.s = self.src.len();
+        self.next_emit = self.s;
+    }
+
     /// Emits one or more copy operations with the given offset and length.
     /// offset must be in the range [1, 65535] and len must be in the range
     /// [4, 65535].
     #[inline(always)]
     fn emit_copy(&mut self, offset: usize, mut len: usize) {
-        debug_assert!(1 <= offset && offset <= 65535);
+        debug_assert!((1..=65535).contains(&offset));
         // Copy operations only allow lengths up to 64, but we'll allow bigger
         // lengths and emit as many operations as we need.
         //
         // N.B. Since our block size is 64KB, we never actually emit a copy 4
         // operation.
-        debug_assert!(4 <= len && len <= 65535);
+        debug_assert!((4..=65535).contains(&len));
 
         // Emit copy 2 operations until we don't have to.
         // We check on 68 here and emit a shorter copy than 64 below because
@@ -361,8 +816,8 @@ impl<'s, 'd> Block<'s, 'd> {
     /// must be in the range [1, 65535] and len must be in the range [1, 64].
     #[inline(always)]
     fn emit_copy2(&mut self, offset: usize, len: usize) {
-        debug_assert!(1 <= offset && offset <= 65535);
-        debug_assert!(1 <= len && len <= 64);
+        debug_assert!((1..=65535).contains(&offset));
+        debug_assert!((1..=64).contains(&len));
         self.dst[self.d] = (((len - 1) as u8) << 2) | (Tag::Copy2 as u8);
         bytes::write_u16_le(offset as u16, &mut self.dst[self.d + 1..]);
         self.d += 3;
@@ -514,7 +969,7 @@ impl Encoder {
         for x in &mut *table {
             *x = 0;
         }
-        BlockTable { table: table, shift: shift }
+        BlockTable { table, shift }
     }
 }
 
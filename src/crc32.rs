@@ -1,31 +1,145 @@
+/*!
+This module provides the CRC32C checksum used by the Snappy frame format.
+
+CRC32C is the CRC32 variant that uses the Castagnoli polynomial. Although
+the Snappy frame format is what this crate uses it for, nothing here is
+specific to Snappy, so it's just as useful for checksumming any other data.
+When the CPU supports SSE 4.2 or PCLMULQDQ, checksums are computed with
+hardware acceleration. Otherwise, a portable "slicing by 16" implementation
+is used.
+
+To compute the checksum of a single buffer, use
+[`crc32c`](fn.crc32c.html). To compute it incrementally over several
+buffers, use [`Crc32c`](struct.Crc32c.html). The "masked" variants compute
+the checksum exactly as it is written in the chunks of the Snappy frame
+format.
+
+# Example
+
+```
+assert_eq!(0xE3069283, snap::crc32::crc32c(b"123456789"));
+```
+*/
+
 use crate::bytes;
 use crate::crc32_table::{TABLE, TABLE16};
 
+/// The CRC32 polynomial used by Snappy (Castagnoli), in reversed bit order.
+/// This must match the polynomial used to generate the tables in build.rs.
+const CASTAGNOLI_POLY: u32 = 0x82f63b78;
+
+/// The constant added to a rotated CRC32C checksum to mask it, as defined by
+/// the Snappy frame format.
+const MASK_DELTA: u32 = 0xA282EAD8;
+
+/// The algorithm used to compute CRC32C checksums.
+///
+/// All algorithms compute identical checksums and differ only in speed.
+/// Choosing one other than `Auto` is primarily useful for testing and
+/// debugging.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CrcAlgo {
+    /// Use the fastest algorithm available. This uses `Pclmulqdq` when the
+    /// CPU supports it (and the `no-simd` feature isn't enabled), and
+    /// otherwise falls back to `Sse42`.
+    Auto,
+    /// A portable table driven algorithm that processes 16 bytes at a time.
+    Slice16,
+    /// A portable algorithm that processes one bit at a time. This is very
+    /// slow, but simple enough to be obviously correct.
+    Bitwise,
+    /// Use the CRC32 instruction of SSE 4.2, which processes 8 bytes at a
+    /// time. When the CPU doesn't support SSE 4.2 (or the `no-simd` feature
+    /// is enabled), this falls back to `Slice16`.
+    Sse42,
+    /// Fold the input 64 bytes at a time using carry-less multiplication
+    /// (the PCLMULQDQ instruction), which is fastest for large buffers.
+    /// Buffers shorter than 128 bytes, as well as the last few bytes of
+    /// longer ones, are checksummed with `Sse42`. When the CPU doesn't
+    /// support PCLMULQDQ and SSE 4.1 (or the `no-simd` feature is enabled),
+    /// this falls back to `Sse42`.
+    Pclmulqdq,
+}
+
 /// Provides a simple API to generate "masked" CRC32C checksums specifically
-/// for use in Snappy. When available, this will make use of SSE 4.2 to compute
-/// checksums. Otherwise, it falls back to only-marginally-slower "slicing by
-/// 16" technique.
+/// for use in Snappy. When available, this will make use of PCLMULQDQ and SSE
+/// 4.2 to compute checksums. Otherwise, it falls back to only-marginally-slower
+/// "slicing by 16" technique. Neither is ever used when the `no-simd` feature
+/// is enabled.
 ///
 /// The main purpose of this type is to cache the CPU feature check and expose
 /// a safe API.
 #[derive(Clone, Copy, Debug)]
-pub struct CheckSummer {
+pub(crate) struct CheckSummer {
     sse42: bool,
+    /// Whether to use carry-less multiplication for large buffers.
+    pclmul: bool,
+    bitwise: bool,
+    /// The constant used for masking checksums. This is always `MASK_DELTA`
+    /// unless it has been overridden for interoperating with nonconforming
+    /// implementations.
+    mask_delta: u32,
 }
 
 impl CheckSummer {
     /// Create a new checksummer that can compute CRC32C checksums on arbitrary
     /// bytes.
-    #[cfg(not(target_arch = "x86_64"))]
+    #[cfg(any(not(target_arch = "x86_64"), feature = "no-simd"))]
     pub fn new() -> CheckSummer {
-        CheckSummer { sse42: false }
+        CheckSummer {
+            sse42: false,
+            pclmul: false,
+            bitwise: false,
+            mask_delta: MASK_DELTA,
+        }
     }
 
     /// Create a new checksummer that can compute CRC32C checksums on arbitrary
     /// bytes.
-    #[cfg(target_arch = "x86_64")]
+    #[cfg(all(target_arch = "x86_64", not(feature = "no-simd")))]
     pub fn new() -> CheckSummer {
-        CheckSummer { sse42: is_x86_feature_detected!("sse4.2") }
+        CheckSummer {
+            sse42: is_x86_feature_detected!("sse4.2"),
+            pclmul: has_pclmul(),
+            bitwise: false,
+            mask_delta: MASK_DELTA,
+        }
+    }
+
+    /// Create a new checksummer that always computes checksums with the
+    /// given algorithm.
+    pub fn with_algorithm(algo: CrcAlgo) -> CheckSummer {
+        match algo {
+            CrcAlgo::Auto => CheckSummer::new(),
+            CrcAlgo::Slice16 => CheckSummer {
+                sse42: false,
+                pclmul: false,
+                bitwise: false,
+                mask_delta: MASK_DELTA,
+            },
+            CrcAlgo::Bitwise => CheckSummer {
+                sse42: false,
+                pclmul: false,
+                bitwise: true,
+                mask_delta: MASK_DELTA,
+            },
+            CrcAlgo::Sse42 => {
+                CheckSummer { pclmul: false, ..CheckSummer::new() }
+            }
+            CrcAlgo::Pclmulqdq => CheckSummer::new(),
+        }
+    }
+
+    /// Sets the constant used for masking checksums in place of the one
+    /// defined by the Snappy frame format.
+    pub fn set_mask_constant(&mut self, delta: u32) {
+        self.mask_delta = delta;
+    }
+
+    /// Returns true if this checksummer computes checksums with hardware
+    /// acceleration.
+    pub fn is_hardware_accelerated(&self) -> bool {
+        self.sse42 || self.pclmul
     }
 
     /// Returns the "masked" CRC32 checksum of `buf` using the Castagnoli
@@ -33,35 +147,253 @@ impl CheckSummer {
     /// format. Masking is supposed to make the checksum robust with respect to
     /// the data that contains the checksum itself.
     pub fn crc32c_masked(&self, buf: &[u8]) -> u32 {
-        let sum = self.crc32c(buf);
-        (sum.wrapping_shr(15) | sum.wrapping_shl(17)).wrapping_add(0xA282EAD8)
+        mask_with(self.crc32c_append(0, buf), self.mask_delta)
     }
 
-    /// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial.
-    #[cfg(not(target_arch = "x86_64"))]
-    fn crc32c(&self, buf: &[u8]) -> u32 {
-        crc32c_slice16(buf)
+    /// Returns the CRC32 checksum, using the Castagnoli polynomial, of the
+    /// bytes whose checksum is `crc` followed by `buf`. When `crc` is `0`,
+    /// this is the checksum of just `buf`.
+    #[cfg(any(not(target_arch = "x86_64"), feature = "no-simd"))]
+    fn crc32c_append(&self, crc: u32, buf: &[u8]) -> u32 {
+        if self.bitwise {
+            crc32c_bitwise(crc, buf)
+        } else {
+            crc32c_slice16(crc, buf)
+        }
     }
 
-    /// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial.
-    #[cfg(target_arch = "x86_64")]
-    fn crc32c(&self, buf: &[u8]) -> u32 {
+    /// Returns the CRC32 checksum, using the Castagnoli polynomial, of the
+    /// bytes whose checksum is `crc` followed by `buf`. When `crc` is `0`,
+    /// this is the checksum of just `buf`.
+    #[cfg(all(target_arch = "x86_64", not(feature = "no-simd")))]
+    fn crc32c_append(&self, mut crc: u32, mut buf: &[u8]) -> u32 {
+        if self.pclmul && buf.len() >= 128 {
+            let n = buf.len() & !15;
+            // SAFETY: When pclmul is true, we are guaranteed to be running
+            // on a CPU that supports PCLMULQDQ and SSE 4.1, and n is a
+            // multiple of 16 that is at least 128.
+            crc = unsafe { crc32c_pclmul(crc, &buf[..n]) };
+            buf = &buf[n..];
+        }
         if self.sse42 {
             // SAFETY: When sse42 is true, we are guaranteed to be running on
             // a CPU that supports SSE 4.2.
-            unsafe { crc32c_sse(buf) }
+            unsafe { crc32c_sse(crc, buf) }
+        } else if self.bitwise {
+            crc32c_bitwise(crc, buf)
         } else {
-            crc32c_slice16(buf)
+            crc32c_slice16(crc, buf)
         }
     }
 }
 
-#[cfg(target_arch = "x86_64")]
+/// Returns the CRC32C checksum of `buf`.
+///
+/// When available, hardware acceleration is used to compute it.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(0xE3069283, snap::crc32::crc32c(b"123456789"));
+/// ```
+pub fn crc32c(buf: &[u8]) -> u32 {
+    CheckSummer::new().crc32c_append(0, buf)
+}
+
+/// Returns the "masked" CRC32C checksum of `buf`, exactly as it is written in
+/// each compressed or uncompressed chunk of the Snappy frame format.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(0xC78AB0E5, snap::crc32::crc32c_masked(b"123456789"));
+/// ```
+pub fn crc32c_masked(buf: &[u8]) -> u32 {
+    CheckSummer::new().crc32c_masked(buf)
+}
+
+/// A CRC32C checksum that is computed incrementally.
+///
+/// This is useful for computing the checksum of bytes that are split across
+/// several buffers, without copying them into one. Feeding bytes to
+/// [`update`](#method.update) in any number of pieces produces the same
+/// checksum as feeding them all at once.
+///
+/// # Example
+///
+/// ```
+/// use snap::crc32::{crc32c_masked, Crc32c};
+///
+/// let mut crc = Crc32c::new();
+/// crc.update(b"1234");
+/// crc.update(b"56789");
+/// assert_eq!(0xE3069283, crc.finalize());
+/// assert_eq!(crc32c_masked(b"123456789"), crc.finalize_masked());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32c {
+    checksummer: CheckSummer,
+    crc: u32,
+}
+
+impl Crc32c {
+    /// Create a new checksum of no bytes, which is computed with the fastest
+    /// algorithm available.
+    pub fn new() -> Crc32c {
+        Crc32c::with_algorithm(CrcAlgo::Auto)
+    }
+
+    /// Create a new checksum of no bytes, which is always computed with the
+    /// given algorithm.
+    pub fn with_algorithm(algo: CrcAlgo) -> Crc32c {
+        Crc32c { checksummer: CheckSummer::with_algorithm(algo), crc: 0 }
+    }
+
+    /// Adds the bytes in `buf` to the checksum.
+    pub fn update(&mut self, buf: &[u8]) {
+        self.crc = self.checksummer.crc32c_append(self.crc, buf);
+    }
+
+    /// Returns the CRC32C checksum of all bytes added so far.
+    ///
+    /// This doesn't reset the checksum, so more bytes may be added after.
+    pub fn finalize(&self) -> u32 {
+        self.crc
+    }
+
+    /// Returns the "masked" CRC32C checksum of all bytes added so far, as it
+    /// is written in the chunks of the Snappy frame format.
+    ///
+    /// This doesn't reset the checksum, so more bytes may be added after.
+    pub fn finalize_masked(&self) -> u32 {
+        mask(self.crc)
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Crc32c {
+        Crc32c::new()
+    }
+}
+
+/// Masks a CRC32C checksum as defined by the Snappy frame format.
+fn mask(sum: u32) -> u32 {
+    mask_with(sum, MASK_DELTA)
+}
+
+/// Masks a CRC32C checksum like `mask`, except with the given constant.
+fn mask_with(sum: u32, delta: u32) -> u32 {
+    (sum.wrapping_shr(15) | sum.wrapping_shl(17)).wrapping_add(delta)
+}
+
+/// Returns true if the CPU supports the instructions used by
+/// `crc32c_pclmul`.
+#[cfg(all(target_arch = "x86_64", not(feature = "no-simd")))]
+fn has_pclmul() -> bool {
+    is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1")
+}
+
+/// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial,
+/// continuing from the checksum `crc` of the preceding bytes.
+///
+/// This folds the input with carry-less multiplication, as described in
+/// Intel's "Fast CRC Computation for Generic Polynomials Using PCLMULQDQ
+/// Instruction", and then reduces the result with a Barrett reduction. The
+/// constants below are specific to the (bit reflected) Castagnoli
+/// polynomial, where `[x]'` denotes the bit reflection of `x`:
+///
+/// * `K1 = [x^(4*128+32) mod P(x)]' << 1`
+/// * `K2 = [x^(4*128-32) mod P(x)]' << 1`
+/// * `K3 = [x^(128+32) mod P(x)]' << 1`
+/// * `K4 = [x^(128-32) mod P(x)]' << 1`
+/// * `K5 = [x^64 mod P(x)]' << 1`
+/// * `P_X = [P(x)]'`
+/// * `U_PRIME = [x^64 / P(x)]'`
+///
+/// The length of `buf` must be a multiple of 16 that is at least 128.
+#[cfg(all(target_arch = "x86_64", not(feature = "no-simd")))]
+#[target_feature(enable = "pclmulqdq", enable = "sse4.1")]
+unsafe fn crc32c_pclmul(crc: u32, mut buf: &[u8]) -> u32 {
+    use std::arch::x86_64::*;
+
+    const K1: i64 = 0x0740eef02;
+    const K2: i64 = 0x09e4addf8;
+    const K3: i64 = 0x0f20c0dfe;
+    const K4: i64 = 0x14cd00bd6;
+    const K5: i64 = 0x0dd45aab8;
+    const P_X: i64 = 0x105ec76f1;
+    const U_PRIME: i64 = 0x0dea713f1;
+
+    // Folds the 128 bit value a into the next 128 bits b, which are at the
+    // distance given by keys.
+    #[inline(always)]
+    unsafe fn fold(a: __m128i, b: __m128i, keys: __m128i) -> __m128i {
+        let lo = _mm_clmulepi64_si128(a, keys, 0x00);
+        let hi = _mm_clmulepi64_si128(a, keys, 0x11);
+        _mm_xor_si128(_mm_xor_si128(b, lo), hi)
+    }
+
+    // Loads the next 16 bytes of buf.
+    #[inline(always)]
+    unsafe fn next(buf: &mut &[u8]) -> __m128i {
+        debug_assert!(buf.len() >= 16);
+        // SAFETY: buf has at least 16 bytes, and loadu permits unaligned
+        // loads.
+        let x = _mm_loadu_si128(buf.as_ptr() as *const __m128i);
+        *buf = &buf[16..];
+        x
+    }
+
+    debug_assert!(buf.len() >= 128 && buf.len() % 16 == 0);
+    // Fold four 128 bit lanes at a time while at least 64 bytes are left.
+    let mut x3 = next(&mut buf);
+    let mut x2 = next(&mut buf);
+    let mut x1 = next(&mut buf);
+    let mut x0 = next(&mut buf);
+    x3 = _mm_xor_si128(x3, _mm_cvtsi32_si128(!crc as i32));
+    let k1k2 = _mm_set_epi64x(K2, K1);
+    while buf.len() >= 64 {
+        x3 = fold(x3, next(&mut buf), k1k2);
+        x2 = fold(x2, next(&mut buf), k1k2);
+        x1 = fold(x1, next(&mut buf), k1k2);
+        x0 = fold(x0, next(&mut buf), k1k2);
+    }
+    // Fold the four lanes into one, and then the rest of buf into it.
+    let k3k4 = _mm_set_epi64x(K4, K3);
+    let mut x = fold(x3, x2, k3k4);
+    x = fold(x, x1, k3k4);
+    x = fold(x, x0, k3k4);
+    while buf.len() >= 16 {
+        x = fold(x, next(&mut buf), k3k4);
+    }
+    // Reduce 128 bits to 64 bits.
+    let low32 = _mm_set_epi32(0, 0, 0, !0);
+    let x = _mm_xor_si128(
+        _mm_clmulepi64_si128(x, k3k4, 0x10),
+        _mm_srli_si128(x, 8),
+    );
+    let x = _mm_xor_si128(
+        _mm_clmulepi64_si128(
+            _mm_and_si128(x, low32),
+            _mm_set_epi64x(0, K5),
+            0x00,
+        ),
+        _mm_srli_si128(x, 4),
+    );
+    // Barrett reduction from 64 bits to 32 bits. Since the polynomial is bit
+    // reflected, the result is in the upper 32 bits of the lower 64 bits.
+    let pu = _mm_set_epi64x(U_PRIME, P_X);
+    let t1 = _mm_clmulepi64_si128(_mm_and_si128(x, low32), pu, 0x10);
+    let t2 = _mm_clmulepi64_si128(_mm_and_si128(t1, low32), pu, 0x00);
+    !(_mm_extract_epi32(_mm_xor_si128(x, t2), 1) as u32)
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "no-simd")))]
 #[target_feature(enable = "sse4.2")]
-unsafe fn crc32c_sse(buf: &[u8]) -> u32 {
+unsafe fn crc32c_sse(crc: u32, buf: &[u8]) -> u32 {
     use std::arch::x86_64::*;
 
-    let mut crc = !0u32;
+    let mut crc = !crc;
     // SAFETY: This is safe since alignment is handled by align_to (oh how I
     // love you) and since 8 adjacent u8's are guaranteed to have the same
     // in-memory representation as u64 for all possible values.
@@ -81,9 +413,10 @@ unsafe fn crc32c_sse(buf: &[u8]) -> u32 {
     !crc
 }
 
-/// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial.
-fn crc32c_slice16(mut buf: &[u8]) -> u32 {
-    let mut crc: u32 = !0;
+/// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial,
+/// continuing from the checksum `crc` of the preceding bytes.
+fn crc32c_slice16(crc: u32, mut buf: &[u8]) -> u32 {
+    let mut crc = !crc;
     while buf.len() >= 16 {
         crc ^= bytes::read_u32_le(buf);
         crc = TABLE16[0][buf[15] as usize]
@@ -109,3 +442,21 @@ fn crc32c_slice16(mut buf: &[u8]) -> u32 {
     }
     !crc
 }
+
+/// Returns the CRC32 checksum of `buf` using the Castagnoli polynomial,
+/// continuing from the checksum `crc` of the preceding bytes. It's computed
+/// one bit at a time without any lookup tables.
+fn crc32c_bitwise(crc: u32, buf: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in buf {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            if crc & 1 == 1 {
+                crc = (crc >> 1) ^ CASTAGNOLI_POLY;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
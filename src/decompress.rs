@@ -1,4 +1,8 @@
+use std::cmp;
+use std::io;
+use std::ops::{self, ControlFlow};
 use std::ptr;
+use std::result;
 
 use crate::bytes;
 use crate::error::{Error, Result};
@@ -16,6 +20,15 @@ const TAG_LOOKUP_TABLE: TagLookupTable = TagLookupTable(tag::TAG_LOOKUP_TABLE);
 /// bits we need. This in particular saves a branch.
 const WORD_MASK: [usize; 5] = [0, 0xFF, 0xFFFF, 0xFFFFFF, 0xFFFFFFFF];
 
+/// The number of decompressed bytes kept around by
+/// `Decoder::decompress_to_writer` so that copies can refer back to them.
+/// Conforming compressors never emit a copy offset bigger than this.
+const WINDOW_SIZE: usize = 1 << 16;
+
+/// The number of buffered decompressed bytes at which
+/// `Decoder::decompress_to_writer` writes them to the underlying writer.
+const WINDOW_FLUSH_SIZE: usize = 2 * WINDOW_SIZE;
+
 /// Returns the decompressed size (in bytes) of the compressed bytes given.
 ///
 /// `input` must be a sequence of bytes returned by a conforming Snappy
@@ -27,6 +40,7 @@ const WORD_MASK: [usize; 5] = [0, 0xFF, 0xFFFF, 0xFFFFFF, 0xFFFFFFFF];
 ///
 /// * An invalid Snappy header was seen.
 /// * The total space required for decompression exceeds `2^32 - 1`.
+#[must_use = "this returns the decompressed length and has no effect"]
 pub fn decompress_len(input: &[u8]) -> Result<usize> {
     if input.is_empty() {
         return Ok(0);
@@ -34,6 +48,26 @@ pub fn decompress_len(input: &[u8]) -> Result<usize> {
     Ok(Header::read(input)?.decompress_len)
 }
 
+/// Decompresses all bytes in `input` into `output` using the raw Snappy
+/// format.
+///
+/// This is a convenience function for one-shot decompression. It is
+/// equivalent to `Decoder::new().decompress(input, output)`, and so it has
+/// the same requirements and returns the same errors.
+///
+/// # Example
+///
+/// ```
+/// let compressed = snap::raw::Encoder::new().compress_vec(b"Hello!").unwrap();
+/// let mut output = vec![0; snap::raw::decompress_len(&compressed).unwrap()];
+/// let n = snap::raw::decompress(&compressed, &mut output).unwrap();
+/// assert_eq!(b"Hello!", &output[..n]);
+/// ```
+#[must_use = "decompression errors must be handled"]
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize> {
+    Decoder::new().decompress(input, output)
+}
+
 /// Decoder is a raw decoder for decompressing bytes in the Snappy format.
 ///
 /// This decoder does not use the Snappy frame format and simply decompresses
@@ -63,7 +97,13 @@ impl Decoder {
     /// bytes from the `input`. The size required can be queried with the
     /// `decompress_len` function.
     ///
-    /// On success, this returns the number of bytes written to `output`.
+    /// `output` may be bigger than required. In that case, only the first
+    /// `decompress_len(input)` bytes of `output` are written to and the
+    /// remaining bytes are left untouched. This makes it possible to reuse a
+    /// single large buffer for decompressing many blocks of varying size.
+    ///
+    /// On success, this returns the number of bytes written to `output`,
+    /// which is always equal to `decompress_len(input)`.
     ///
     /// # Errors
     ///
@@ -72,11 +112,104 @@ impl Decoder {
     /// * Invalid compressed Snappy data was seen.
     /// * The total space required for decompression exceeds `2^32 - 1`.
     /// * `output` has length less than `decompress_len(input)`.
+    #[must_use = "decompression errors must be handled"]
     pub fn decompress(
         &mut self,
         input: &[u8],
         output: &mut [u8],
+    ) -> Result<usize> {
+        self.decompress_with_dict(&[], input, output)
+    }
+
+    /// Decompresses the payload of a compressed chunk from a stream in the
+    /// Snappy frame format into `output`.
+    ///
+    /// A compressed chunk consists of a 4 byte chunk header, a 4 byte masked
+    /// CRC32C checksum of the uncompressed data, and then its payload, which
+    /// is a complete block in the raw Snappy format. So given just the
+    /// payload, e.g., as extracted from a stream by some other tool, this is
+    /// exactly equivalent to `decompress`. Note that the checksum isn't part
+    /// of the payload, so it isn't verified.
+    ///
+    /// The requirements on `output`, as well as the return value and the
+    /// errors returned, are the same as for `decompress`.
+    pub fn decompress_frame_payload(
+        &mut self,
+        payload: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize> {
+        self.decompress(payload, output)
+    }
+
+    /// Decompresses all bytes in `input` into `output`, resolving copies that
+    /// reach before the start of `output` against `dict`.
+    ///
+    /// This is the counterpart to `Encoder::compress_with_dict`, and `dict`
+    /// must be the same dictionary that `input` was compressed with.
+    /// Conceptually, copies are resolved as if the decompressed bytes were
+    /// preceded by `dict`, but `dict` itself is never written to `output`.
+    /// When `dict` is empty, this is equivalent to `decompress`.
+    ///
+    /// All other requirements on `output`, as well as the return value, are
+    /// the same as for `decompress`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress` does, except that a copy is only considered invalid if it
+    /// reaches before the start of `dict`.
+    #[must_use = "decompression errors must be handled"]
+    pub fn decompress_with_dict(
+        &mut self,
+        dict: &[u8],
+        input: &[u8],
+        output: &mut [u8],
     ) -> Result<usize> {
+        if input.is_empty() {
+            return Err(Error::Empty);
+        }
+        let hdr = Header::read_block(input)?;
+        if hdr.decompress_len > output.len() {
+            return Err(Error::BufferTooSmall {
+                given: output.len() as u64,
+                min: hdr.decompress_len as u64,
+            });
+        }
+        let dst = &mut output[..hdr.decompress_len];
+        let mut dec = Decompress {
+            src: &input[hdr.len..],
+            s: 0,
+            out: Buffer { dict, dst },
+            d: 0,
+        };
+        dec.decompress()?;
+        Ok(dec.d)
+    }
+
+    /// Decompresses the block at the start of `input` into `output`, and
+    /// returns the number of bytes of `input` that the block occupies along
+    /// with the number of bytes written to `output`.
+    ///
+    /// Unlike `decompress`, `input` may contain more bytes after the block,
+    /// which are ignored. Since a block in the raw format isn't prefixed by
+    /// its compressed length, the end of the block is only known once all of
+    /// the decompressed bytes reported by its header have been produced.
+    /// This makes it possible to decompress blocks that are concatenated
+    /// without any framing, by advancing `input` by the number of bytes
+    /// consumed each time.
+    ///
+    /// All other requirements on `output` are the same as for `decompress`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress` does. In particular, if `input` ends before the block
+    /// does, then an error is returned.
+    pub fn decompress_prefix(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, usize)> {
         if input.is_empty() {
             return Err(Error::Empty);
         }
@@ -88,10 +221,57 @@ impl Decoder {
             });
         }
         let dst = &mut output[..hdr.decompress_len];
-        let mut dec =
-            Decompress { src: &input[hdr.len..], s: 0, dst: dst, d: 0 };
+        let mut dec = Decompress {
+            src: &input[hdr.len..],
+            s: 0,
+            out: Buffer { dict: &[], dst },
+            d: 0,
+        };
+        dec.decompress_prefix()?;
+        Ok((hdr.len + dec.s, dec.d))
+    }
+
+    /// Decompresses all bytes in `input` into `output`, and returns
+    /// statistics about the operations that `input` is made of along with
+    /// the number of bytes written to `output`.
+    ///
+    /// This is useful for analyzing how well a compressor did, e.g., by
+    /// comparing the number of bytes produced by literals and by copies.
+    /// Decompression is otherwise identical to `decompress`, although it's a
+    /// little slower since every operation is counted.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress` does.
+    pub fn decompress_stats(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeStats)> {
+        if input.is_empty() {
+            return Err(Error::Empty);
+        }
+        let hdr = Header::read_block(input)?;
+        if hdr.decompress_len > output.len() {
+            return Err(Error::BufferTooSmall {
+                given: output.len() as u64,
+                min: hdr.decompress_len as u64,
+            });
+        }
+        let dst = &mut output[..hdr.decompress_len];
+        let mut stats = DecodeStats::default();
+        let mut dec = Decompress {
+            src: &input[hdr.len..],
+            s: 0,
+            out: Counting {
+                out: Buffer { dict: &[], dst },
+                stats: &mut stats,
+            },
+            d: 0,
+        };
         dec.decompress()?;
-        Ok(dec.dst.len())
+        Ok((dec.d, stats))
     }
 
     /// Decompresses all bytes in `input` into a freshly allocated `Vec`.
@@ -102,54 +282,381 @@ impl Decoder {
     ///
     /// This method returns an error under the same circumstances that
     /// `decompress` does.
+    #[must_use = "the decompressed bytes are only returned"]
     pub fn decompress_vec(&mut self, input: &[u8]) -> Result<Vec<u8>> {
         let mut buf = vec![0; decompress_len(input)?];
         let n = self.decompress(input, &mut buf)?;
         buf.truncate(n);
         Ok(buf)
     }
+
+    /// Decompresses all bytes in `input` into `scratch`, and returns it.
+    ///
+    /// This is just like the `decompress_vec` method, except it reuses the
+    /// allocation of `scratch` instead of allocating a new `Vec`. Any
+    /// existing contents of `scratch` are discarded. Threading the returned
+    /// `Vec` through subsequent calls avoids allocating for each input.
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress` does.
+    #[must_use = "the decompressed bytes are only returned"]
+    pub fn decompress_into(
+        &mut self,
+        input: &[u8],
+        mut scratch: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        scratch.clear();
+        scratch.resize(decompress_len(input)?, 0);
+        let n = self.decompress(input, &mut scratch)?;
+        scratch.truncate(n);
+        Ok(scratch)
+    }
+
+    /// Decompresses all bytes in `input` and writes them to `wtr`.
+    ///
+    /// Unlike `decompress`, this never needs a buffer big enough for all of
+    /// the decompressed bytes. Instead, decompressed bytes are written to
+    /// `wtr` as they are produced, and only a sliding window of the most
+    /// recent 64KB (plus some slack for batching writes) is kept in memory
+    /// so that copies can refer back to it. Memory usage is therefore bounded
+    /// regardless of the decompressed size of `input`.
+    ///
+    /// On success, this returns the total number of bytes written to `wtr`,
+    /// which is always equal to `decompress_len(input)`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress` does, converted to a `std::io::Error`. Additionally, a
+    /// copy whose offset reaches further back than the 64KB window is
+    /// reported as an `Error::Offset`, even if it would otherwise be valid.
+    /// Conforming compressors (including this crate's `Encoder`) never
+    /// produce such copies. Finally, any error returned by `wtr` is returned
+    /// as is.
+    ///
+    /// Since bytes are written to `wtr` incrementally, some output may have
+    /// already been written when an error is returned.
+    pub fn decompress_to_writer<W: io::Write>(
+        &mut self,
+        input: &[u8],
+        mut wtr: W,
+    ) -> io::Result<u64> {
+        let result =
+            self.decompress_window(input, |buf| match wtr.write_all(buf) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(err) => ControlFlow::Break(err),
+            });
+        match result {
+            Ok(n) => Ok(n),
+            Err(Stop::Error(err)) => Err(err.into()),
+            Err(Stop::Break(err)) => Err(err),
+        }
+    }
+
+    /// Returns true if the bytes that `input` decompresses to contain
+    /// `needle`.
+    ///
+    /// This is like decompressing `input` with `decompress_to_writer` and
+    /// searching the result, except the decompressed bytes are searched as
+    /// they're produced, without ever holding all of them in memory.
+    /// Decompression stops as soon as `needle` is found, which makes this
+    /// much faster than decompressing everything when matches tend to occur
+    /// early. Matches that straddle the boundary between two batches of
+    /// decompressed bytes are found too.
+    ///
+    /// An empty `needle` is contained in everything, including empty
+    /// decompressed bytes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error under the same circumstances that
+    /// `decompress_to_writer` does, except that errors in the compressed
+    /// bytes following a match are never detected.
+    pub fn decompress_and_contains(
+        &mut self,
+        input: &[u8],
+        needle: &[u8],
+    ) -> Result<bool> {
+        let mut searcher = Searcher::new(needle);
+        let result = self.decompress_window(input, |buf| {
+            if searcher.search(buf) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        match result {
+            Ok(_) => Ok(searcher.found()),
+            Err(Stop::Break(())) => Ok(true),
+            // An empty needle is found before any error can be.
+            Err(Stop::Error(_)) if searcher.found() => Ok(true),
+            Err(Stop::Error(err)) => Err(err),
+        }
+    }
+
+    /// Checks that all bytes in `input` would decompress successfully,
+    /// without actually decompressing them.
+    ///
+    /// On success, this returns the number of bytes that decompressing
+    /// `input` would produce, which is equal to `decompress_len(input)`.
+    ///
+    /// No output buffer is needed or allocated. In particular, no sliding
+    /// window of decompressed bytes is kept either, so memory usage is
+    /// constant regardless of the size of `input` or the copy offsets it
+    /// uses. This works because whether a Snappy block is valid only depends
+    /// on the lengths and offsets of its operations, and never on the bytes
+    /// that they produce.
+    ///
+    /// # Errors
+    ///
+    /// This method returns exactly the error that `decompress` would return
+    /// when given `input` and an output buffer of size
+    /// `decompress_len(input)`.
+    pub fn validate(&self, input: &[u8]) -> Result<usize> {
+        if input.is_empty() {
+            return Err(Error::Empty);
+        }
+        let hdr = Header::read_block(input)?;
+        let mut dec = Decompress {
+            src: &input[hdr.len..],
+            s: 0,
+            out: Discard { len: hdr.decompress_len },
+            d: 0,
+        };
+        dec.decompress()?;
+        Ok(dec.d)
+    }
+
+    /// Continues decompressing the body of a block, i.e., everything after
+    /// its header, onto the end of `output` when only the first `body.len()`
+    /// bytes of it may be available so far. `len` is the decompressed length
+    /// given by the block's header.
+    ///
+    /// `output` only grows as bytes are decompressed, so a header claiming a
+    /// huge length doesn't cause a huge allocation by itself.
+    ///
+    /// `progress` records where decompression stopped, and must start out
+    /// as `Progress::default()` (with an empty `output`) for each block. When
+    /// `more` is false, `body` is known to contain every byte that there is,
+    /// and running out of bytes is an error just like for
+    /// `decompress_prefix`.
+    pub(crate) fn decompress_partial(
+        &mut self,
+        body: &[u8],
+        len: usize,
+        output: &mut Vec<u8>,
+        progress: &mut Progress,
+        more: bool,
+    ) -> Result<Partial> {
+        debug_assert_eq!(progress.d, output.len());
+        let mut dec = Decompress {
+            src: body,
+            s: progress.s,
+            out: Growing { len, dst: output },
+            d: progress.d,
+        };
+        let result = dec.run(true);
+        progress.s = dec.s;
+        progress.d = dec.d;
+        match result {
+            Ok(()) if dec.d == dec.out.len() => Ok(Partial::Done(dec.s)),
+            Ok(()) if more => Ok(Partial::Needs(1)),
+            Err(err) if more => match missing_input(&err) {
+                Some(n) => Ok(Partial::Needs(n)),
+                None => Err(err),
+            },
+            Ok(()) => dec.check_len().map(|()| Partial::Done(dec.s)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Decompresses all bytes in `input` and gives them to `consume` in
+    /// batches, keeping only a bounded window of them in memory.
+    ///
+    /// On success, this returns the total number of decompressed bytes.
+    fn decompress_window<B, F: FnMut(&[u8]) -> ControlFlow<B>>(
+        &mut self,
+        input: &[u8],
+        consume: F,
+    ) -> result::Result<u64, Stop<B>> {
+        if input.is_empty() {
+            return Err(Error::Empty.into());
+        }
+        let hdr = Header::read_block(input)?;
+        let mut dec = Decompress {
+            src: &input[hdr.len..],
+            s: 0,
+            out: Window::new(hdr.decompress_len, consume),
+            d: 0,
+        };
+        dec.decompress()?;
+        dec.out.flush()?;
+        Ok(dec.d as u64)
+    }
+}
+
+/// Progress is how far `Decoder::decompress_partial` got in the body of a
+/// block.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Progress {
+    /// The position in the compressed bytes of the body.
+    s: usize,
+    /// The position in the decompressed bytes.
+    d: usize,
+}
+
+/// Partial is the outcome of `Decoder::decompress_partial`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Partial {
+    /// The block is complete, and its body has the given length.
+    Done(usize),
+    /// At least this many more bytes of the body are needed to continue.
+    Needs(usize),
+}
+
+/// Returns the number of compressed bytes missing from the end of the input
+/// if `err` is only due to the input ending in the middle of an operation.
+fn missing_input(err: &Error) -> Option<usize> {
+    match *err {
+        // A literal that can't fit in the output is invalid no matter how
+        // many more bytes there are. The exception is a literal whose 1-4
+        // byte length is cut short, which is reported with a length of 4.
+        Error::Literal { len, src_len, dst_len }
+            if src_len < len && len <= cmp::max(dst_len, 4) =>
+        {
+            Some((len - src_len) as usize)
+        }
+        Error::CopyRead { len, src_len } => Some((len - src_len) as usize),
+        _ => None,
+    }
+}
+
+/// Statistics about the operations in a block of compressed bytes, as
+/// returned by
+/// [`Decoder::decompress_stats`](struct.Decoder.html#method.decompress_stats).
+///
+/// A block is a sequence of literals, which are runs of bytes copied from
+/// the block itself, and copies, which repeat bytes that were decompressed
+/// earlier.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeStats {
+    literals: u64,
+    literal_bytes: u64,
+    copies: u64,
+    copy_bytes: u64,
+}
+
+impl DecodeStats {
+    /// Returns the number of literal operations.
+    pub fn literals(&self) -> u64 {
+        self.literals
+    }
+
+    /// Returns the total number of bytes produced by literal operations.
+    pub fn literal_bytes(&self) -> u64 {
+        self.literal_bytes
+    }
+
+    /// Returns the number of copy operations.
+    pub fn copies(&self) -> u64 {
+        self.copies
+    }
+
+    /// Returns the total number of bytes produced by copy operations.
+    pub fn copy_bytes(&self) -> u64 {
+        self.copy_bytes
+    }
+
+    /// Returns the average number of bytes produced by a copy operation.
+    ///
+    /// If there were no copies, then `0.0` is returned.
+    pub fn average_copy_len(&self) -> f64 {
+        if self.copies == 0 {
+            return 0.0;
+        }
+        self.copy_bytes as f64 / self.copies as f64
+    }
 }
 
-/// Decompress is the state of the Snappy compressor.
-struct Decompress<'s, 'd> {
+/// Decompress is the state of the Snappy decompressor.
+///
+/// It parses the operations in `src` and checks each of them against the
+/// number of decompressed bytes expected, while `out` decides what happens
+/// to the bytes they produce. Every way of decompressing a block drives this
+/// one loop, so an operation is valid for all of them or for none.
+struct Decompress<'s, O> {
     /// The original compressed bytes not including the header.
     src: &'s [u8],
     /// The current position in the compressed bytes.
     s: usize,
-    /// The output buffer to write the decompressed bytes.
-    dst: &'d mut [u8],
-    /// The current position in the decompressed buffer.
+    /// Where the decompressed bytes go.
+    out: O,
+    /// The current position in the decompressed bytes.
     d: usize,
 }
 
-impl<'s, 'd> Decompress<'s, 'd> {
-    /// Decompresses snappy compressed bytes in `src` to `dst`.
+impl<'s, O: Output> Decompress<'s, O> {
+    /// Decompresses snappy compressed bytes in `src` to `out`.
+    ///
+    /// This assumes that the header has already been read and that `out`
+    /// expects exactly as many bytes as the header says.
+    fn decompress(&mut self) -> result::Result<(), O::Error> {
+        self.run(false)?;
+        self.check_len()
+    }
+
+    /// Like `decompress`, except this stops as soon as `out` is full, even
+    /// if there are bytes left in `src`. Afterwards, `s` is the length of the
+    /// block.
+    fn decompress_prefix(&mut self) -> result::Result<(), O::Error> {
+        self.run(true)?;
+        self.check_len()
+    }
+
+    /// Decompresses operations until `src` runs out or, if `prefix` is
+    /// true, until `out` is full.
     ///
-    /// This assumes that the header has already been read and that `dst` is
-    /// big enough to store all decompressed bytes.
-    fn decompress(&mut self) -> Result<()> {
-        while self.s < self.src.len() {
+    /// When `prefix` is true and an operation fails, `s` is reset to the
+    /// start of that operation, so that decompression can resume from there
+    /// once more compressed bytes are available.
+    #[inline(always)]
+    fn run(&mut self, prefix: bool) -> result::Result<(), O::Error> {
+        while self.s < self.src.len() && !(prefix && self.d == self.out.len())
+        {
+            let op = self.s;
             let byte = self.src[self.s];
             self.s += 1;
-            if byte & 0b000000_11 == 0 {
+            let result = if byte & 0b11 == 0 {
                 let len = (byte >> 2) as usize + 1;
-                self.read_literal(len)?;
+                self.read_literal(len)
             } else {
-                self.read_copy(byte)?;
+                self.read_copy(byte)
+            };
+            if let Err(err) = result {
+                if prefix {
+                    self.s = op;
+                }
+                return Err(err);
             }
         }
-        if self.d != self.dst.len() {
+        Ok(())
+    }
+
+    /// Returns an error if fewer bytes were decompressed than expected.
+    fn check_len(&self) -> result::Result<(), O::Error> {
+        if self.d != self.out.len() {
             return Err(Error::HeaderMismatch {
-                expected_len: self.dst.len() as u64,
+                expected_len: self.out.len() as u64,
                 got_len: self.d as u64,
-            });
+            }
+            .into());
         }
         Ok(())
     }
 
-    /// Decompresses a literal from `src` starting at `s` to `dst` starting at
-    /// `d` and returns the updated values of `s` and `d`. `s` should point to
-    /// the byte immediately proceding the literal tag byte.
+    /// Decompresses a literal from `src` starting at `s` to `out` starting at
+    /// `d` and updates `s` and `d`. `s` should point to the byte immediately
+    /// proceding the literal tag byte.
     ///
     /// `len` is the length of the literal if it's <=60. Otherwise, it's the
     /// length tag, indicating the number of bytes needed to read a little
@@ -158,27 +665,22 @@ impl<'s, 'd> Decompress<'s, 'd> {
     ///
     /// `len` must be <=64.
     #[inline(always)]
-    fn read_literal(&mut self, len: usize) -> Result<()> {
+    fn read_literal(&mut self, len: usize) -> result::Result<(), O::Error> {
         debug_assert!(len <= 64);
         let mut len = len as u64;
         // As an optimization for the common case, if the literal length is
-        // <=16 and we have enough room in both `src` and `dst`, copy the
-        // literal using unaligned loads and stores.
-        //
-        // We pick 16 bytes with the hope that it optimizes down to a 128 bit
-        // load/store.
+        // <=16 and we have enough room in both `src` and `out`, let `out`
+        // copy all 16 bytes. See `Output::write_short_literal`.
         if len <= 16
             && self.s + 16 <= self.src.len()
-            && self.d + 16 <= self.dst.len()
+            && self.d + 16 <= self.out.len()
         {
             unsafe {
-                // SAFETY: We know both src and dst have at least 16 bytes of
-                // wiggle room after s/d, even if `len` is <16, so the copy is
-                // safe.
-                let srcp = self.src.as_ptr().add(self.s);
-                let dstp = self.dst.as_mut_ptr().add(self.d);
-                // Hopefully uses SIMD registers for 128 bit load/store.
-                ptr::copy_nonoverlapping(srcp, dstp, 16);
+                // SAFETY: The conditional above guarantees that
+                // src[s..s+16] is in bounds and that `out` has room for 16
+                // bytes at `d`.
+                let lit = self.src.get_unchecked(self.s..self.s + 16);
+                self.out.write_short_literal(self.d, lit, len as usize)?;
             }
             self.d += len as usize;
             self.s += len as usize;
@@ -193,8 +695,9 @@ impl<'s, 'd> Decompress<'s, 'd> {
                 return Err(Error::Literal {
                     len: 4,
                     src_len: (self.src.len() - self.s) as u64,
-                    dst_len: (self.dst.len() - self.d) as u64,
-                });
+                    dst_len: (self.out.len() - self.d) as u64,
+                }
+                .into());
             }
             // Since we know there are 4 bytes left to read, read a 32 bit LE
             // integer and mask away the bits we don't need.
@@ -205,34 +708,38 @@ impl<'s, 'd> Decompress<'s, 'd> {
         }
         // If there's not enough buffer left to load or store this literal,
         // then the input is corrupt.
-        // if self.s + len > self.src.len() || self.d + len > self.dst.len() {
+        //
+        // We compare against the remaining lengths instead of computing
+        // `s + len` or `d + len`, since `len` may be as big as `2^32`, which
+        // would wrap a 32 bit `usize`. The subtractions can't underflow
+        // because `s <= src.len()` and `d <= out.len()` always hold here.
         if ((self.src.len() - self.s) as u64) < len
-            || ((self.dst.len() - self.d) as u64) < len
+            || ((self.out.len() - self.d) as u64) < len
         {
             return Err(Error::Literal {
-                len: len,
+                len,
                 src_len: (self.src.len() - self.s) as u64,
-                dst_len: (self.dst.len() - self.d) as u64,
-            });
+                dst_len: (self.out.len() - self.d) as u64,
+            }
+            .into());
         }
         unsafe {
-            // SAFETY: We've already checked the bounds, so we know this copy
-            // is correct.
-            let srcp = self.src.as_ptr().add(self.s);
-            let dstp = self.dst.as_mut_ptr().add(self.d);
-            ptr::copy_nonoverlapping(srcp, dstp, len as usize);
+            // SAFETY: We've already checked the bounds, so we know that
+            // src[s..s+len] is in bounds and that `out` has room for it.
+            let lit = self.src.get_unchecked(self.s..self.s + len as usize);
+            self.out.write_literal(self.d, lit)?;
         }
         self.s += len as usize;
         self.d += len as usize;
         Ok(())
     }
 
-    /// Reads a copy from `src` and writes the decompressed bytes to `dst`. `s`
-    /// should point to the byte immediately proceding the copy tag byte.
+    /// Reads a copy from `src` and writes the decompressed bytes to `out`.
+    /// `s` should point to the byte immediately proceding the copy tag byte.
     #[inline(always)]
-    fn read_copy(&mut self, tag_byte: u8) -> Result<()> {
+    fn read_copy(&mut self, tag_byte: u8) -> result::Result<(), O::Error> {
         // Find the copy offset and len, then advance the input past the copy.
-        // The rest of this function deals with reading/writing to output only.
+        // The rest of this function deals with writing to output only.
         let entry = TAG_LOOKUP_TABLE.entry(tag_byte);
         let offset = entry.offset(self.src, self.s)?;
         let len = entry.len();
@@ -242,34 +749,206 @@ impl<'s, 'd> Decompress<'s, 'd> {
         // To save an extra branch, use `d < offset - 1` instead. If `d` is
         // `0`, then `offset.wrapping_sub(1)` will be usize::MAX which is also
         // the max value of `d`.
-        if self.d <= offset.wrapping_sub(1) {
+        //
+        // Copies that reach before the start of the output, e.g., into a
+        // dictionary, fail this check too, so they are handled out of line
+        // to keep this path fast.
+        if self.d > offset.wrapping_sub(1) {
+            // SAFETY: The conditional above guarantees that
+            // `1 <= offset <= d`.
+            if unsafe { self.out.write_copy_fast(self.d, offset, len) } {
+                self.d += len;
+                return Ok(());
+            }
+        }
+        self.read_copy_checked(offset, len)
+    }
+
+    /// Checks a copy against the bytes that it may refer to and the room left
+    /// in `out`, and then writes it.
+    ///
+    /// This is only used when `Output::write_copy_fast` declines a copy,
+    /// which for in-memory output happens near the end of the output or for
+    /// copies reaching into a dictionary. It's kept out of line so that it
+    /// doesn't bloat the main decompression loop.
+    #[cold]
+    #[inline(never)]
+    fn read_copy_checked(
+        &mut self,
+        offset: usize,
+        len: usize,
+    ) -> result::Result<(), O::Error> {
+        if offset == 0 || offset > self.out.reach(self.d) {
             return Err(Error::Offset {
                 offset: offset as u64,
                 dst_pos: self.d as u64,
-            });
+            }
+            .into());
         }
-        // When all is said and done, dst is advanced to end.
         let end = self.d + len;
+        if end > self.out.len() {
+            return Err(Error::CopyWrite {
+                len: len as u64,
+                dst_len: (self.out.len() - self.d) as u64,
+            }
+            .into());
+        }
+        self.out.write_copy(self.d, offset, len)?;
+        self.d = end;
+        Ok(())
+    }
+}
+
+/// Output is where `Decompress` puts the bytes that it decompresses.
+///
+/// `Decompress` validates every operation before handing it to an output,
+/// so implementations only ever store bytes and never check them.
+trait Output {
+    /// The error returned by this output. Errors in the compressed bytes are
+    /// converted into it.
+    type Error: From<Error>;
+
+    /// Returns the total number of decompressed bytes expected.
+    fn len(&self) -> usize;
+
+    /// Returns the number of bytes preceding position `d` that copies may
+    /// refer to, i.e., the biggest valid copy offset at `d`.
+    fn reach(&self, d: usize) -> usize;
+
+    /// Writes the first `len` bytes of `lit` at position `d`.
+    ///
+    /// Any bytes written past `d + len` are overwritten by later operations.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `lit` has length 16, that `len <= 16` and
+    /// that `d + 16 <= self.len()`.
+    unsafe fn write_short_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+        len: usize,
+    ) -> result::Result<(), Self::Error>;
+
+    /// Writes `lit` at position `d`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `d + lit.len() <= self.len()`.
+    unsafe fn write_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+    ) -> result::Result<(), Self::Error>;
+
+    /// Tries to write a copy of `len` bytes from `offset` bytes before `d`
+    /// without checking it against `len()`, and returns whether it did.
+    ///
+    /// If it didn't, the copy is checked and then written with
+    /// `write_copy` instead. By default, every copy takes that path.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `1 <= offset <= d`.
+    #[inline(always)]
+    unsafe fn write_copy_fast(
+        &mut self,
+        _d: usize,
+        _offset: usize,
+        _len: usize,
+    ) -> bool {
+        false
+    }
+
+    /// Writes a copy of `len` bytes from `offset` bytes before `d`.
+    ///
+    /// Callers must ensure that `1 <= offset <= self.reach(d)` and that
+    /// `d + len <= self.len()`.
+    fn write_copy(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> result::Result<(), Self::Error>;
+}
+
+/// Buffer is the output for decompressing into memory.
+struct Buffer<'s, 'd> {
+    /// Bytes that conceptually precede `dst`, which copies may refer to. This
+    /// is empty unless decompressing with a dictionary.
+    dict: &'s [u8],
+    /// The output buffer to write the decompressed bytes, which is exactly
+    /// as long as the decompressed bytes.
+    dst: &'d mut [u8],
+}
+
+impl<'s, 'd> Output for Buffer<'s, 'd> {
+    type Error = Error;
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.dst.len()
+    }
+
+    fn reach(&self, d: usize) -> usize {
+        d + self.dict.len()
+    }
+
+    #[inline(always)]
+    unsafe fn write_short_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+        _len: usize,
+    ) -> Result<()> {
+        debug_assert!(lit.len() == 16 && d + 16 <= self.dst.len());
+        // SAFETY: The caller guarantees that both `lit` and `dst` have at
+        // least 16 bytes of wiggle room, even if `len` is <16, so the copy
+        // is safe.
+        //
+        // Hopefully uses SIMD registers for 128 bit load/store.
+        let dstp = self.dst.as_mut_ptr().add(d);
+        ptr::copy_nonoverlapping(lit.as_ptr(), dstp, 16);
+        Ok(())
+    }
+
+    #[inline(always)]
+    unsafe fn write_literal(&mut self, d: usize, lit: &[u8]) -> Result<()> {
+        debug_assert!(d + lit.len() <= self.dst.len());
+        // SAFETY: The caller has already checked the bounds, so we know
+        // this copy is correct.
+        let dstp = self.dst.as_mut_ptr().add(d);
+        ptr::copy_nonoverlapping(lit.as_ptr(), dstp, lit.len());
+        Ok(())
+    }
+
+    #[inline(always)]
+    unsafe fn write_copy_fast(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> bool {
+        // When all is said and done, dst is advanced to end.
+        let end = d + len;
         // When the copy is small and the offset is at least 8 bytes away from
         // `d`, then we can decompress the copy with two 64 bit unaligned
         // loads/stores.
-        if offset >= 8 && len <= 16 && self.d + 16 <= self.dst.len() {
-            unsafe {
-                // SAFETY: We know dstp points to at least 16 bytes of memory
-                // from the condition above, and we also know that dstp is
-                // preceded by at least `offset` bytes from the `d <= offset`
-                // check above.
-                //
-                // We also know that dstp and dstp-8 do not overlap from the
-                // check above, justifying the use of copy_nonoverlapping.
-                let dstp = self.dst.as_mut_ptr().add(self.d);
-                let srcp = dstp.sub(offset);
-                // We can't do a single 16 byte load/store because src/dst may
-                // overlap with each other. Namely, the second copy here may
-                // copy bytes written in the first copy!
-                ptr::copy_nonoverlapping(srcp, dstp, 8);
-                ptr::copy_nonoverlapping(srcp.add(8), dstp.add(8), 8);
-            }
+        if offset >= 8 && len <= 16 && d + 16 <= self.dst.len() {
+            // SAFETY: We know dstp points to at least 16 bytes of memory
+            // from the condition above, and we also know that dstp is
+            // preceded by at least `offset` bytes from the caller's
+            // guarantee that `offset <= d`.
+            //
+            // We also know that dstp and dstp-8 do not overlap from the
+            // check above, justifying the use of copy_nonoverlapping.
+            let dstp = self.dst.as_mut_ptr().add(d);
+            let srcp = dstp.sub(offset);
+            // We can't do a single 16 byte load/store because src/dst may
+            // overlap with each other. Namely, the second copy here may
+            // copy bytes written in the first copy!
+            ptr::copy_nonoverlapping(srcp, dstp, 8);
+            ptr::copy_nonoverlapping(srcp.add(8), dstp.add(8), 8);
         // If we have some wiggle room, try to decompress the copy 16 bytes
         // at a time with 128 bit unaligned loads/stores. Remember, we can't
         // just do a memcpy because decompressing copies may require copying
@@ -277,72 +956,490 @@ impl<'s, 'd> Decompress<'s, 'd> {
         //
         // We need the extra wiggle room to make effective use of 128 bit
         // loads/stores. Even if the store ends up copying more data than we
-        // need, we're careful to advance `d` by the correct amount at the end.
+        // need, the caller advances `d` by the correct amount at the end.
         } else if end + 24 <= self.dst.len() {
-            unsafe {
-                // SAFETY: We know that dstp is preceded by at least `offset`
-                // bytes from the `d <= offset` check above.
-                //
-                // We don't know whether dstp overlaps with srcp, so we start
-                // by copying from srcp to dstp until they no longer overlap.
-                // The worst case is when dstp-src = 3 and copy length = 1. The
-                // first loop will issue these copy operations before stopping:
-                //
-                //   [-1, 14] -> [0, 15]
-                //   [-1, 14] -> [3, 18]
-                //   [-1, 14] -> [9, 24]
-                //
-                // But the copy had length 1, so it was only supposed to write
-                // to [0, 0]. But the last copy wrote to [9, 24], which is 24
-                // extra bytes in dst *beyond* the end of the copy, which is
-                // guaranteed by the conditional above.
-
-                // Save destination length here to avoid a reborrow UB violation
-                // under the Tree Borrows model.
-                let dest_len = self.dst.len();
-
-                let mut dstp = self.dst.as_mut_ptr().add(self.d);
-                let mut srcp = dstp.sub(offset);
-                loop {
-                    debug_assert!(dstp >= srcp);
-                    let diff = (dstp as usize) - (srcp as usize);
-                    if diff >= 16 {
-                        break;
-                    }
-                    // srcp and dstp can overlap, so use ptr::copy.
-                    debug_assert!(self.d + 16 <= dest_len);
-                    ptr::copy(srcp, dstp, 16);
-                    self.d += diff as usize;
-                    dstp = dstp.add(diff);
-                }
-                while self.d < end {
-                    ptr::copy_nonoverlapping(srcp, dstp, 16);
-                    srcp = srcp.add(16);
-                    dstp = dstp.add(16);
-                    self.d += 16;
+            // SAFETY: We know that dstp is preceded by at least `offset`
+            // bytes from the caller's guarantee that `offset <= d`.
+            //
+            // We don't know whether dstp overlaps with srcp, so we start
+            // by copying from srcp to dstp until they no longer overlap.
+            // The worst case is when dstp-src = 3 and copy length = 1. The
+            // first loop will issue these copy operations before stopping:
+            //
+            //   [-1, 14] -> [0, 15]
+            //   [-1, 14] -> [3, 18]
+            //   [-1, 14] -> [9, 24]
+            //
+            // But the copy had length 1, so it was only supposed to write
+            // to [0, 0]. But the last copy wrote to [9, 24], which is 24
+            // extra bytes in dst *beyond* the end of the copy, which is
+            // guaranteed by the conditional above.
+
+            // Save destination length here to avoid a reborrow UB violation
+            // under the Tree Borrows model.
+            let dest_len = self.dst.len();
+
+            let mut d = d;
+            let mut dstp = self.dst.as_mut_ptr().add(d);
+            let mut srcp = dstp.sub(offset);
+            loop {
+                debug_assert!(dstp >= srcp);
+                let diff = (dstp as usize) - (srcp as usize);
+                if diff >= 16 {
+                    break;
                 }
-                // At this point, `d` is likely wrong. We correct it before
-                // returning. It's correct value is `end`.
+                // srcp and dstp can overlap, so use ptr::copy.
+                debug_assert!(d + 16 <= dest_len);
+                ptr::copy(srcp, dstp, 16);
+                d += diff;
+                dstp = dstp.add(diff);
             }
-        } else {
-            if end > self.dst.len() {
-                return Err(Error::CopyWrite {
-                    len: len as u64,
-                    dst_len: (self.dst.len() - self.d) as u64,
-                });
+            while d < end {
+                ptr::copy_nonoverlapping(srcp, dstp, 16);
+                srcp = srcp.add(16);
+                dstp = dstp.add(16);
+                d += 16;
             }
+        } else {
             // Finally, the slow byte-by-byte case, which should only be used
             // for the last few bytes of decompression.
-            while self.d != end {
-                self.dst[self.d] = self.dst[self.d - offset];
-                self.d += 1;
-            }
+            return false;
+        }
+        true
+    }
+
+    fn write_copy(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> Result<()> {
+        // The copy may straddle the end of `dict` and the start of `dst`, or
+        // overlap with the bytes it produces, so write it one byte at a time.
+        for d in d..d + len {
+            self.dst[d] = if d < offset {
+                self.dict[self.dict.len() + d - offset]
+            } else {
+                self.dst[d - offset]
+            };
         }
-        self.d = end;
         Ok(())
     }
 }
 
+/// Growing is the output for decompressing onto the end of a `Vec`, which
+/// only grows as bytes are decompressed.
+///
+/// Unlike with `Buffer`, there's no need for room for every decompressed byte
+/// up front. Thus, a corrupt header claiming a huge length can't cause a huge
+/// allocation before the compressed bytes to back it up have been seen.
+struct Growing<'d> {
+    /// The total number of decompressed bytes expected.
+    len: usize,
+    /// The bytes decompressed so far.
+    dst: &'d mut Vec<u8>,
+}
+
+impl<'d> Output for Growing<'d> {
+    type Error = Error;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn reach(&self, d: usize) -> usize {
+        d
+    }
+
+    unsafe fn write_short_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+        len: usize,
+    ) -> Result<()> {
+        debug_assert_eq!(d, self.dst.len());
+        self.dst.extend_from_slice(&lit[..len]);
+        Ok(())
+    }
+
+    unsafe fn write_literal(&mut self, d: usize, lit: &[u8]) -> Result<()> {
+        debug_assert_eq!(d, self.dst.len());
+        self.dst.extend_from_slice(lit);
+        Ok(())
+    }
+
+    fn write_copy(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> Result<()> {
+        debug_assert_eq!(d, self.dst.len());
+        extend_copy(self.dst, offset, len);
+        Ok(())
+    }
+}
+
+/// Appends a copy of `len` bytes from `offset` bytes before the end of `buf`
+/// to `buf`.
+///
+/// Callers must ensure that `1 <= offset <= buf.len()`.
+fn extend_copy(buf: &mut Vec<u8>, offset: usize, len: usize) {
+    let start = buf.len() - offset;
+    if offset >= len {
+        buf.extend_from_within(start..start + len);
+    } else {
+        // The copy overlaps with the bytes it produces, so it has to be
+        // done one byte at a time.
+        for i in start..start + len {
+            let byte = buf[i];
+            buf.push(byte);
+        }
+    }
+}
+
+/// Counting wraps an output and counts the operations written to it.
+///
+/// This keeps counting out of the main decompression loop, so that it never
+/// slows down normal decompression.
+struct Counting<'a, O> {
+    /// The output that the decompressed bytes are written to.
+    out: O,
+    /// The statistics to update with every operation.
+    stats: &'a mut DecodeStats,
+}
+
+impl<'a, O: Output> Counting<'a, O> {
+    fn literal(&mut self, len: usize) {
+        self.stats.literals += 1;
+        self.stats.literal_bytes += len as u64;
+    }
+
+    fn copy(&mut self, len: usize) {
+        self.stats.copies += 1;
+        self.stats.copy_bytes += len as u64;
+    }
+}
+
+impl<'a, O: Output> Output for Counting<'a, O> {
+    type Error = O::Error;
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.out.len()
+    }
+
+    fn reach(&self, d: usize) -> usize {
+        self.out.reach(d)
+    }
+
+    #[inline(always)]
+    unsafe fn write_short_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+        len: usize,
+    ) -> result::Result<(), O::Error> {
+        self.literal(len);
+        self.out.write_short_literal(d, lit, len)
+    }
+
+    #[inline(always)]
+    unsafe fn write_literal(
+        &mut self,
+        d: usize,
+        lit: &[u8],
+    ) -> result::Result<(), O::Error> {
+        self.literal(lit.len());
+        self.out.write_literal(d, lit)
+    }
+
+    #[inline(always)]
+    unsafe fn write_copy_fast(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> bool {
+        let written = self.out.write_copy_fast(d, offset, len);
+        if written {
+            self.copy(len);
+        }
+        written
+    }
+
+    fn write_copy(
+        &mut self,
+        d: usize,
+        offset: usize,
+        len: usize,
+    ) -> result::Result<(), O::Error> {
+        self.copy(len);
+        self.out.write_copy(d, offset, len)
+    }
+}
+
+/// Discard is the output for validating compressed bytes. It only knows how
+/// many decompressed bytes to expect and throws away everything else.
+///
+/// This works because whether an operation is valid only depends on its
+/// length and offset, and never on the bytes that it produces.
+struct Discard {
+    /// The total number of decompressed bytes expected.
+    len: usize,
+}
+
+impl Output for Discard {
+    type Error = Error;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn reach(&self, d: usize) -> usize {
+        d
+    }
+
+    unsafe fn write_short_literal(
+        &mut self,
+        _d: usize,
+        _lit: &[u8],
+        _len: usize,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    unsafe fn write_literal(&mut self, _d: usize, _lit: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_copy(
+        &mut self,
+        _d: usize,
+        _offset: usize,
+        _len: usize,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Window is the output for handing decompressed bytes to a consumer in
+/// batches, e.g., to write them to an `io::Write` implementation.
+///
+/// Instead of a buffer big enough for all of the decompressed bytes, only a
+/// bounded sliding window of the most recent ones is kept, so that copies
+/// can refer back to them.
+struct Window<F> {
+    /// The total number of decompressed bytes expected.
+    len: usize,
+    /// The most recently decompressed bytes. After each flush, this contains
+    /// at least the last `WINDOW_SIZE` bytes (or all of them, if fewer have
+    /// been produced).
+    buf: Vec<u8>,
+    /// The number of bytes at the start of `buf` that have already been
+    /// given to `consume`.
+    consumed: usize,
+    /// Receives each batch of decompressed bytes, and may stop decompression
+    /// by returning `ControlFlow::Break`.
+    consume: F,
+}
+
+/// Stop is the reason that decompressing into a `Window` failed.
+enum Stop<B> {
+    /// The compressed bytes are invalid.
+    Error(Error),
+    /// The consumer of the decompressed bytes stopped decompression.
+    Break(B),
+}
+
+impl<B> From<Error> for Stop<B> {
+    fn from(err: Error) -> Stop<B> {
+        Stop::Error(err)
+    }
+}
+
+impl<B, F: FnMut(&[u8]) -> ControlFlow<B>> Window<F> {
+    /// Create a new window for a block that decompresses to `len` bytes.
+    fn new(len: usize, consume: F) -> Window<F> {
+        Window {
+            len,
+            buf: Vec::with_capacity(WINDOW_FLUSH_SIZE + WINDOW_SIZE),
+            consumed: 0,
+            consume,
+        }
+    }
+
+    /// Gives all unconsumed bytes in the window to `consume`, and then
+    /// shrinks the window to its last `WINDOW_SIZE` bytes.
+    fn flush(&mut self) -> result::Result<(), Stop<B>> {
+        if self.consumed < self.buf.len() {
+            self.consume_bytes(self.consumed..self.buf.len())?;
+        }
+        if self.buf.len() > WINDOW_SIZE {
+            self.buf.drain(..self.buf.len() - WINDOW_SIZE);
+        }
+        self.consumed = self.buf.len();
+        Ok(())
+    }
+
+    /// Flushes the window once enough unconsumed bytes have accumulated.
+    fn maybe_flush(&mut self) -> result::Result<(), Stop<B>> {
+        if self.buf.len() >= WINDOW_FLUSH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Gives `buf[range]` to `consume`.
+    fn consume_bytes(
+        &mut self,
+        range: ops::Range<usize>,
+    ) -> result::Result<(), Stop<B>> {
+        match (self.consume)(&self.buf[range]) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(b) => Err(Stop::Break(b)),
+        }
+    }
+}
+
+impl<B, F: FnMut(&[u8]) -> ControlFlow<B>> Output for Window<F> {
+    type Error = Stop<B>;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn reach(&self, _d: usize) -> usize {
+        // The window may hold more than `WINDOW_SIZE` bytes between flushes,
+        // but whether a copy is valid must not depend on when those happen.
+        cmp::min(self.buf.len(), WINDOW_SIZE)
+    }
+
+    unsafe fn write_short_literal(
+        &mut self,
+        _d: usize,
+        lit: &[u8],
+        len: usize,
+    ) -> result::Result<(), Stop<B>> {
+        self.buf.extend_from_slice(&lit[..len]);
+        self.maybe_flush()
+    }
+
+    unsafe fn write_literal(
+        &mut self,
+        _d: usize,
+        lit: &[u8],
+    ) -> result::Result<(), Stop<B>> {
+        if lit.len() <= WINDOW_SIZE {
+            self.buf.extend_from_slice(lit);
+            return self.maybe_flush();
+        }
+        // Big literals are passed straight through, so that the window never
+        // has to grow to hold them. Only their tail is needed for future
+        // copies.
+        self.flush()?;
+        if let ControlFlow::Break(b) = (self.consume)(lit) {
+            return Err(Stop::Break(b));
+        }
+        self.buf.clear();
+        self.buf.extend_from_slice(&lit[lit.len() - WINDOW_SIZE..]);
+        self.consumed = self.buf.len();
+        Ok(())
+    }
+
+    fn write_copy(
+        &mut self,
+        _d: usize,
+        offset: usize,
+        len: usize,
+    ) -> result::Result<(), Stop<B>> {
+        extend_copy(&mut self.buf, offset, len);
+        self.maybe_flush()
+    }
+}
+
+/// Searcher looks for a needle in a sequence of haystacks, as if they were
+/// concatenated.
+pub(crate) struct Searcher<'n> {
+    /// The bytes to search for.
+    needle: &'n [u8],
+    /// The last `needle.len() - 1` bytes searched (or all of them, if fewer
+    /// have been searched), so that matches straddling two haystacks are
+    /// found.
+    tail: Vec<u8>,
+    /// Whether the needle has been found.
+    found: bool,
+}
+
+impl<'n> Searcher<'n> {
+    /// Create a new searcher for `needle`. An empty needle is found before
+    /// any bytes are searched.
+    pub(crate) fn new(needle: &'n [u8]) -> Searcher<'n> {
+        Searcher {
+            needle,
+            tail: Vec::with_capacity(2 * needle.len()),
+            found: needle.is_empty(),
+        }
+    }
+
+    /// Returns true if the needle has been found.
+    pub(crate) fn found(&self) -> bool {
+        self.found
+    }
+
+    /// Searches `haystack`, which follows all bytes searched previously, and
+    /// returns true if the needle has been found so far.
+    pub(crate) fn search(&mut self, haystack: &[u8]) -> bool {
+        if self.found {
+            return true;
+        }
+        let keep = self.needle.len() - 1;
+        // Any match straddling the previous haystacks and this one is found
+        // in the tail followed by the first keep bytes of this haystack.
+        self.tail
+            .extend_from_slice(&haystack[..cmp::min(keep, haystack.len())]);
+        if contains(&self.tail, self.needle) || contains(haystack, self.needle)
+        {
+            self.found = true;
+            return true;
+        }
+        if haystack.len() >= keep {
+            self.tail.clear();
+            self.tail.extend_from_slice(&haystack[haystack.len() - keep..]);
+        } else {
+            // All of haystack was appended to the tail above.
+            let excess = self.tail.len().saturating_sub(keep);
+            self.tail.drain(..excess);
+        }
+        false
+    }
+}
+
+/// Returns true if `haystack` contains `needle`, which must not be empty.
+///
+/// Candidate positions are found by scanning for the first byte of `needle`,
+/// so that the rest of `needle` is only compared where it can match.
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    let (&first, rest) = needle.split_first().expect("non-empty needle");
+    if haystack.len() < needle.len() {
+        return false;
+    }
+    // The last position where a match can start.
+    let last = haystack.len() - needle.len();
+    let mut at = 0;
+    while let Some(i) = haystack[at..=last].iter().position(|&b| b == first) {
+        at += i;
+        if &haystack[at + 1..at + needle.len()] == rest {
+            return true;
+        }
+        if at == last {
+            return false;
+        }
+        at += 1;
+    }
+    false
+}
+
 /// Header represents the single varint that starts every Snappy compressed
 /// block.
 #[derive(Debug)]
@@ -366,12 +1463,29 @@ impl Header {
         }
         if decompress_len > MAX_INPUT_SIZE {
             return Err(Error::TooBig {
-                given: decompress_len as u64,
+                given: decompress_len,
                 max: MAX_INPUT_SIZE,
             });
         }
         Ok(Header { len: header_len, decompress_len: decompress_len as usize })
     }
+
+    /// Reads the varint header from a complete compressed block.
+    ///
+    /// In addition to the checks done by `read`, this rejects a header that
+    /// reports zero decompressed bytes but is followed by more bytes, since
+    /// the block can't possibly be valid.
+    #[inline(always)]
+    fn read_block(input: &[u8]) -> Result<Header> {
+        let hdr = Header::read(input)?;
+        if hdr.decompress_len == 0 && input.len() > hdr.len {
+            return Err(Error::HeaderMismatch {
+                expected_len: 0,
+                got_len: (input.len() - hdr.len) as u64,
+            });
+        }
+        Ok(hdr)
+    }
 }
 
 /// A lookup table for quickly computing the various attributes derived from
@@ -96,6 +96,10 @@ pub enum Error {
     /// actual decompressed bytes. In this error case, the number of actual
     /// decompressed bytes is always less than the number reported in the
     /// header.
+    ///
+    /// The one exception is a header that reports zero decompressed bytes
+    /// but is followed by more compressed bytes. In that case, `got_len` is
+    /// the number of compressed bytes following the header.
     HeaderMismatch {
         /// The total number of decompressed bytes expected (i.e., the header
         /// value).
@@ -143,6 +147,10 @@ pub enum Error {
     /// This error occurs when a stream header chunk type was expected but got
     /// a different chunk type.
     /// This error only occurs when reading a Snappy frame formatted stream.
+    ///
+    /// A common cause is compressing with `raw::Encoder` and then
+    /// decompressing with a frame decoder, so the message of this error
+    /// suggests using `raw::Decoder` instead.
     StreamHeader {
         /// The chunk type byte that was read.
         byte: u8,
@@ -163,6 +171,10 @@ pub enum Error {
     /// This error occurs when trying to read a chunk with an unexpected or
     /// incorrect length when reading a Snappy frame formatted stream.
     /// This error only occurs when reading a Snappy frame formatted stream.
+    ///
+    /// This includes a compressed chunk whose block claims to decompress to
+    /// more than the maximum block size of 65536 bytes, in which case `len`
+    /// is the claimed decompressed length.
     UnsupportedChunkLength {
         /// The length of the chunk encountered.
         len: u64,
@@ -188,6 +200,8 @@ impl From<Error> for io::Error {
 impl Eq for Error {}
 
 impl PartialEq for Error {
+    // Every arm matches through a reference, so keep them uniform.
+    #[allow(clippy::needless_borrowed_reference)]
     fn eq(&self, other: &Error) -> bool {
         use self::Error::*;
         match (self, other) {
@@ -298,19 +312,23 @@ impl fmt::Display for Error {
             Error::StreamHeader { byte } => write!(
                 f,
                 "snappy: corrupt input (expected stream header but \
-                         got unexpected chunk type byte {})",
+                         got unexpected chunk type byte {}; if the input \
+                         is in the raw Snappy format, use raw::Decoder)",
                 byte
             ),
             Error::StreamHeaderMismatch { ref bytes } => write!(
                 f,
                 "snappy: corrupt input (expected sNaPpY stream \
                          header but got {})",
-                escape(&**bytes)
+                escape(bytes)
             ),
             Error::UnsupportedChunkType { byte } => write!(
                 f,
-                "snappy: corrupt input (unsupported chunk type: {})",
-                byte
+                "snappy: corrupt input (unsupported chunk type: {} \
+                         ({:#04X}), {})",
+                byte,
+                byte,
+                chunk_type_description(byte)
             ),
             Error::UnsupportedChunkLength { len, header: false } => write!(
                 f,
@@ -334,6 +352,20 @@ impl fmt::Display for Error {
     }
 }
 
+/// Describes what the frame format defines the chunk type `byte` to be. For
+/// a reserved chunk type, this is the range it falls in, which determines
+/// whether a decoder must reject it or skip it.
+fn chunk_type_description(byte: u8) -> &'static str {
+    match byte {
+        0x00 => "compressed data",
+        0x01 => "uncompressed data",
+        0x02..=0x7F => "in the reserved unskippable range 0x02-0x7F",
+        0x80..=0xFD => "in the reserved skippable range 0x80-0xFD",
+        0xFE => "padding",
+        0xFF => "stream identifier",
+    }
+}
+
 fn escape(bytes: &[u8]) -> String {
     use std::ascii::escape_default;
     bytes.iter().flat_map(|&b| escape_default(b)).map(|b| b as char).collect()
@@ -0,0 +1,135 @@
+/*!
+This module provides a way to guess which Snappy format a sequence of bytes
+is encoded with, and convenience functions for compressing or decompressing a
+buffer in either format.
+
+The Snappy frame format always starts with a fixed stream identifier, so
+detecting it is reliable. The raw Snappy format, however, has no magic bytes.
+Detecting it is therefore a heuristic: the bytes are only checked to be
+consistent with a raw Snappy block, which random data may occasionally be.
+*/
+
+use std::io;
+
+use crate::bytes;
+use crate::compress::{max_compress_len, Encoder};
+use crate::decompress::Decoder;
+use crate::frame::STREAM_IDENTIFIER;
+use crate::{read, write, MAX_INPUT_SIZE};
+
+/// The largest number of decompressed bytes that a single byte of a raw
+/// Snappy block can produce.
+///
+/// The most efficient operation in the raw format is a copy 2 operation,
+/// which encodes a copy of length 64 in 3 bytes. We round up to leave a
+/// little slack.
+const MAX_EXPANSION: u64 = 22;
+
+/// The Snappy formats that this crate supports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// The raw Snappy format, as produced by
+    /// [`raw::Encoder`](../raw/struct.Encoder.html).
+    Raw,
+    /// The Snappy frame format, as produced by
+    /// [`read::FrameEncoder`](../read/struct.FrameEncoder.html) or
+    /// [`write::FrameEncoder`](../write/struct.FrameEncoder.html).
+    Frame,
+}
+
+/// Compresses all bytes in `input` using the given Snappy format, and returns
+/// the compressed bytes.
+///
+/// This is a convenience function for compressing a buffer that is already
+/// in memory. With `Format::Raw`, this is equivalent to
+/// [`raw::Encoder::compress_vec`](crate::raw::Encoder::compress_vec).
+/// With `Format::Frame`, this is equivalent to
+/// [`write::compress_into_vec`].
+///
+/// Errors are returned as a `std::io::Error`, since that is what the frame
+/// format uses. An error from the raw format can be recovered by
+/// downcasting it to a [`snap::Error`](crate::Error).
+///
+/// # Example
+///
+/// ```
+/// use snap::Format;
+///
+/// let input = b"Hello, Hello, Hello!";
+/// for &format in &[Format::Raw, Format::Frame] {
+///     let compressed = snap::compress(input, format).unwrap();
+///     let decompressed = snap::decompress(&compressed, format).unwrap();
+///     assert_eq!(&input[..], &*decompressed);
+/// }
+/// ```
+pub fn compress(input: &[u8], format: Format) -> io::Result<Vec<u8>> {
+    match format {
+        Format::Raw => Ok(Encoder::new().compress_vec(input)?),
+        Format::Frame => {
+            let mut output = vec![];
+            write::compress_into_vec(input, &mut output)?;
+            Ok(output)
+        }
+    }
+}
+
+/// Decompresses all bytes in `input`, which must be encoded using the given
+/// Snappy format, and returns the decompressed bytes.
+///
+/// With `Format::Raw`, this is equivalent to
+/// [`raw::Decoder::decompress_vec`](crate::raw::Decoder::decompress_vec).
+/// With `Format::Frame`, this is equivalent to
+/// [`read::decompress_slice`]. When the
+/// format isn't known in advance,
+/// [`detect_format`] may be used to guess it.
+///
+/// Errors are returned as a `std::io::Error`, since that is what the frame
+/// format uses. Errors caused by corrupt input can be recovered by
+/// downcasting them to a [`snap::Error`](crate::Error).
+pub fn decompress(input: &[u8], format: Format) -> io::Result<Vec<u8>> {
+    match format {
+        Format::Raw => Ok(Decoder::new().decompress_vec(input)?),
+        Format::Frame => read::decompress_slice(input),
+    }
+}
+
+/// Guesses the Snappy format of the given bytes.
+///
+/// If `data` starts with the Snappy frame format stream identifier, then
+/// `Some(Format::Frame)` is returned.
+///
+/// Otherwise, if `data` starts with a valid raw Snappy header whose
+/// decompressed length is plausible given the length of `data`, then
+/// `Some(Format::Raw)` is returned. Since the raw format has no magic bytes,
+/// this is only a heuristic. A return value of `Some(Format::Raw)` does not
+/// guarantee that `data` will decompress successfully.
+///
+/// In all other cases, `None` is returned.
+pub fn detect_format(data: &[u8]) -> Option<Format> {
+    if data.starts_with(STREAM_IDENTIFIER) {
+        return Some(Format::Frame);
+    }
+    let (decompress_len, header_len) = bytes::read_varu64(data);
+    if header_len == 0 || decompress_len > MAX_INPUT_SIZE {
+        return None;
+    }
+    let body = &data[header_len..];
+    if decompress_len == 0 {
+        // The only valid encoding of an empty input is a lone header.
+        return if body.is_empty() { Some(Format::Raw) } else { None };
+    }
+    // The compressed bytes can't be bigger than what the compressor could
+    // possibly emit, and they can't be so small that they would have to
+    // expand beyond what the format permits.
+    if data.len() > max_compress_len(decompress_len as usize)
+        || (body.len() as u64).saturating_mul(MAX_EXPANSION) < decompress_len
+    {
+        return None;
+    }
+    // The first operation must always be a literal, since there is nothing
+    // to copy from yet.
+    if body[0] & 0b11 != 0 {
+        return None;
+    }
+    Some(Format::Raw)
+}
@@ -1,36 +1,80 @@
+/*!
+This module provides helpers for working with the
+[Snappy frame format](https://github.com/google/snappy/blob/master/framing_format.txt)
+directly.
+
+Most users should not need this module. The
+[`read`](../read/index.html) and [`write`](../write/index.html) modules
+already encode and decode the frame format.
+
+For protocols that drive their own I/O, e.g., from an event loop,
+[`frame::Decoder`](struct.Decoder.html) and
+[`frame::Encoder`](struct.Encoder.html) decode and encode a stream from bytes
+handed to them by the caller, without going through `std::io::Read` or
+`std::io::Write`.
+*/
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+
 use crate::bytes;
-use crate::compress::{max_compress_len, Encoder};
+use crate::compress::{
+    max_compress_len, CompressOutcome, Encoder as RawEncoder,
+};
 use crate::crc32::CheckSummer;
+use crate::decompress::{decompress_len, Decoder as RawDecoder};
 use crate::error::Error;
 use crate::MAX_BLOCK_SIZE;
 
 /// The maximum chunk of compressed bytes that can be processed at one time.
-///
-/// This is computed via `max_compress_len(MAX_BLOCK_SIZE)`.
-///
-/// TODO(ag): Replace with const fn once they support nominal branching.
-pub const MAX_COMPRESS_BLOCK_SIZE: usize = 76490;
+pub(crate) const MAX_COMPRESS_BLOCK_SIZE: usize =
+    max_compress_len(MAX_BLOCK_SIZE);
+
+// This used to be hardcoded, and other implementations of the frame format
+// may still rely on it. So make sure it never changes by accident.
+const _: () = assert!(MAX_COMPRESS_BLOCK_SIZE == 76490);
 
 /// The special magic string that starts any stream.
 ///
 /// This may appear more than once in a stream in order to support easy
 /// concatenation of files compressed in the Snappy frame format.
-pub const STREAM_IDENTIFIER: &'static [u8] = b"\xFF\x06\x00\x00sNaPpY";
+pub(crate) const STREAM_IDENTIFIER: &[u8] = b"\xFF\x06\x00\x00sNaPpY";
 
 /// The body of the special stream identifier.
-pub const STREAM_BODY: &'static [u8] = b"sNaPpY";
+pub(crate) const STREAM_BODY: &[u8] = b"sNaPpY";
+
+/// The length of a snappy chunk type (1 byte) and packet length (3 bytes).
+pub(crate) const CHUNK_HEADER_SIZE: usize = 4;
 
 /// The length of a snappy chunk type (1 byte), packet length (3 bytes)
 /// and CRC field (4 bytes). This is technically the chunk header _plus_
 /// the CRC present in most chunks.
-pub const CHUNK_HEADER_AND_CRC_SIZE: usize = 8;
+pub(crate) const CHUNK_HEADER_AND_CRC_SIZE: usize = 8;
+
+/// Returns the maximum size of a Snappy frame formatted stream produced by
+/// compressing `input_len` bytes in blocks of at most `MAX_BLOCK_SIZE`.
+///
+/// Since a chunk is only stored compressed when doing so saves space, the
+/// payload of every chunk is never bigger than its uncompressed bytes.
+pub(crate) fn max_frame_len(input_len: usize) -> usize {
+    let chunks = (input_len + MAX_BLOCK_SIZE - 1) / MAX_BLOCK_SIZE;
+    STREAM_IDENTIFIER.len() + (chunks * CHUNK_HEADER_AND_CRC_SIZE) + input_len
+}
 
 /// An enumeration describing each of the 4 main chunk types.
+///
+/// All other chunk types are reserved. Types `0x02` through `0x7F` are
+/// unskippable, while types `0x80` through `0xFD` are skippable.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ChunkType {
+    /// The stream identifier, which must start every stream.
     Stream = 0xFF,
+    /// A chunk containing a block in the raw Snappy format.
     Compressed = 0x00,
+    /// A chunk containing uncompressed bytes.
     Uncompressed = 0x01,
+    /// A chunk that decoders must skip.
     Padding = 0xFE,
 }
 
@@ -47,6 +91,304 @@ impl ChunkType {
             b => Err(b),
         }
     }
+
+    /// Returns the byte that represents this chunk type in a stream.
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl TryFrom<u8> for ChunkType {
+    type Error = u8;
+
+    fn try_from(b: u8) -> Result<ChunkType, u8> {
+        ChunkType::from_u8(b)
+    }
+}
+
+/// Returns true if checksums in the Snappy frame format are computed with
+/// hardware acceleration on the current CPU.
+///
+/// This is always false when this crate is compiled with the `no-simd`
+/// feature, which forces portable code paths everywhere.
+pub fn crc32_hardware_accelerated() -> bool {
+    CheckSummer::new().is_hardware_accelerated()
+}
+
+/// Decodes the chunk at the start of `input`, and returns its decompressed
+/// bytes along with the number of bytes of `input` that the chunk occupies.
+///
+/// This is useful for decoding individual chunks of a stream, e.g., in a
+/// storage engine that indexes them. The chunk is parsed and checked exactly
+/// like [`read::FrameDecoder`](../read/struct.FrameDecoder.html) does,
+/// except that it need not be preceded by a stream identifier. The checksum
+/// of a chunk containing data is only checked if `verify_crc` is true.
+///
+/// Chunks that don't contain any data, i.e., stream identifiers, padding and
+/// reserved but skippable chunks, are decoded as no bytes. Any bytes in
+/// `input` following the chunk are ignored, so consecutive chunks can be
+/// decoded by advancing `input` by the number of bytes consumed each time.
+///
+/// # Errors
+///
+/// This returns an error under the same circumstances that reading the
+/// chunk with a `read::FrameDecoder` does. In particular, if `input` doesn't
+/// contain the entire chunk, then an error with kind
+/// `std::io::ErrorKind::UnexpectedEof` is returned.
+///
+/// # Example
+///
+/// ```
+/// let compressed = snap::write::FrameEncoder::compress_all(b"hello");
+/// let (data, n) = snap::frame::decompress_chunk(&compressed, true).unwrap();
+/// assert!(data.is_empty());
+/// let (data, _) =
+///     snap::frame::decompress_chunk(&compressed[n..], true).unwrap();
+/// assert_eq!(b"hello", &*data);
+/// ```
+pub fn decompress_chunk(
+    input: &[u8],
+    verify_crc: bool,
+) -> io::Result<(Vec<u8>, usize)> {
+    let mut dst = vec![];
+    match decode_chunk(
+        &mut RawDecoder::new(),
+        CheckSummer::new(),
+        input,
+        &mut dst,
+        &mut true,
+        if verify_crc { VerifyCrc::Yes } else { VerifyCrc::No },
+    )? {
+        Some((data, consumed)) => Ok((data.to_vec(), consumed)),
+        None => Err(truncated_chunk_error(input)),
+    }
+}
+
+/// Returns the error to report when `input` ends before the chunk that it
+/// starts is complete.
+///
+/// When `input` is too short to even contain a chunk header, then the
+/// length reported is that of the header.
+pub(crate) fn truncated_chunk_error(input: &[u8]) -> io::Error {
+    let (len, got) = if input.len() < CHUNK_HEADER_SIZE {
+        (CHUNK_HEADER_SIZE, input.len())
+    } else {
+        let len = bytes::read_u24_le(&input[1..]) as usize;
+        (len, input.len() - CHUNK_HEADER_SIZE)
+    };
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!(
+            "snappy: corrupt input (chunk truncated; \
+             expected {} bytes but got {})",
+            len, got
+        ),
+    )
+}
+
+/// Validates the chunk header in `header[0..4]` and returns the length of
+/// the chunk that follows it.
+///
+/// This checks everything about a chunk that can be checked without reading
+/// its body. `read_stream_ident` records whether a stream identifier has
+/// been seen, since one must precede all other chunks.
+pub(crate) fn check_chunk_header(
+    header: &[u8],
+    read_stream_ident: &mut bool,
+) -> Result<usize, Error> {
+    macro_rules! fail {
+        ($err:expr) => {
+            return Err($err)
+        };
+    }
+    let ty = ChunkType::from_u8(header[0]);
+    if !*read_stream_ident {
+        if ty != Ok(ChunkType::Stream) {
+            fail!(Error::StreamHeader { byte: header[0] });
+        }
+        *read_stream_ident = true;
+    }
+    let len64 = bytes::read_u24_le(&header[1..]) as u64;
+    if len64 > MAX_COMPRESS_BLOCK_SIZE as u64 {
+        fail!(Error::UnsupportedChunkLength { len: len64, header: false });
+    }
+    let len = len64 as usize;
+    match ty {
+        Err(b) if (0x02..=0x7F).contains(&b) => {
+            // Spec says that chunk types 0x02-0x7F are reserved and
+            // conformant decoders must return an error.
+            fail!(Error::UnsupportedChunkType { byte: b });
+        }
+        Err(b) if (0x80..=0xFD).contains(&b) => {
+            // Spec says that chunk types 0x80-0xFD are reserved but
+            // skippable.
+        }
+        Err(b) => {
+            // Can never happen. 0x02-0x7F and 0x80-0xFD are handled
+            // above in the error case. That leaves 0x00, 0x01, 0xFE
+            // and 0xFF, each of which correspond to one of the four
+            // defined chunk types.
+            unreachable!("BUG: unhandled chunk type: {}", b);
+        }
+        Ok(ChunkType::Padding) => {}
+        Ok(ChunkType::Stream) => {
+            if len != STREAM_BODY.len() {
+                fail!(Error::UnsupportedChunkLength {
+                    len: len64,
+                    header: true,
+                })
+            }
+        }
+        Ok(ChunkType::Uncompressed) => {
+            if len < 4 {
+                fail!(Error::UnsupportedChunkLength {
+                    len: len as u64,
+                    header: false,
+                });
+            }
+            if len - 4 > MAX_BLOCK_SIZE {
+                fail!(Error::UnsupportedChunkLength {
+                    len: (len - 4) as u64,
+                    header: false,
+                });
+            }
+        }
+        Ok(ChunkType::Compressed) => {
+            if len < 4 {
+                fail!(Error::UnsupportedChunkLength {
+                    len: len as u64,
+                    header: false,
+                });
+            }
+        }
+    }
+    Ok(len)
+}
+
+/// How the checksum of a chunk containing data is checked when decoding it.
+pub(crate) enum VerifyCrc<'a> {
+    /// The checksum isn't checked at all.
+    No,
+    /// A checksum mismatch is an error.
+    Yes,
+    /// A checksum mismatch is passed to the callback, along with the type of
+    /// the chunk, the checksum read from the stream and the computed
+    /// checksum. The callback returns whether to use the chunk's bytes
+    /// anyway instead of failing.
+    Callback(&'a mut dyn FnMut(ChunkType, u32, u32) -> bool),
+}
+
+/// Decodes the chunk at the start of `input`, and returns its decompressed
+/// bytes along with the number of bytes of `input` that the chunk occupies.
+/// If `input` doesn't contain the entire chunk, then `None` is returned.
+///
+/// The decompressed bytes point into either `input` (for uncompressed
+/// chunks) or `dst` (for compressed chunks), which is grown as needed.
+/// `read_stream_ident` records whether a stream identifier has been seen,
+/// as required by `check_chunk_header`. It's only updated once the entire
+/// chunk is available.
+pub(crate) fn decode_chunk<'a>(
+    dec: &mut RawDecoder,
+    checksummer: CheckSummer,
+    input: &'a [u8],
+    dst: &'a mut Vec<u8>,
+    read_stream_ident: &mut bool,
+    verify: VerifyCrc<'_>,
+) -> Result<Option<(&'a [u8], usize)>, Error> {
+    if input.len() < CHUNK_HEADER_SIZE {
+        return Ok(None);
+    }
+    let mut saw_stream_ident = *read_stream_ident;
+    let len = check_chunk_header(input, &mut saw_stream_ident)?;
+    if input.len() - CHUNK_HEADER_SIZE < len {
+        return Ok(None);
+    }
+    *read_stream_ident = saw_stream_ident;
+    let chunk = &input[..CHUNK_HEADER_SIZE + len];
+    let data = decode_chunk_body(
+        dec,
+        checksummer,
+        chunk,
+        dst,
+        MAX_BLOCK_SIZE,
+        false,
+        verify,
+    )?;
+    Ok(Some((data, chunk.len())))
+}
+
+/// Decodes the body of `chunk`, whose header must have already been checked
+/// by `check_chunk_header`, and returns its decompressed bytes. Chunks that
+/// don't contain any data, i.e., stream identifiers, padding and reserved
+/// but skippable chunks, are decoded as no bytes.
+///
+/// Chunks whose data exceeds `max_block` bytes are rejected. When a
+/// compressed chunk doesn't fit in `dst`, then `dst` is grown to `max_block`
+/// bytes.
+///
+/// If `always_use_dst` is set to false, the return value may point into
+/// either `chunk` (for uncompressed chunks) or into `dst` (for compressed
+/// chunks). If `always_use_dst` is true, the data will always be at the
+/// start of `dst`.
+pub(crate) fn decode_chunk_body<'a>(
+    dec: &mut RawDecoder,
+    checksummer: CheckSummer,
+    chunk: &'a [u8],
+    dst: &'a mut Vec<u8>,
+    max_block: usize,
+    always_use_dst: bool,
+    verify: VerifyCrc<'_>,
+) -> Result<&'a [u8], Error> {
+    let body = &chunk[CHUNK_HEADER_SIZE..];
+    let (ty, dn) = match ChunkType::from_u8(chunk[0]) {
+        Ok(ChunkType::Stream) if body != STREAM_BODY => {
+            return Err(Error::StreamHeaderMismatch { bytes: body.to_vec() });
+        }
+        Ok(ty @ ChunkType::Uncompressed) => (ty, body.len() - 4),
+        Ok(ty @ ChunkType::Compressed) => (ty, decompress_len(&body[4..])?),
+        // Stream identifiers, padding and reserved but skippable chunks
+        // are just dropped.
+        _ => return Ok(&[]),
+    };
+    // Every chunk holds a single block, so this also rejects compressed
+    // chunks whose block claims more than the maximum block size.
+    if dn > max_block {
+        return Err(Error::UnsupportedChunkLength {
+            len: dn as u64,
+            header: false,
+        });
+    }
+    let compressed = ty == ChunkType::Compressed;
+    if (compressed || always_use_dst) && dn > dst.len() {
+        dst.resize(max_block, 0);
+    }
+    let data: &[u8] = if compressed {
+        dec.decompress(&body[4..], &mut dst[..dn])?;
+        &dst[..dn]
+    } else if always_use_dst {
+        dst[..dn].copy_from_slice(&body[4..]);
+        &dst[..dn]
+    } else {
+        &body[4..]
+    };
+    let expected_sum = bytes::read_u32_le(body);
+    let got_sum = match verify {
+        VerifyCrc::No => return Ok(data),
+        _ => checksummer.crc32c_masked(data),
+    };
+    if expected_sum != got_sum {
+        let ok = match verify {
+            VerifyCrc::Callback(f) => f(ty, expected_sum, got_sum),
+            _ => false,
+        };
+        if !ok {
+            return Err(Error::Checksum {
+                expected: expected_sum,
+                got: got_sum,
+            });
+        }
+    }
+    Ok(data)
 }
 
 /// Compress a single frame (or decide to pass it through uncompressed). This
@@ -59,8 +401,8 @@ impl ChunkType {
 /// compress). If `always_use_dst` is true, the data will always be in `dst`.
 /// This is a bit weird, but because of Rust's ownership rules, it's easiest
 /// for a single function to always be in charge of writing to `dst`.
-pub fn compress_frame<'a>(
-    enc: &mut Encoder,
+pub(crate) fn compress_frame<'a>(
+    enc: &mut RawEncoder,
     checksummer: CheckSummer,
     src: &'a [u8],
     dst_chunk_header: &mut [u8],
@@ -79,16 +421,14 @@ pub fn compress_frame<'a>(
     // write uncompressed bytes instead. Since our buffer is at most
     // MAX_BLOCK_SIZE and our dst buffer has size
     // max_compress_len(MAX_BLOCK_SIZE), we have enough space.
-    let compress_len = enc.compress(src, dst)?;
-    let (chunk_type, chunk_len) =
-        // We add 4 to the chunk_len because of the checksum.
-        if compress_len >= src.len() - (src.len() / 8) {
-            (ChunkType::Uncompressed, 4 + src.len())
-        } else {
-            (ChunkType::Compressed, 4 + compress_len)
-        };
+    let (chunk_type, compress_len) = match enc.compress_or_store(src, dst)? {
+        CompressOutcome::Compressed(n) => (ChunkType::Compressed, n),
+        CompressOutcome::Store => (ChunkType::Uncompressed, src.len()),
+    };
+    // We add 4 to the chunk_len because of the checksum.
+    let chunk_len = 4 + compress_len;
 
-    dst_chunk_header[0] = chunk_type as u8;
+    dst_chunk_header[0] = chunk_type.as_u8();
     bytes::write_u24_le(chunk_len as u32, &mut dst_chunk_header[1..]);
     bytes::write_u32_le(checksum, &mut dst_chunk_header[4..]);
 
@@ -102,3 +442,298 @@ pub fn compress_frame<'a>(
         Ok(src)
     }
 }
+
+/// The state needed to compress blocks into chunks, which is shared by
+/// `Encoder` and `write::EncoderPool`.
+#[derive(Debug)]
+pub(crate) struct EncoderState {
+    /// The encoder that does the actual block based compression.
+    enc: RawEncoder,
+    /// The checksummer for the uncompressed bytes of each chunk.
+    checksummer: CheckSummer,
+    /// The compressed bytes buffer for a single block.
+    dst: Vec<u8>,
+    /// Space for writing the header of a chunk.
+    chunk_header: [u8; 8],
+}
+
+impl EncoderState {
+    /// Create new state for compressing blocks of at most `MAX_BLOCK_SIZE`.
+    pub(crate) fn new() -> EncoderState {
+        EncoderState {
+            enc: RawEncoder::new(),
+            checksummer: CheckSummer::new(),
+            dst: vec![0; MAX_COMPRESS_BLOCK_SIZE],
+            chunk_header: [0; CHUNK_HEADER_AND_CRC_SIZE],
+        }
+    }
+
+    /// Compresses `src` and appends it to `output` as a single chunk. The
+    /// stream identifier must have already been appended.
+    pub(crate) fn push_chunk(
+        &mut self,
+        src: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let frame_data = compress_frame(
+            &mut self.enc,
+            self.checksummer,
+            src,
+            &mut self.chunk_header,
+            &mut self.dst,
+            false,
+        )?;
+        output.extend_from_slice(&self.chunk_header);
+        output.extend_from_slice(frame_data);
+        Ok(())
+    }
+}
+
+/// A decoder for the Snappy frame format that performs no I/O.
+///
+/// Compressed bytes are handed to this decoder with
+/// [`push`](#method.push) as they arrive, in pieces of any size, and the
+/// decompressed bytes of every complete chunk received so far are retrieved
+/// with [`pull`](#method.pull). This gives the caller full control over when
+/// and how bytes are read, e.g., in a single threaded event loop. Chunks are
+/// parsed and checked exactly like
+/// [`read::FrameDecoder`](../read/struct.FrameDecoder.html) does.
+///
+/// # Example
+///
+/// ```
+/// let compressed = snap::write::FrameEncoder::compress_all(b"hello");
+///
+/// let mut dec = snap::frame::Decoder::new();
+/// let mut out = vec![];
+/// for piece in compressed.chunks(3) {
+///     dec.push(piece);
+///     dec.pull(&mut out).unwrap();
+/// }
+/// assert_eq!(b"hello", &*out);
+/// assert_eq!(0, dec.pending_input());
+/// ```
+pub struct Decoder {
+    /// A raw Snappy decoder that we reuse for every compressed chunk.
+    dec: RawDecoder,
+    /// The checksummer for the uncompressed bytes of each chunk.
+    checksummer: CheckSummer,
+    /// Compressed bytes that have been pushed but not decoded yet start at
+    /// `src[srcs..]`.
+    src: Vec<u8>,
+    /// The start of the bytes in `src` that haven't been decoded yet.
+    srcs: usize,
+    /// Space for decompressing the data of a compressed chunk.
+    dst: Vec<u8>,
+    /// Whether a stream identifier has been decoded.
+    read_stream_ident: bool,
+}
+
+impl Decoder {
+    /// Create a new decoder for a Snappy frame formatted stream.
+    pub fn new() -> Decoder {
+        Decoder {
+            dec: RawDecoder::new(),
+            checksummer: CheckSummer::new(),
+            src: vec![],
+            srcs: 0,
+            dst: vec![],
+            read_stream_ident: false,
+        }
+    }
+
+    /// Adds `input` to the compressed bytes to be decoded.
+    ///
+    /// Nothing is decoded until [`pull`](#method.pull) is called.
+    pub fn push(&mut self, input: &[u8]) {
+        if self.srcs > 0 {
+            self.src.drain(..self.srcs);
+            self.srcs = 0;
+        }
+        self.src.extend_from_slice(input);
+    }
+
+    /// Decodes every complete chunk pushed so far and appends the
+    /// decompressed bytes to `out`. The number of bytes appended is
+    /// returned.
+    ///
+    /// Bytes of an incomplete chunk at the end are kept until more are
+    /// pushed, so this returns `0` when no chunk containing data is complete
+    /// yet.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error under the same circumstances that reading the
+    /// stream with a `read::FrameDecoder` does, except that it never reports
+    /// a truncated stream, since more bytes may yet be pushed. Use
+    /// [`pending_input`](#method.pending_input) to check that a stream is
+    /// complete once all of it has been pushed. Bytes decompressed before an
+    /// error is found are still appended to `out`.
+    pub fn pull(&mut self, out: &mut Vec<u8>) -> Result<usize, Error> {
+        let start = out.len();
+        while let Some((data, consumed)) = decode_chunk(
+            &mut self.dec,
+            self.checksummer,
+            &self.src[self.srcs..],
+            &mut self.dst,
+            &mut self.read_stream_ident,
+            VerifyCrc::Yes,
+        )? {
+            out.extend_from_slice(data);
+            self.srcs += consumed;
+        }
+        Ok(out.len() - start)
+    }
+
+    /// Returns the number of compressed bytes pushed to this decoder that
+    /// have not been decoded yet, because they don't form a complete chunk.
+    ///
+    /// Once an entire stream has been pushed and pulled, this is `0` if and
+    /// only if the stream is complete.
+    pub fn pending_input(&self) -> usize {
+        self.src.len() - self.srcs
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("dec", &self.dec)
+            .field("checksummer", &self.checksummer)
+            .field("src", &"[...]")
+            .field("srcs", &self.srcs)
+            .field("dst", &"[...]")
+            .field("read_stream_ident", &self.read_stream_ident)
+            .finish()
+    }
+}
+
+/// An encoder for the Snappy frame format that performs no I/O.
+///
+/// Uncompressed bytes are handed to this encoder with
+/// [`push`](#method.push), in pieces of any size, and are compressed in
+/// blocks of `65536` bytes. The chunks of every complete block pushed so far
+/// are retrieved with [`pull`](#method.pull), and the last, partial block is
+/// retrieved with [`finish`](#method.finish). The stream identifier is
+/// emitted along with the first chunk. This gives the caller full control
+/// over when and how bytes are written, e.g., in a single threaded event
+/// loop. The stream produced is identical to the one written by
+/// [`write::FrameEncoder`](../write/struct.FrameEncoder.html) when given the
+/// same bytes and flushed at the same points.
+///
+/// # Example
+///
+/// ```
+/// let mut enc = snap::frame::Encoder::new();
+/// let mut out = vec![];
+/// for piece in b"hello, world".chunks(5) {
+///     enc.push(piece);
+///     enc.pull(&mut out);
+/// }
+/// enc.finish(&mut out);
+///
+/// let got = snap::read::FrameDecoder::decompress_all(&out).unwrap();
+/// assert_eq!(b"hello, world", &*got);
+/// ```
+pub struct Encoder {
+    /// The state used to compress each block.
+    state: EncoderState,
+    /// Uncompressed bytes that have been pushed but not compressed yet start
+    /// at `src[srcs..]`.
+    src: Vec<u8>,
+    /// The start of the bytes in `src` that haven't been compressed yet.
+    srcs: usize,
+    /// Whether the stream identifier has been emitted yet.
+    wrote_stream_ident: bool,
+}
+
+impl Encoder {
+    /// Create a new encoder for a Snappy frame formatted stream.
+    pub fn new() -> Encoder {
+        Encoder {
+            state: EncoderState::new(),
+            src: vec![],
+            srcs: 0,
+            wrote_stream_ident: false,
+        }
+    }
+
+    /// Adds `input` to the uncompressed bytes to be encoded.
+    ///
+    /// Nothing is compressed until [`pull`](#method.pull) or
+    /// [`finish`](#method.finish) is called.
+    pub fn push(&mut self, input: &[u8]) {
+        if self.srcs > 0 {
+            self.src.drain(..self.srcs);
+            self.srcs = 0;
+        }
+        self.src.extend_from_slice(input);
+    }
+
+    /// Compresses every complete block pushed so far and appends the
+    /// resulting chunks to `out`. The number of bytes appended is returned.
+    ///
+    /// Bytes that don't make up a complete block are kept until more are
+    /// pushed or `finish` is called.
+    pub fn pull(&mut self, out: &mut Vec<u8>) -> usize {
+        let start = out.len();
+        while self.src.len() - self.srcs >= MAX_BLOCK_SIZE {
+            let end = self.srcs + MAX_BLOCK_SIZE;
+            self.push_chunk(self.srcs, end, out);
+            self.srcs = end;
+        }
+        out.len() - start
+    }
+
+    /// Compresses all bytes pushed so far, including a final partial block,
+    /// and appends the resulting chunks to `out`. The number of bytes
+    /// appended is returned.
+    ///
+    /// If no bytes were pushed at all, then nothing, not even the stream
+    /// identifier, is appended. More bytes may be pushed afterwards, in
+    /// which case they continue the same stream.
+    pub fn finish(&mut self, out: &mut Vec<u8>) -> usize {
+        let start = out.len();
+        self.pull(out);
+        if self.srcs < self.src.len() {
+            self.push_chunk(self.srcs, self.src.len(), out);
+            self.srcs = self.src.len();
+        }
+        out.len() - start
+    }
+
+    /// Compresses `src[start..end]` and appends it to `out` as one chunk,
+    /// preceded by the stream identifier if this is the first chunk.
+    fn push_chunk(&mut self, start: usize, end: usize, out: &mut Vec<u8>) {
+        if !self.wrote_stream_ident {
+            self.wrote_stream_ident = true;
+            out.extend_from_slice(STREAM_IDENTIFIER);
+        }
+        self.state
+            .push_chunk(&self.src[start..end], out)
+            .expect("compressing a block never fails");
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Encoder {
+        Encoder::new()
+    }
+}
+
+impl fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Encoder")
+            .field("state", &self.state)
+            .field("src", &"[...]")
+            .field("srcs", &self.srcs)
+            .field("wrote_stream_ident", &self.wrote_stream_ident)
+            .finish()
+    }
+}
@@ -37,6 +37,9 @@ types. These types provide lower level control to the raw Snappy format, and
 don't support a streaming interface directly. You should only use these types
 if you know you specifically need the Snappy raw format.
 
+The CRC32C checksum used by the Snappy frame format is also exposed in the
+[`crc32`](crc32/index.html) module, for use with any other data.
+
 Finally, the `Error` type in this crate provides an exhaustive list of error
 conditions that are probably useless in most circumstances. Therefore,
 `From<snap::Error> for io::Error` is implemented in this crate, which will let
@@ -88,9 +91,24 @@ fn main() {
 doc_comment::doctest!("../README.md");
 
 pub use crate::error::{Error, Result};
+pub use crate::format::{compress, decompress, Format};
+
+/// Performs any one-time setup that this crate would otherwise do lazily.
+///
+/// Currently, this detects whether the CPU supports the instructions used
+/// to compute checksums in the Snappy frame format quickly. The result is
+/// cached, so only the first encoder or decoder to be created pays for
+/// detecting it. Latency sensitive programs may call this once at startup to
+/// move that cost out of the first request they serve.
+///
+/// Calling this is always optional, and calling it more than once is
+/// harmless.
+pub fn init() {
+    let _ = crc32::CheckSummer::new();
+}
 
 /// We don't permit compressing a block bigger than what can fit in a u32.
-const MAX_INPUT_SIZE: u64 = std::u32::MAX as u64;
+const MAX_INPUT_SIZE: u64 = u32::MAX as u64;
 
 /// The maximum number of bytes that we process at once. A block is the unit
 /// at which we scan for candidates for compression.
@@ -98,11 +116,12 @@ const MAX_BLOCK_SIZE: usize = 1 << 16;
 
 mod bytes;
 mod compress;
-mod crc32;
+pub mod crc32;
 mod crc32_table;
 mod decompress;
 mod error;
-mod frame;
+pub mod format;
+pub mod frame;
 pub mod raw;
 pub mod read;
 mod tag;
@@ -10,5 +10,10 @@ Generally, one only needs to use the raw format if some other source is
 generating raw Snappy compressed data and you have no choice but to do the
 same. Otherwise, the Snappy frame format should probably always be preferred.
 */
-pub use crate::compress::{max_compress_len, Encoder};
-pub use crate::decompress::{decompress_len, Decoder};
+pub use crate::compress::{
+    checked_max_compress_len, compress, max_compress_len, CompressOutcome,
+    Encoder,
+};
+pub use crate::decompress::{
+    decompress, decompress_len, DecodeStats, Decoder,
+};
@@ -1,5 +1,5 @@
 /*!
-This module provides two `std::io::Read` implementations:
+This module provides several `std::io::Read` implementations:
 
 * [`read::FrameDecoder`](struct.FrameDecoder.html)
   wraps another `std::io::Read` implemenation, and decompresses data encoded
@@ -9,8 +9,26 @@ This module provides two `std::io::Read` implementations:
   wraps another `std::io::Read` implemenation, and compresses data encoded
   using the Snappy frame format. Use this if you have uncompressed data source
   and wish to read it as compressed data.
+* [`read::ChainDecoder`](struct.ChainDecoder.html)
+  wraps a sequence of `std::io::Read` implementations, each of which may be
+  encoded using either the Snappy frame format or the raw Snappy format, and
+  decompresses them one after the other. Use this if you have several
+  compressed data sources and wish to read them as one uncompressed stream.
+* [`read::RawConcatDecoder`](struct.RawConcatDecoder.html)
+  wraps another `std::io::Read` implementation, and decompresses a sequence
+  of concatenated blocks encoded using the raw Snappy format.
+* [`read::AutoDecoder`](struct.AutoDecoder.html)
+  wraps another `std::io::Read` implementation, and decompresses it only if
+  it's encoded using the Snappy frame format. Use this if you have a data
+  source that may or may not be compressed.
 
 Typically, `read::FrameDecoder` is the version that you'll want.
+
+When a compressed stream is already in memory, e.g., in a memory mapped file,
+[`read::decompress_slice`](fn.decompress_slice.html) decompresses it more
+efficiently. To salvage what's left of a corrupt stream, use
+[`read::repair`](fn.repair.html). To read a stream prefixed by its length,
+use [`read::LengthPrefixedDecoder`](struct.LengthPrefixedDecoder.html).
 */
 
 use std::cmp;
@@ -18,13 +36,17 @@ use std::fmt;
 use std::io;
 
 use crate::bytes;
-use crate::compress::Encoder;
-use crate::crc32::CheckSummer;
-use crate::decompress::{decompress_len, Decoder};
+use crate::compress::{max_compress_len, Encoder};
+use crate::crc32::{CheckSummer, Crc32c};
+use crate::decompress::{
+    decompress_len, Decoder, Partial, Progress, Searcher,
+};
 use crate::error::Error;
+use crate::format::Format;
 use crate::frame::{
-    compress_frame, ChunkType, CHUNK_HEADER_AND_CRC_SIZE,
-    MAX_COMPRESS_BLOCK_SIZE, STREAM_BODY, STREAM_IDENTIFIER,
+    check_chunk_header, compress_frame, decode_chunk, decode_chunk_body,
+    truncated_chunk_error, ChunkType, VerifyCrc, CHUNK_HEADER_AND_CRC_SIZE,
+    CHUNK_HEADER_SIZE, MAX_COMPRESS_BLOCK_SIZE, STREAM_IDENTIFIER,
 };
 use crate::MAX_BLOCK_SIZE;
 
@@ -34,6 +56,288 @@ const MAX_READ_FRAME_ENCODER_BLOCK_SIZE: usize = STREAM_IDENTIFIER.len()
     + CHUNK_HEADER_AND_CRC_SIZE
     + MAX_COMPRESS_BLOCK_SIZE;
 
+/// The capacity of the buffered reader used by `FrameDecoder::buffered`, which
+/// is big enough to hold the largest possible chunk, including its header.
+const MAX_READ_FRAME_DECODER_BUF_SIZE: usize =
+    CHUNK_HEADER_AND_CRC_SIZE + MAX_COMPRESS_BLOCK_SIZE;
+
+/// The type of the callback set by `FrameDecoder::set_on_skippable`.
+type SkippableCallback = dyn FnMut(u8, &[u8]) + Send + Sync;
+
+/// The type of the callback set by `FrameDecoder::set_on_checksum_error`.
+type ChecksumCallback = dyn FnMut(ChunkType, u32, u32) -> bool + Send + Sync;
+
+/// Decompresses all bytes in `input`, which must be a complete stream in the
+/// Snappy frame format, and returns the decompressed bytes.
+///
+/// The result is identical to reading everything from a
+/// [`FrameDecoder`](struct.FrameDecoder.html) wrapping `input`. But since
+/// `input` is already in memory, each chunk is decoded directly from it,
+/// instead of first being copied into an internal buffer.
+///
+/// # Errors
+///
+/// This returns an error under the same circumstances that reading from a
+/// `FrameDecoder` does. In particular, if `input` ends with an incomplete
+/// chunk, then an error with kind `std::io::ErrorKind::UnexpectedEof` is
+/// returned.
+///
+/// # Example
+///
+/// ```
+/// let mut compressed = vec![];
+/// snap::write::compress_into_vec(b"Hello, Hello!", &mut compressed).unwrap();
+/// let got = snap::read::decompress_slice(&compressed).unwrap();
+/// assert_eq!(b"Hello, Hello!", &*got);
+/// ```
+pub fn decompress_slice(input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    decompress_slice_to_writer(input, &mut output)?;
+    Ok(output)
+}
+
+/// Decompresses all bytes in `input`, which must be a complete stream in the
+/// Snappy frame format, and writes the decompressed bytes to `wtr`.
+///
+/// This is just like [`decompress_slice`](fn.decompress_slice.html), except
+/// the decompressed bytes are written to `wtr` one chunk at a time instead of
+/// being collected into a `Vec`. On success, the total number of bytes
+/// written is returned.
+///
+/// If an error occurs, then the bytes of every chunk preceding the erroneous
+/// one have already been written to `wtr`.
+pub fn decompress_slice_to_writer<W: io::Write>(
+    mut input: &[u8],
+    mut wtr: W,
+) -> io::Result<u64> {
+    let mut dec = Decoder::new();
+    let checksummer = CheckSummer::new();
+    let mut dst = vec![];
+    let mut read_stream_ident = false;
+    let mut total = 0;
+    while !input.is_empty() {
+        let (data, consumed) = match decode_chunk(
+            &mut dec,
+            checksummer,
+            input,
+            &mut dst,
+            &mut read_stream_ident,
+            VerifyCrc::Yes,
+        )? {
+            Some(chunk) => chunk,
+            None => return Err(truncated_chunk_error(input)),
+        };
+        wtr.write_all(data)?;
+        total += data.len() as u64;
+        input = &input[consumed..];
+    }
+    Ok(total)
+}
+
+/// Returns true if the bytes that the Snappy frame formatted stream read from
+/// `rdr` decompresses to contain `needle`.
+///
+/// This is the frame format counterpart to
+/// [`raw::Decoder::decompress_and_contains`](../raw/struct.Decoder.html#method.decompress_and_contains).
+/// Each chunk is searched as soon as it's decompressed, and no more of `rdr`
+/// is read once `needle` is found. Matches that straddle chunks are found
+/// too.
+///
+/// # Errors
+///
+/// This returns an error under the same circumstances that reading from a
+/// [`FrameDecoder`](struct.FrameDecoder.html) does, except that errors in
+/// the stream following a match are never detected.
+///
+/// # Example
+///
+/// ```
+/// let mut compressed = vec![];
+/// snap::write::compress_into_vec(b"foo bar baz", &mut compressed).unwrap();
+/// assert!(snap::read::decompress_and_contains(&compressed[..], b"bar")
+///     .unwrap());
+/// assert!(!snap::read::decompress_and_contains(&compressed[..], b"quux")
+///     .unwrap());
+/// ```
+pub fn decompress_and_contains<R: io::Read>(
+    rdr: R,
+    needle: &[u8],
+) -> io::Result<bool> {
+    let mut dec = FrameDecoder::new(rdr);
+    let mut searcher = Searcher::new(needle);
+    while !searcher.found() {
+        let buf = io::BufRead::fill_buf(&mut dec)?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        let n = buf.len();
+        searcher.search(buf);
+        io::BufRead::consume(&mut dec, n);
+    }
+    Ok(true)
+}
+
+/// Decompresses as much as possible of a possibly corrupt stream in the
+/// Snappy frame format read from `rdr`, and writes the decompressed bytes to
+/// `wtr`.
+///
+/// This is useful for salvaging data from a damaged archive. Every chunk
+/// that fails to decode, e.g., because of a checksum mismatch or an invalid
+/// compressed block, is dropped and decoding continues with the chunk that
+/// follows it. If a chunk header is itself invalid, then the position of the
+/// next chunk can't be known, so everything up to the next stream identifier
+/// is dropped instead. A truncated chunk at the end of the stream is also
+/// dropped.
+///
+/// The returned statistics count the chunks containing data that were
+/// recovered and dropped.
+///
+/// # Errors
+///
+/// This only returns an error if reading from `rdr` or writing to `wtr`
+/// fails. Any bytes decompressed before the error have already been written
+/// to `wtr`.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+///
+/// let mut wtr = snap::write::FrameEncoder::new(vec![]);
+/// wtr.write_all(b"foo").unwrap();
+/// wtr.flush().unwrap();
+/// wtr.write_all(b"bar").unwrap();
+/// let mut compressed = wtr.into_inner().unwrap();
+/// // Corrupt the checksum of the first chunk after the stream identifier.
+/// compressed[14] ^= 0xFF;
+///
+/// let mut got = vec![];
+/// let stats = snap::read::repair(&compressed[..], &mut got).unwrap();
+/// assert_eq!(b"bar", &*got);
+/// assert_eq!(1, stats.recovered_chunks());
+/// assert_eq!(1, stats.dropped_chunks());
+/// ```
+pub fn repair<R: io::Read, W: io::Write>(
+    rdr: R,
+    mut wtr: W,
+) -> io::Result<RepairStats> {
+    let mut dec = FrameDecoder::buffered(rdr);
+    let mut stats = RepairStats::default();
+    loop {
+        match dec.read_next_chunk() {
+            Ok(false) => return Ok(stats),
+            Ok(true) => {
+                wtr.write_all(&dec.dst[dec.dsts..dec.dste])?;
+                dec.dsts = dec.dste;
+                stats.recovered += 1;
+            }
+            Err(err) => {
+                let corrupt = err.kind() == io::ErrorKind::UnexpectedEof
+                    || err.get_ref().is_some_and(|e| e.is::<Error>());
+                if !corrupt {
+                    return Err(err);
+                }
+                stats.dropped += 1;
+                // If the chunk wasn't read in full, then its header is the
+                // problem and there's no telling where the next chunk is.
+                if dec.srcn > 0 && !dec.resync_to_next_stream()? {
+                    return Ok(stats);
+                }
+            }
+        }
+    }
+}
+
+/// Statistics about the chunks of a stream repaired by
+/// [`repair`](fn.repair.html).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RepairStats {
+    recovered: u64,
+    dropped: u64,
+}
+
+impl RepairStats {
+    /// Returns the number of chunks whose decompressed bytes were written.
+    ///
+    /// Only chunks containing data, i.e., compressed and uncompressed chunks,
+    /// are counted.
+    pub fn recovered_chunks(&self) -> u64 {
+        self.recovered
+    }
+
+    /// Returns the number of chunks that were dropped because they couldn't
+    /// be decoded.
+    ///
+    /// When a chunk header is invalid, everything up to the next stream
+    /// identifier is dropped and counted as a single chunk.
+    pub fn dropped_chunks(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Statistics about the chunks read by a
+/// [`FrameDecoder`](struct.FrameDecoder.html), as returned by
+/// [`FrameDecoder::stats`](struct.FrameDecoder.html#method.stats).
+///
+/// Only chunks that have been read in their entirety are counted. Chunks
+/// that fail to decode are counted as far as they were decoded, e.g., a
+/// chunk whose checksum doesn't match counts as a checksum verified, but not
+/// as a chunk read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameStats {
+    compressed_chunks: u64,
+    uncompressed_chunks: u64,
+    padding_chunks: u64,
+    skippable_chunks: u64,
+    compressed_bytes: u64,
+    decompressed_bytes: u64,
+    checksums: u64,
+}
+
+impl FrameStats {
+    /// Returns the number of chunks containing compressed data.
+    pub fn compressed_chunks(&self) -> u64 {
+        self.compressed_chunks
+    }
+
+    /// Returns the number of chunks containing uncompressed data.
+    pub fn uncompressed_chunks(&self) -> u64 {
+        self.uncompressed_chunks
+    }
+
+    /// Returns the number of padding chunks, which were skipped.
+    pub fn padding_chunks(&self) -> u64 {
+        self.padding_chunks
+    }
+
+    /// Returns the number of reserved but skippable chunks, which were
+    /// skipped.
+    pub fn skippable_chunks(&self) -> u64 {
+        self.skippable_chunks
+    }
+
+    /// Returns the total number of compressed bytes read, including the
+    /// headers of all chunks and any stream identifiers.
+    pub fn compressed_bytes(&self) -> u64 {
+        self.compressed_bytes
+    }
+
+    /// Returns the total number of decompressed bytes produced by chunks
+    /// containing data.
+    ///
+    /// This includes bytes that have been decompressed but not yet read
+    /// from the decoder.
+    pub fn decompressed_bytes(&self) -> u64 {
+        self.decompressed_bytes
+    }
+
+    /// Returns the number of checksums that were verified, whether or not
+    /// they matched.
+    pub fn checksums(&self) -> u64 {
+        self.checksums
+    }
+}
+
 /// A reader for decompressing a Snappy stream.
 ///
 /// This `FrameDecoder` wraps any other reader that implements `std::io::Read`.
@@ -43,7 +347,13 @@ const MAX_READ_FRAME_ENCODER_BLOCK_SIZE: usize = STREAM_IDENTIFIER.len()
 ///
 /// This reader can potentially make many small reads from the underlying
 /// stream depending on its format, therefore, passing in a buffered reader
-/// may be beneficial.
+/// may be beneficial. [`FrameDecoder::buffered`](#method.buffered) does this
+/// for you.
+///
+/// If reading from the underlying stream fails, e.g., with
+/// `std::io::ErrorKind::WouldBlock` on a nonblocking socket, then the error
+/// is returned and no progress is lost. Reading again resumes decoding from
+/// where it left off.
 pub struct FrameDecoder<R: io::Read> {
     /// The underlying reader.
     r: R,
@@ -54,34 +364,346 @@ pub struct FrameDecoder<R: io::Read> {
     /// fallback version or the SSE4.2 accelerated version when the right CPU
     /// features are available.
     checksummer: CheckSummer,
-    /// The compressed bytes buffer, taken from the underlying reader.
+    /// The compressed bytes buffer, taken from the underlying reader. This
+    /// holds the chunk currently being read, including its header.
     src: Vec<u8>,
+    /// The length that src grows to when a chunk doesn't fit in it. Chunks
+    /// that don't fit in this many bytes are rejected.
+    max_src: usize,
+    /// Index into src: the number of bytes of the current chunk read so far.
+    srcn: usize,
+    /// The length of the current chunk's body, once its header has been read
+    /// and validated.
+    chunk_len: Option<usize>,
     /// The decompressed bytes buffer. Bytes are decompressed from src to dst
     /// before being passed back to the caller.
     dst: Vec<u8>,
+    /// The length that dst grows to when a chunk's decompressed bytes don't
+    /// fit in it. Chunks that don't fit in this many bytes are rejected.
+    max_dst: usize,
     /// Index into dst: starting point of bytes not yet given back to caller.
     dsts: usize,
     /// Index into dst: ending point of bytes not yet given back to caller.
     dste: usize,
     /// Whether we've read the special stream header or not.
     read_stream_ident: bool,
+    /// The total number of chunks read so far, of any type.
+    chunks: u64,
+    /// The offset in the compressed stream of the end of the last chunk read
+    /// in its entirety.
+    compressed_pos: u64,
+    /// Options that were changed from their defaults, if any.
+    opts: Option<Box<FrameDecoderOptions>>,
+    /// Counters for the chunks read so far.
+    stats: FrameStats,
+}
+
+/// The options of a `FrameDecoder` that are rarely set.
+///
+/// These are kept behind a pointer, so that decoders using the defaults
+/// stay small.
+#[derive(Default)]
+struct FrameDecoderOptions {
+    /// The maximum number of chunks permitted, if any.
+    max_chunks: Option<u64>,
+    /// A callback invoked with each reserved but skippable chunk, if any.
+    on_skippable: Option<Box<SkippableCallback>>,
+    /// A callback invoked with each checksum mismatch, if any, which decides
+    /// whether decoding continues.
+    on_checksum_error: Option<Box<ChecksumCallback>>,
 }
 
 impl<R: io::Read> FrameDecoder<R> {
     /// Create a new reader for streaming Snappy decompression.
     pub fn new(rdr: R) -> FrameDecoder<R> {
+        FrameDecoder::with_capacity(rdr, MAX_BLOCK_SIZE)
+    }
+
+    /// Create a new reader for streaming Snappy decompression whose buffers
+    /// are sized for blocks of at most `max_block` decompressed bytes.
+    ///
+    /// By default, a decoder allocates enough memory for the biggest blocks
+    /// permitted by the Snappy frame format, which is about 140KB. When
+    /// decoding many streams at once whose blocks are known to be small, this
+    /// can be used to reduce memory usage. `max_block` is capped at the
+    /// maximum block size of the frame format (65536 bytes).
+    ///
+    /// Reading a stream containing a chunk that doesn't fit in these buffers
+    /// returns an error, reported as `Error::UnsupportedChunkLength`. This
+    /// includes chunks whose decompressed bytes exceed `max_block`, and any
+    /// other chunk that is bigger than the biggest chunk a block of
+    /// `max_block` bytes compresses to.
+    pub fn with_capacity(rdr: R, max_block: usize) -> FrameDecoder<R> {
+        let max_block = cmp::min(max_block, MAX_BLOCK_SIZE);
+        let max_chunk =
+            cmp::min(4 + max_compress_len(max_block), MAX_COMPRESS_BLOCK_SIZE);
         FrameDecoder {
             r: rdr,
             dec: Decoder::new(),
             checksummer: CheckSummer::new(),
-            src: vec![0; MAX_COMPRESS_BLOCK_SIZE],
-            dst: vec![0; MAX_BLOCK_SIZE],
+            src: vec![0; CHUNK_HEADER_SIZE + max_chunk],
+            max_src: CHUNK_HEADER_SIZE + max_chunk,
+            srcn: 0,
+            chunk_len: None,
+            dst: vec![0; max_block],
+            max_dst: max_block,
             dsts: 0,
             dste: 0,
             read_stream_ident: false,
+            chunks: 0,
+            compressed_pos: 0,
+            opts: None,
+            stats: FrameStats::default(),
         }
     }
 
+    /// Create a new reader that resumes decoding a stream at `position`, as
+    /// previously returned by
+    /// [`compressed_position`](#method.compressed_position).
+    ///
+    /// `rdr` must already be positioned at `position` in the compressed
+    /// stream, e.g., by seeking it there. Since `position` is a chunk
+    /// boundary, decoding continues with the next chunk as if the stream had
+    /// been read up to that point. In particular, the stream identifier
+    /// isn't expected again unless `position` is `0`. Subsequent calls to
+    /// `compressed_position` continue counting from `position`.
+    pub fn resume(rdr: R, position: u64) -> FrameDecoder<R> {
+        let mut dec = FrameDecoder::new(rdr);
+        dec.read_stream_ident = position > 0;
+        dec.compressed_pos = position;
+        dec
+    }
+
+    /// Create a new reader for streaming Snappy decompression that wraps
+    /// `rdr` in a buffered reader.
+    ///
+    /// Since `FrameDecoder` may make many small reads from the underlying
+    /// reader, wrapping unbuffered sources such as files or sockets in a
+    /// buffer is usually beneficial. The buffer is large enough to hold the
+    /// biggest chunk permitted by the Snappy frame format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::io::{self, Read, Write};
+    ///
+    /// # fn example() -> io::Result<()> {
+    /// let path = std::env::temp_dir().join("snap-frame-decoder-buffered.sz");
+    /// let mut wtr = snap::write::FrameEncoder::new(File::create(&path)?);
+    /// wtr.write_all(b"hello, world")?;
+    /// wtr.flush()?;
+    /// drop(wtr);
+    ///
+    /// let file = File::open(&path)?;
+    /// let mut rdr = snap::read::FrameDecoder::buffered(file);
+    /// let mut got = String::new();
+    /// rdr.read_to_string(&mut got)?;
+    /// assert_eq!("hello, world", got);
+    /// # std::fs::remove_file(&path) }
+    /// # example().unwrap();
+    /// ```
+    pub fn buffered(rdr: R) -> FrameDecoder<io::BufReader<R>> {
+        FrameDecoder::new(io::BufReader::with_capacity(
+            MAX_READ_FRAME_DECODER_BUF_SIZE,
+            rdr,
+        ))
+    }
+
+    /// Create a new reader for streaming Snappy decompression that also
+    /// writes every byte read from `rdr` to `tee`.
+    ///
+    /// This makes it possible to save the exact compressed bytes of a stream,
+    /// e.g., to a cache on disk, while decompressing it from a source that
+    /// can only be read once, such as a socket. Bytes are written to `tee`
+    /// as soon as they're read from `rdr`, before they are decompressed. So
+    /// `tee` receives every byte that was read, even if the stream later
+    /// turns out to be corrupt.
+    ///
+    /// If writing to `tee` fails, then the error is returned by the read
+    /// that caused it. The bytes that couldn't be written are lost, so
+    /// decoding can't continue correctly after such an error.
+    pub fn with_tee<T: io::Write>(rdr: R, tee: T) -> FrameDecoder<Tee<R, T>> {
+        FrameDecoder::new(Tee { rdr, tee })
+    }
+
+    /// Set the maximum number of chunks that this decoder will read.
+    ///
+    /// Every chunk in a stream carries some fixed overhead when decoding it,
+    /// regardless of how much data it contains. Thus, a stream consisting of
+    /// a very large number of tiny chunks can be expensive to decode even if
+    /// its decompressed size is small. Setting a limit guards against this.
+    ///
+    /// Every chunk counts towards the limit, including stream identifiers
+    /// and padding. Once more than `max` chunks have been seen, reading
+    /// returns an error with kind `std::io::ErrorKind::InvalidData`.
+    ///
+    /// By default, there is no limit.
+    pub fn set_max_chunks(&mut self, max: u64) {
+        self.opts_mut().max_chunks = Some(max);
+    }
+
+    /// Set a callback that is invoked for each reserved but skippable chunk.
+    ///
+    /// The Snappy frame format reserves chunk types `0x80` through `0xFD`
+    /// for chunks that decoders must skip. Some applications use them to
+    /// embed metadata in a stream. The callback is given the chunk type and
+    /// the chunk's payload, before the chunk is skipped. Decoding is
+    /// otherwise unaffected.
+    ///
+    /// The callback must be `Send` and `Sync` so that this decoder remains
+    /// so. Setting a callback replaces any previously set callback.
+    pub fn set_on_skippable<F>(&mut self, f: F)
+    where
+        F: FnMut(u8, &[u8]) + Send + Sync + 'static,
+    {
+        self.opts_mut().on_skippable = Some(Box::new(f));
+    }
+
+    /// Set a callback that is invoked when the checksum of a chunk doesn't
+    /// match its decompressed bytes.
+    ///
+    /// The callback is given the type of the chunk, the checksum read from
+    /// the stream and the checksum computed from the decompressed bytes, in
+    /// that order. If it returns `true`, then the decompressed bytes are used
+    /// anyway, even though they are likely wrong, and decoding continues. If
+    /// it returns `false`, then reading fails with a checksum error.
+    ///
+    /// This is useful for recovering as much data as possible from a
+    /// corrupt stream. By default, a checksum mismatch is always an error.
+    ///
+    /// The callback must be `Send` and `Sync` so that this decoder remains
+    /// so. Setting a callback replaces any previously set callback.
+    pub fn set_on_checksum_error<F>(&mut self, f: F)
+    where
+        F: FnMut(ChunkType, u32, u32) -> bool + Send + Sync + 'static,
+    {
+        self.opts_mut().on_checksum_error = Some(Box::new(f));
+    }
+
+    /// Set the constant used to mask the checksum of each chunk, in place of
+    /// the one defined by the Snappy frame format.
+    ///
+    /// This makes it possible to read streams written by nonstandard
+    /// implementations, but streams that conform to the Snappy frame format
+    /// will then fail with a checksum error. See
+    /// [`write::FrameEncoder::set_crc_mask_constant`](../write/struct.FrameEncoder.html#method.set_crc_mask_constant)
+    /// for details.
+    pub fn set_crc_mask_constant(&mut self, delta: u32) {
+        self.checksummer.set_mask_constant(delta);
+    }
+
+    /// Returns the offset in the compressed stream just past the last chunk
+    /// that has been read in its entirety.
+    ///
+    /// This is always a chunk boundary. Any bytes of the next chunk that
+    /// have been read from the underlying reader so far aren't counted.
+    /// Combined with [`resume`](#method.resume), this makes it possible to
+    /// checkpoint decoding and resume it later, e.g., after a restart.
+    ///
+    /// Note that the decompressed bytes of the last chunk read may not have
+    /// been consumed yet. So a checkpoint should only be taken when none are
+    /// buffered, e.g., after consuming everything returned by
+    /// `std::io::BufRead::fill_buf`. Otherwise, those bytes are skipped when
+    /// decoding resumes.
+    pub fn compressed_position(&self) -> u64 {
+        self.compressed_pos
+    }
+
+    /// Returns statistics about the chunks this decoder has read since it was
+    /// created.
+    ///
+    /// The statistics accumulate across streams, e.g., when several streams
+    /// are concatenated or after
+    /// [`begin_new_stream`](#method.begin_new_stream).
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Returns the current lengths of this decoder's buffers for compressed
+    /// and decompressed bytes, in that order.
+    ///
+    /// This is useful for measuring how much memory a decoder holds on to.
+    /// See also [`shrink_buffers`](#method.shrink_buffers).
+    pub fn buffer_sizes(&self) -> (usize, usize) {
+        (self.src.len(), self.dst.len())
+    }
+
+    /// Shrinks this decoder's buffers as much as possible without losing any
+    /// bytes that are buffered, and releases the memory they no longer need.
+    ///
+    /// This is useful for a long lived decoder, e.g., one kept in a pool,
+    /// that is idle between bursts of use. The buffers grow back to their
+    /// original sizes as soon as they're needed to read another chunk. So
+    /// shrinking them never changes what is decoded, but it does mean that
+    /// they're allocated again.
+    ///
+    /// The buffer for compressed bytes is never shrunk below the length of
+    /// the stream identifier (10 bytes), or below the length of a partially
+    /// read chunk. The buffer for decompressed bytes keeps only those bytes
+    /// that have not been read yet.
+    pub fn shrink_buffers(&mut self) {
+        let keep = match self.chunk_len {
+            Some(len) => CHUNK_HEADER_SIZE + len,
+            None => cmp::max(self.srcn, STREAM_IDENTIFIER.len()),
+        };
+        self.src.truncate(keep);
+        self.src.shrink_to_fit();
+        self.dst.copy_within(self.dsts..self.dste, 0);
+        self.dste -= self.dsts;
+        self.dsts = 0;
+        self.dst.truncate(self.dste);
+        self.dst.shrink_to_fit();
+    }
+
+    /// Returns an iterator over the lines of the decompressed stream.
+    ///
+    /// This is just like `std::io::BufRead::lines`, which works since this
+    /// decoder implements `BufRead` using its own buffer of decompressed
+    /// bytes. In particular, there's no need to wrap it in a
+    /// `std::io::BufReader` first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressed = vec![];
+    /// snap::write::compress_into_vec(b"foo\nbar\r\nbaz", &mut compressed)
+    ///     .unwrap();
+    /// let rdr = snap::read::FrameDecoder::new(&compressed[..]);
+    /// let lines: Vec<String> = rdr.lines().map(|r| r.unwrap()).collect();
+    /// assert_eq!(vec!["foo", "bar", "baz"], lines);
+    /// ```
+    pub fn lines(self) -> io::Lines<FrameDecoder<R>> {
+        io::BufRead::lines(self)
+    }
+
+    /// Returns a reader that yields at most `max` decompressed bytes from
+    /// this decoder.
+    ///
+    /// This is like `std::io::Read::take`, except that reaching the limit
+    /// isn't treated as EOF when the stream has more bytes to give. Instead,
+    /// reading past the limit returns an error, which distinguishes a stream
+    /// that decompresses to at most `max` bytes from one that was cut short
+    /// by the limit. This is useful for guarding against decompression bombs
+    /// while still validating the length of a stream.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// let mut compressed = vec![];
+    /// snap::write::compress_into_vec(b"Hello, world!", &mut compressed)
+    ///     .unwrap();
+    /// let rdr = snap::read::FrameDecoder::new(&compressed[..]);
+    /// let mut got = vec![];
+    /// assert!(rdr.take_limited(5).read_to_end(&mut got).is_err());
+    /// assert_eq!(b"Hello", &*got);
+    /// ```
+    pub fn take_limited(self, max: u64) -> LimitedDecoder<R> {
+        LimitedDecoder { dec: self, max, remaining: max }
+    }
+
     /// Gets a reference to the underlying reader in this decoder.
     pub fn get_ref(&self) -> &R {
         &self.r
@@ -99,156 +721,642 @@ impl<R: io::Read> FrameDecoder<R> {
     pub fn into_inner(self) -> R {
         self.r
     }
+
+    /// Discards all buffered state and skips ahead in the underlying reader
+    /// to the start of the next stream identifier.
+    ///
+    /// This is useful for recovering from an error in a corrupt stream,
+    /// e.g., in a file that concatenates several Snappy streams. Any
+    /// decompressed bytes that haven't been read yet, along with any bytes of
+    /// the current chunk read so far, are discarded. The underlying reader is
+    /// then scanned for the magic bytes of the stream identifier. If they're
+    /// found, then this returns `true` and subsequent reads decode the stream
+    /// that they start. Otherwise, the underlying reader is at EOF and
+    /// `false` is returned.
+    ///
+    /// Since the underlying reader is scanned one byte at a time, it should
+    /// be buffered, e.g., by using [`buffered`](#method.buffered).
+    ///
+    /// If reading from the underlying reader fails, then the error is
+    /// returned, and the bytes scanned so far are lost.
+    pub fn resync_to_next_stream(&mut self) -> io::Result<bool> {
+        self.compressed_pos += self.srcn as u64;
+        self.srcn = 0;
+        self.chunk_len = None;
+        self.dsts = 0;
+        self.dste = 0;
+        self.read_stream_ident = false;
+        let mut matched = 0;
+        while matched < STREAM_IDENTIFIER.len() {
+            let (mut byte, mut pos) = ([0], 0);
+            if !fill(&mut self.r, &mut byte, &mut pos, 1)? {
+                return Ok(false);
+            }
+            self.compressed_pos += 1;
+            // Since the first byte of the stream identifier doesn't occur
+            // anywhere else in it, a mismatch can only be the start of a new
+            // match if it is that first byte.
+            matched = if byte[0] == STREAM_IDENTIFIER[matched] {
+                matched + 1
+            } else if byte[0] == STREAM_IDENTIFIER[0] {
+                1
+            } else {
+                0
+            };
+        }
+        // Put the stream identifier back, so that it's read like any other
+        // chunk.
+        let n = STREAM_IDENTIFIER.len();
+        self.src[..n].copy_from_slice(STREAM_IDENTIFIER);
+        self.srcn = n;
+        self.compressed_pos -= n as u64;
+        Ok(true)
+    }
+
+    /// Discards all buffered state, and expects the next bytes read from the
+    /// underlying reader to start a new stream.
+    ///
+    /// This is useful for reading several concatenated streams from the same
+    /// underlying reader, while treating each as a fresh start. After this
+    /// is called, the next chunk must be a stream identifier, just like at
+    /// the start of a new decoder, or else reading returns an error. Any
+    /// decompressed bytes that haven't been read yet, along with any bytes of
+    /// the current chunk read so far, are discarded. So this should usually
+    /// be called once the previous stream has been read in its entirety.
+    ///
+    /// Unlike [`resync_to_next_stream`](#method.resync_to_next_stream), this
+    /// never reads from the underlying reader or skips any of its bytes.
+    pub fn begin_new_stream(&mut self) {
+        self.srcn = 0;
+        self.chunk_len = None;
+        self.dsts = 0;
+        self.dste = 0;
+        self.read_stream_ident = false;
+    }
+
+    /// Reads and returns up to `max` decompressed bytes from the start of
+    /// what's left of the stream.
+    ///
+    /// Only as many chunks as are needed to produce `max` bytes are read
+    /// from the underlying reader and decompressed, which makes this cheap
+    /// for previewing the beginning of a big stream. Fewer than `max` bytes
+    /// are returned only if the end of the stream is reached first. Since
+    /// chunks are decompressed whole, any bytes of the last chunk beyond
+    /// `max` are kept, and subsequent reads continue from there.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error under the same circumstances that reading does.
+    /// Any bytes read before the error are lost.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressed = vec![];
+    /// snap::write::compress_into_vec(b"Hello, world!", &mut compressed)
+    ///     .unwrap();
+    /// let mut rdr = snap::read::FrameDecoder::new(&compressed[..]);
+    /// assert_eq!(b"Hello", &*rdr.read_prefix(5).unwrap());
+    /// ```
+    pub fn read_prefix(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        let mut prefix = Vec::with_capacity(cmp::min(max, MAX_BLOCK_SIZE));
+        while prefix.len() < max {
+            let buf = io::BufRead::fill_buf(self)?;
+            if buf.is_empty() {
+                break;
+            }
+            let n = cmp::min(buf.len(), max - prefix.len());
+            prefix.extend_from_slice(&buf[..n]);
+            io::BufRead::consume(self, n);
+        }
+        Ok(prefix)
+    }
+
+    /// Fills `out` with as many decompressed bytes as possible, and returns
+    /// the number of bytes written to it.
+    ///
+    /// Unlike `std::io::Read::read`, which may return fewer bytes than fit in
+    /// `out` whenever it's convenient, this keeps decoding chunks until `out`
+    /// is full. Fewer than `out.len()` bytes are returned only if the end of
+    /// the stream is reached first. Any decompressed bytes of the last chunk
+    /// that don't fit in `out` are kept, and are returned first by the next
+    /// read. This is useful for filling fixed size buffers, e.g., the payload
+    /// of a protocol frame. The number of compressed bytes consumed so far is
+    /// reported by
+    /// [`compressed_position`](#method.compressed_position).
+    ///
+    /// # Errors
+    ///
+    /// This returns an error under the same circumstances that reading does.
+    /// Any bytes written to `out` before the error are lost.
+    pub fn read_into_fixed(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < out.len() {
+            let buf = io::BufRead::fill_buf(self)?;
+            if buf.is_empty() {
+                break;
+            }
+            let len = cmp::min(buf.len(), out.len() - n);
+            out[n..n + len].copy_from_slice(&buf[..len]);
+            io::BufRead::consume(self, len);
+            n += len;
+        }
+        Ok(n)
+    }
+
+    /// Decompresses the rest of the stream, and checks that the CRC32C
+    /// checksum of the decompressed bytes is `expected_crc`.
+    ///
+    /// This is useful for checking the integrity of a whole payload end to
+    /// end, in addition to the checksum of each chunk that the frame format
+    /// already checks. The checksum is computed incrementally as each chunk
+    /// is decompressed, exactly like
+    /// [`crc32::crc32c`](../crc32/fn.crc32c.html) computes it (i.e., it isn't
+    /// masked).
+    ///
+    /// # Errors
+    ///
+    /// If the checksum doesn't match, then an error converted from
+    /// `Error::Checksum` is returned. Otherwise, this returns an error under
+    /// the same circumstances that reading does. In either case, the
+    /// decompressed bytes are lost.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let compressed = snap::write::FrameEncoder::compress_all(b"hello");
+    /// let crc = snap::crc32::crc32c(b"hello");
+    /// let mut rdr = snap::read::FrameDecoder::new(&compressed[..]);
+    /// assert_eq!(b"hello", &*rdr.decompress_verify(crc).unwrap());
+    /// ```
+    pub fn decompress_verify(
+        &mut self,
+        expected_crc: u32,
+    ) -> io::Result<Vec<u8>> {
+        let mut crc = Crc32c::new();
+        let mut out = vec![];
+        loop {
+            let buf = io::BufRead::fill_buf(self)?;
+            if buf.is_empty() {
+                break;
+            }
+            crc.update(buf);
+            out.extend_from_slice(buf);
+            let n = buf.len();
+            io::BufRead::consume(self, n);
+        }
+        if crc.finalize() != expected_crc {
+            return Err(io::Error::from(Error::Checksum {
+                expected: expected_crc,
+                got: crc.finalize(),
+            }));
+        }
+        Ok(out)
+    }
+
+    /// Returns the number of decompressed bytes that the current chunk will
+    /// yield before the next chunk is started.
+    ///
+    /// If this decoder is positioned at a chunk boundary, then only the
+    /// header of the next chunk containing data is read from the underlying
+    /// reader, along with the header of its compressed block, if any. The
+    /// number of bytes that these headers say the chunk decompresses to is
+    /// returned, but the body of the chunk is neither read nor checked until
+    /// the next read. Any other chunks preceding it, such as padding, are
+    /// skipped. If the underlying reader is at EOF, then `None` is returned.
+    ///
+    /// If some bytes of the current chunk have not been read yet, then the
+    /// number of such bytes is returned and the underlying reader is not
+    /// touched.
+    ///
+    /// This is useful for sizing buffers before reading.
+    pub fn peek_chunk_decompressed_len(
+        &mut self,
+    ) -> io::Result<Option<usize>> {
+        if self.dsts < self.dste {
+            return Ok(Some(self.dste - self.dsts));
+        }
+        loop {
+            let len = match self.read_chunk_len()? {
+                Some(len) => len,
+                None => return Ok(None),
+            };
+            match ChunkType::from_u8(self.src[0]) {
+                Ok(ChunkType::Uncompressed) => return Ok(Some(len - 4)),
+                Ok(ChunkType::Compressed) => {
+                    // Enough for the checksum and the longest varint that
+                    // can start a compressed block.
+                    let end = cmp::min(
+                        CHUNK_HEADER_SIZE + len,
+                        CHUNK_HEADER_AND_CRC_SIZE + 5,
+                    );
+                    if !fill(&mut self.r, &mut self.src, &mut self.srcn, end)?
+                    {
+                        return Err(truncated_chunk_error(
+                            &self.src[..self.srcn],
+                        ));
+                    }
+                    let block = &self.src[CHUNK_HEADER_AND_CRC_SIZE..end];
+                    return Ok(Some(decompress_len(block)?));
+                }
+                _ => {
+                    self.read_chunk(len)?;
+                }
+            }
+        }
+    }
+
+    /// Reads chunks from the underlying reader until one containing data is
+    /// found, and then decompresses it into `dst`.
+    ///
+    /// This returns `false` if the underlying reader is at EOF.
+    ///
+    /// If reading from the underlying reader fails, e.g., with
+    /// `ErrorKind::WouldBlock`, then the bytes of the current chunk read so
+    /// far are kept in `src`. Calling this again resumes where it left off.
+    fn read_next_chunk(&mut self) -> io::Result<bool> {
+        loop {
+            let len = match self.read_chunk_len()? {
+                Some(len) => len,
+                None => return Ok(false),
+            };
+            if self.read_chunk(len)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Reads the header of the current chunk, unless that was already done,
+    /// and returns the length of the chunk that follows it.
+    ///
+    /// This returns `None` if the underlying reader is at EOF.
+    fn read_chunk_len(&mut self) -> io::Result<Option<usize>> {
+        if let Some(len) = self.chunk_len {
+            return Ok(Some(len));
+        }
+        if !fill(&mut self.r, &mut self.src, &mut self.srcn, 4)? {
+            if self.srcn == 0 {
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+        let len = self.read_chunk_header()?;
+        self.chunk_len = Some(len);
+        Ok(Some(len))
+    }
+
+    /// Reads the rest of the current chunk, whose header says it has length
+    /// `len`. If it contains data, then it's decompressed into `dst` and this
+    /// returns true.
+    fn read_chunk(&mut self, len: usize) -> io::Result<bool> {
+        macro_rules! fail {
+            ($err:expr) => {
+                return Err(io::Error::from($err))
+            };
+        }
+        let end = CHUNK_HEADER_SIZE + len;
+        if !fill(&mut self.r, &mut self.src, &mut self.srcn, end)? {
+            return Err(truncated_chunk_error(&self.src[..self.srcn]));
+        }
+        // The whole chunk is in src, so the next call starts a new one.
+        self.srcn = 0;
+        self.chunk_len = None;
+        self.compressed_pos += end as u64;
+        self.stats.compressed_bytes += end as u64;
+
+        let on_checksum_error =
+            self.opts.as_mut().and_then(|o| o.on_checksum_error.as_mut());
+        let verify = match on_checksum_error {
+            Some(f) => VerifyCrc::Callback(&mut **f),
+            None => VerifyCrc::Yes,
+        };
+        let n = match decode_chunk_body(
+            &mut self.dec,
+            self.checksummer,
+            &self.src[..end],
+            &mut self.dst,
+            self.max_dst,
+            true,
+            verify,
+        ) {
+            Ok(data) => data.len(),
+            Err(err) => {
+                if let Error::Checksum { .. } = err {
+                    self.stats.checksums += 1;
+                }
+                fail!(err);
+            }
+        };
+        match ChunkType::from_u8(self.src[0]) {
+            Ok(ty @ ChunkType::Compressed)
+            | Ok(ty @ ChunkType::Uncompressed) => {
+                if ty == ChunkType::Compressed {
+                    self.stats.compressed_chunks += 1;
+                } else {
+                    self.stats.uncompressed_chunks += 1;
+                }
+                self.stats.checksums += 1;
+                self.stats.decompressed_bytes += n as u64;
+                self.dsts = 0;
+                self.dste = n;
+                return Ok(true);
+            }
+            // Only reserved but skippable chunk types make it here. The
+            // others were rejected by read_chunk_header.
+            Err(b) => {
+                let opts = self.opts.as_mut();
+                if let Some(on_skippable) =
+                    opts.and_then(|o| o.on_skippable.as_mut())
+                {
+                    on_skippable(b, &self.src[CHUNK_HEADER_SIZE..end]);
+                }
+                self.stats.skippable_chunks += 1;
+            }
+            Ok(ChunkType::Padding) => self.stats.padding_chunks += 1,
+            // Valid stream identifiers are just dropped.
+            Ok(ChunkType::Stream) => {}
+        }
+        Ok(false)
+    }
+
+    /// Validates the chunk header in `src[0..4]` and returns the length of
+    /// the chunk that follows it.
+    ///
+    /// This checks everything about a chunk that can be checked without
+    /// reading its body.
+    fn read_chunk_header(&mut self) -> io::Result<usize> {
+        self.chunks += 1;
+        if let Some(max) = self.opts.as_ref().and_then(|o| o.max_chunks) {
+            if self.chunks > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "snappy: too many chunks in stream (max allowed: {})",
+                        max
+                    ),
+                ));
+            }
+        }
+        let len = check_chunk_header(&self.src, &mut self.read_stream_ident)?;
+        // A decoder created with `with_capacity` may have smaller buffers
+        // than the frame format permits.
+        if CHUNK_HEADER_SIZE + len > self.max_src {
+            return Err(io::Error::from(Error::UnsupportedChunkLength {
+                len: len as u64,
+                header: false,
+            }));
+        }
+        // The buffer may have been shrunk by `shrink_buffers`.
+        if CHUNK_HEADER_SIZE + len > self.src.len() {
+            self.src.resize(self.max_src, 0);
+        }
+        Ok(len)
+    }
+
+    /// Returns the options of this decoder for changing them, allocating
+    /// them first if they still have their defaults.
+    fn opts_mut(&mut self) -> &mut FrameDecoderOptions {
+        self.opts.get_or_insert_with(Box::default)
+    }
+}
+
+impl FrameDecoder<&[u8]> {
+    /// Decompresses all bytes in `input`, which must be a complete stream in
+    /// the Snappy frame format, and returns the decompressed bytes.
+    ///
+    /// This is a convenience for the common case of decompressing a buffer
+    /// that is already in memory. It's equivalent to
+    /// [`decompress_slice`](fn.decompress_slice.html), and returns an error
+    /// under the same circumstances.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use snap::{read::FrameDecoder, write::FrameEncoder};
+    ///
+    /// let compressed = FrameEncoder::compress_all(b"hello");
+    /// let got = FrameDecoder::decompress_all(&compressed).unwrap();
+    /// assert_eq!(b"hello", &*got);
+    /// ```
+    #[must_use = "the decompressed bytes are only returned"]
+    pub fn decompress_all(input: &[u8]) -> io::Result<Vec<u8>> {
+        decompress_slice(input)
+    }
+}
+
+impl<R: io::Read + io::Seek> FrameDecoder<R> {
+    /// Returns the number of decompressed bytes left to read from this
+    /// decoder.
+    ///
+    /// This is computed by walking the headers of the remaining chunks in
+    /// the underlying reader, seeking past their bodies, and then seeking
+    /// back to where the underlying reader was. Only the first few bytes of
+    /// each compressed chunk are read, so this is much faster than
+    /// decompressing the rest of the stream. This is useful for sizing a
+    /// buffer before reading everything, e.g., with
+    /// `std::io::Read::read_to_end`.
+    ///
+    /// The length is exact for a valid stream. But since checksums and the
+    /// bodies of compressed chunks aren't checked, a corrupt stream may
+    /// still fail to decode later. Similarly, a stream whose last chunk is
+    /// truncated isn't detected as such.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if seeking or reading from the underlying reader
+    /// fails, or if an invalid chunk header is found.
+    pub fn estimated_len(&mut self) -> io::Result<u64> {
+        let pos = self.r.stream_position()?;
+        // Any bytes of the current chunk read so far are in src, so the walk
+        // starts at the beginning of that chunk.
+        self.r.seek(io::SeekFrom::Start(pos - self.srcn as u64))?;
+        let len = walk_decompressed_len(&mut self.r, self.read_stream_ident);
+        self.r.seek(io::SeekFrom::Start(pos))?;
+        Ok((self.dste - self.dsts) as u64 + len?)
+    }
 }
 
 impl<R: io::Read> io::Read for FrameDecoder<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        macro_rules! fail {
-            ($err:expr) => {
-                return Err(io::Error::from($err))
-            };
+        loop {
+            if self.dsts < self.dste {
+                let len = cmp::min(self.dste - self.dsts, buf.len());
+                let dste = self.dsts.checked_add(len).unwrap();
+                buf[0..len].copy_from_slice(&self.dst[self.dsts..dste]);
+                self.dsts = dste;
+                return Ok(len);
+            }
+            if !self.read_next_chunk()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<R: io::Read> io::BufRead for FrameDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.dsts == self.dste {
+            if !self.read_next_chunk()? {
+                break;
+            }
+        }
+        Ok(&self.dst[self.dsts..self.dste])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.dsts = cmp::min(self.dste, self.dsts + amt);
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for FrameDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameDecoder")
+            .field("r", &self.r)
+            .field("dec", &self.dec)
+            .field("checksummer", &self.checksummer)
+            .field("src", &"[...]")
+            .field("max_src", &self.max_src)
+            .field("srcn", &self.srcn)
+            .field("chunk_len", &self.chunk_len)
+            .field("dst", &"[...]")
+            .field("max_dst", &self.max_dst)
+            .field("dsts", &self.dsts)
+            .field("dste", &self.dste)
+            .field("read_stream_ident", &self.read_stream_ident)
+            .field("chunks", &self.chunks)
+            .field("compressed_pos", &self.compressed_pos)
+            .field("opts", &self.opts)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl fmt::Debug for FrameDecoderOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameDecoderOptions")
+            .field("max_chunks", &self.max_chunks)
+            .field("on_skippable", &self.on_skippable.as_ref().map(|_| "..."))
+            .field(
+                "on_checksum_error",
+                &self.on_checksum_error.as_ref().map(|_| "..."),
+            )
+            .finish()
+    }
+}
+
+/// A reader that writes every byte it reads to a writer.
+///
+/// This is created by
+/// [`FrameDecoder::with_tee`](struct.FrameDecoder.html#method.with_tee).
+pub struct Tee<R, T> {
+    rdr: R,
+    tee: T,
+}
+
+impl<R, T> Tee<R, T> {
+    /// Gets a reference to the reader that bytes are read from.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Gets a reference to the writer that bytes are written to.
+    pub fn tee(&self) -> &T {
+        &self.tee
+    }
+
+    /// Returns the reader and the writer.
+    pub fn into_inner(self) -> (R, T) {
+        (self.rdr, self.tee)
+    }
+}
+
+impl<R: io::Read, T: io::Write> io::Read for Tee<R, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.rdr.read(buf)?;
+        self.tee.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+impl<R: fmt::Debug, T: fmt::Debug> fmt::Debug for Tee<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tee")
+            .field("rdr", &self.rdr)
+            .field("tee", &self.tee)
+            .finish()
+    }
+}
+
+/// A reader that decompresses at most a fixed number of bytes, and fails if
+/// the stream has more.
+///
+/// This is created by
+/// [`FrameDecoder::take_limited`](struct.FrameDecoder.html#method.take_limited).
+/// When the stream decompresses to more bytes than permitted, reads return
+/// all of the permitted bytes, after which the next read returns an error
+/// with kind `std::io::ErrorKind::InvalidData`.
+pub struct LimitedDecoder<R: io::Read> {
+    dec: FrameDecoder<R>,
+    /// The maximum number of decompressed bytes permitted.
+    max: u64,
+    /// The number of decompressed bytes that may still be read.
+    remaining: u64,
+}
+
+impl<R: io::Read> LimitedDecoder<R> {
+    /// Returns the number of decompressed bytes that may still be read
+    /// before the limit is reached.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Gets a reference to the underlying reader in this decoder.
+    pub fn get_ref(&self) -> &R {
+        self.dec.get_ref()
+    }
+
+    /// Gets the decoder wrapped by this reader.
+    pub fn into_inner(self) -> FrameDecoder<R> {
+        self.dec
+    }
+}
+
+impl<R: io::Read> io::Read for LimitedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
         }
-        loop {
-            if self.dsts < self.dste {
-                let len = cmp::min(self.dste - self.dsts, buf.len());
-                let dste = self.dsts.checked_add(len).unwrap();
-                buf[0..len].copy_from_slice(&self.dst[self.dsts..dste]);
-                self.dsts = dste;
-                return Ok(len);
-            }
-            if !read_exact_eof(&mut self.r, &mut self.src[0..4])? {
+        if self.remaining == 0 {
+            // The limit has been reached, so this is the end only if the
+            // stream has no more bytes to give.
+            if io::BufRead::fill_buf(&mut self.dec)?.is_empty() {
                 return Ok(0);
             }
-            let ty = ChunkType::from_u8(self.src[0]);
-            if !self.read_stream_ident {
-                if ty != Ok(ChunkType::Stream) {
-                    fail!(Error::StreamHeader { byte: self.src[0] });
-                }
-                self.read_stream_ident = true;
-            }
-            let len64 = bytes::read_u24_le(&self.src[1..]) as u64;
-            if len64 > self.src.len() as u64 {
-                fail!(Error::UnsupportedChunkLength {
-                    len: len64,
-                    header: false,
-                });
-            }
-            let len = len64 as usize;
-            match ty {
-                Err(b) if 0x02 <= b && b <= 0x7F => {
-                    // Spec says that chunk types 0x02-0x7F are reserved and
-                    // conformant decoders must return an error.
-                    fail!(Error::UnsupportedChunkType { byte: b });
-                }
-                Err(b) if 0x80 <= b && b <= 0xFD => {
-                    // Spec says that chunk types 0x80-0xFD are reserved but
-                    // skippable.
-                    self.r.read_exact(&mut self.src[0..len])?;
-                }
-                Err(b) => {
-                    // Can never happen. 0x02-0x7F and 0x80-0xFD are handled
-                    // above in the error case. That leaves 0x00, 0x01, 0xFE
-                    // and 0xFF, each of which correspond to one of the four
-                    // defined chunk types.
-                    unreachable!("BUG: unhandled chunk type: {}", b);
-                }
-                Ok(ChunkType::Padding) => {
-                    // Just read and move on.
-                    self.r.read_exact(&mut self.src[0..len])?;
-                }
-                Ok(ChunkType::Stream) => {
-                    if len != STREAM_BODY.len() {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: len64,
-                            header: true,
-                        })
-                    }
-                    self.r.read_exact(&mut self.src[0..len])?;
-                    if &self.src[0..len] != STREAM_BODY {
-                        fail!(Error::StreamHeaderMismatch {
-                            bytes: self.src[0..len].to_vec(),
-                        });
-                    }
-                }
-                Ok(ChunkType::Uncompressed) => {
-                    if len < 4 {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: len as u64,
-                            header: false,
-                        });
-                    }
-                    let expected_sum = bytes::io_read_u32_le(&mut self.r)?;
-                    let n = len - 4;
-                    if n > self.dst.len() {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: n as u64,
-                            header: false,
-                        });
-                    }
-                    self.r.read_exact(&mut self.dst[0..n])?;
-                    let got_sum =
-                        self.checksummer.crc32c_masked(&self.dst[0..n]);
-                    if expected_sum != got_sum {
-                        fail!(Error::Checksum {
-                            expected: expected_sum,
-                            got: got_sum,
-                        });
-                    }
-                    self.dsts = 0;
-                    self.dste = n;
-                }
-                Ok(ChunkType::Compressed) => {
-                    if len < 4 {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: len as u64,
-                            header: false,
-                        });
-                    }
-                    let expected_sum = bytes::io_read_u32_le(&mut self.r)?;
-                    let sn = len - 4;
-                    if sn > self.src.len() {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: len64,
-                            header: false,
-                        });
-                    }
-                    self.r.read_exact(&mut self.src[0..sn])?;
-                    let dn = decompress_len(&self.src)?;
-                    if dn > self.dst.len() {
-                        fail!(Error::UnsupportedChunkLength {
-                            len: dn as u64,
-                            header: false,
-                        });
-                    }
-                    self.dec
-                        .decompress(&self.src[0..sn], &mut self.dst[0..dn])?;
-                    let got_sum =
-                        self.checksummer.crc32c_masked(&self.dst[0..dn]);
-                    if expected_sum != got_sum {
-                        fail!(Error::Checksum {
-                            expected: expected_sum,
-                            got: got_sum,
-                        });
-                    }
-                    self.dsts = 0;
-                    self.dste = dn;
-                }
-            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snappy: stream decompresses to too many bytes \
+                     (max allowed: {})",
+                    self.max
+                ),
+            ));
         }
+        let len = cmp::min(buf.len() as u64, self.remaining) as usize;
+        let n = self.dec.read(&mut buf[..len])?;
+        self.remaining -= n as u64;
+        Ok(n)
     }
 }
 
-impl<R: fmt::Debug + io::Read> fmt::Debug for FrameDecoder<R> {
+impl<R: fmt::Debug + io::Read> fmt::Debug for LimitedDecoder<R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("FrameDecoder")
-            .field("r", &self.r)
+        f.debug_struct("LimitedDecoder")
             .field("dec", &self.dec)
-            .field("checksummer", &self.checksummer)
-            .field("src", &"[...]")
-            .field("dst", &"[...]")
-            .field("dsts", &self.dsts)
-            .field("dste", &self.dste)
-            .field("read_stream_ident", &self.read_stream_ident)
+            .field("max", &self.max)
+            .field("remaining", &self.remaining)
             .finish()
     }
 }
@@ -269,6 +1377,15 @@ impl<R: fmt::Debug + io::Read> fmt::Debug for FrameDecoder<R> {
 /// Unlike `FrameDecoder`, this will attempt to make large reads roughly
 /// equivalent to the size of a single Snappy block. Therefore, callers may not
 /// benefit from using a buffered reader.
+///
+/// As is conventional for `std::io::Read`, a read of `0` bytes from the
+/// underlying reader is taken to mean that it is exhausted. This ends the
+/// compressed stream for good: the underlying reader is never read from
+/// again, and every subsequent read from this encoder returns `0` bytes
+/// without emitting any more chunks. Therefore, underlying readers that may
+/// return `0` bytes before they're actually exhausted shouldn't be used. If
+/// no bytes were read at all, then the compressed stream is empty and doesn't
+/// even contain the stream identifier.
 pub struct FrameEncoder<R: io::Read> {
     /// Internally, we split `FrameEncoder` in two to keep the borrow checker
     /// happy. The `inner` member contains everything that `read_frame` needs
@@ -293,8 +1410,13 @@ struct Inner<R: io::Read> {
     checksummer: CheckSummer,
     /// Data taken from the underlying `r`, and not yet compressed.
     src: Vec<u8>,
+    /// The maximum number of bytes requested from `r` in a single read. This
+    /// is always in the range `[1, MAX_BLOCK_SIZE]`.
+    read_size: usize,
     /// Have we written the standard snappy header to `dst` yet?
     wrote_stream_ident: bool,
+    /// Has `r` returned EOF? Once it has, it's never read from again.
+    eof: bool,
 }
 
 impl<R: io::Read> FrameEncoder<R> {
@@ -306,7 +1428,9 @@ impl<R: io::Read> FrameEncoder<R> {
                 enc: Encoder::new(),
                 checksummer: CheckSummer::new(),
                 src: vec![0; MAX_BLOCK_SIZE],
+                read_size: MAX_BLOCK_SIZE,
                 wrote_stream_ident: false,
+                eof: false,
             },
             dst: vec![0; MAX_READ_FRAME_ENCODER_BLOCK_SIZE],
             dsts: 0,
@@ -327,6 +1451,70 @@ impl<R: io::Read> FrameEncoder<R> {
         &mut self.inner.r
     }
 
+    /// Set the maximum number of bytes requested from the underlying reader
+    /// in a single read.
+    ///
+    /// Since each read from the underlying reader is compressed into its own
+    /// chunk, this also bounds the number of uncompressed bytes in each chunk
+    /// emitted by this encoder. Smaller sizes may be useful to align reads to
+    /// a device's block size, at the cost of compression ratio.
+    ///
+    /// `size` is clamped to the range `[1, 65536]`, where `65536` is the
+    /// maximum block size of the Snappy frame format. This is also the
+    /// default.
+    pub fn set_read_size(&mut self, size: usize) {
+        self.inner.read_size = size.clamp(1, MAX_BLOCK_SIZE);
+    }
+
+    /// Set the constant used to mask the checksum of each chunk, in place of
+    /// the one defined by the Snappy frame format.
+    ///
+    /// **The resulting stream doesn't conform to the Snappy frame format.**
+    /// See
+    /// [`write::FrameEncoder::set_crc_mask_constant`](../write/struct.FrameEncoder.html#method.set_crc_mask_constant)
+    /// for details.
+    pub fn set_crc_mask_constant(&mut self, delta: u32) {
+        self.inner.checksummer.set_mask_constant(delta);
+    }
+
+    /// Converts this encoder into an iterator over compressed chunks.
+    ///
+    /// Each item yielded corresponds to one read from the underlying reader,
+    /// compressed into a single Snappy frame chunk (including its header).
+    /// The first item is also prefixed with the stream identifier. If some
+    /// compressed bytes were already buffered by this encoder but not yet
+    /// read, then they are yielded first.
+    ///
+    /// Concatenating all items yields the same bytes as reading from this
+    /// encoder. This is useful for producer/consumer setups where each chunk
+    /// is sent somewhere else, e.g., over a channel.
+    ///
+    /// After an error is yielded, the iterator is exhausted.
+    pub fn chunks(self) -> FrameChunks<R> {
+        FrameChunks { enc: self, done: false }
+    }
+
+    /// Reads the next compressed chunk into `out`, replacing its contents.
+    ///
+    /// This is like the iterator returned by
+    /// [`chunks`](#method.chunks), except the caller provides the buffer
+    /// that each chunk is written to. Reusing the same buffer for every
+    /// chunk avoids an allocation per chunk.
+    ///
+    /// `out` is always cleared first. If the underlying reader is exhausted,
+    /// then `out` is left empty and `false` is returned. Otherwise, `true`
+    /// is returned.
+    pub fn read_chunk_into(&mut self, out: &mut Vec<u8>) -> io::Result<bool> {
+        out.clear();
+        if self.dsts == self.dste {
+            self.dste = self.inner.read_frame(&mut self.dst)?;
+            self.dsts = 0;
+        }
+        out.extend_from_slice(&self.dst[self.dsts..self.dste]);
+        self.dsts = self.dste;
+        Ok(!out.is_empty())
+    }
+
     /// Read previously compressed data from `self.dst`, returning the number of
     /// bytes read. If `self.dst` is empty, returns 0.
     fn read_from_dst(&mut self, buf: &mut [u8]) -> usize {
@@ -362,6 +1550,53 @@ impl<R: io::Read> io::Read for FrameEncoder<R> {
     }
 }
 
+/// An iterator over compressed Snappy frame chunks.
+///
+/// This is created by
+/// [`read::FrameEncoder::chunks`](struct.FrameEncoder.html#method.chunks).
+pub struct FrameChunks<R: io::Read> {
+    /// The encoder that produces each chunk.
+    enc: FrameEncoder<R>,
+    /// Set once the underlying reader is exhausted or an error occurs.
+    done: bool,
+}
+
+impl<R: io::Read> Iterator for FrameChunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+        let enc = &mut self.enc;
+        if enc.dsts < enc.dste {
+            let chunk = enc.dst[enc.dsts..enc.dste].to_vec();
+            enc.dsts = enc.dste;
+            return Some(Ok(chunk));
+        }
+        match enc.inner.read_frame(&mut enc.dst) {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+            Ok(n) => Some(Ok(enc.dst[..n].to_vec())),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for FrameChunks<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameChunks")
+            .field("enc", &self.enc)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
 impl<R: io::Read> Inner<R> {
     /// Read from `self.r`, and create a new frame, writing it to `dst`, which
     /// must be at least `MAX_READ_FRAME_ENCODER_BLOCK_SIZE` bytes in size.
@@ -375,8 +1610,18 @@ impl<R: io::Read> Inner<R> {
         // more surprising. In general, io::Read implementations should try to
         // fill the caller's buffer as much as they can, so this seems like the
         // better choice.
-        let nread = self.r.read(&mut self.src)?;
+        //
+        // Since read_size never exceeds MAX_BLOCK_SIZE, a single read always
+        // fits in a single chunk, which is why dst only needs room for one.
+        // Reading more than that at once would require emitting several
+        // chunks here, and sizing dst for all of them.
+        debug_assert!(self.read_size <= MAX_BLOCK_SIZE);
+        if self.eof {
+            return Ok(0);
+        }
+        let nread = self.r.read(&mut self.src[..self.read_size])?;
         if nread == 0 {
+            self.eof = true;
             return Ok(0);
         }
 
@@ -427,29 +1672,532 @@ impl<R: fmt::Debug + io::Read> fmt::Debug for Inner<R> {
             .field("enc", &self.enc)
             .field("checksummer", &self.checksummer)
             .field("src", &"[...]")
+            .field("read_size", &self.read_size)
             .field("wrote_stream_ident", &self.wrote_stream_ident)
+            .field("eof", &self.eof)
+            .finish()
+    }
+}
+
+/// A reader for decompressing a sequence of Snappy sources as one stream.
+///
+/// Each source is a reader paired with the
+/// [`Format`](../format/enum.Format.html) it is encoded with. Sources are
+/// decompressed in the order given, and the decompressed bytes of all of them
+/// are presented as a single `std::io::Read` implementation.
+///
+/// Sources in the Snappy frame format are decompressed incrementally with a
+/// [`read::FrameDecoder`](struct.FrameDecoder.html). Since the raw Snappy
+/// format does not support streaming, a raw source is read to the end and
+/// decompressed in one go when it is reached.
+pub struct ChainDecoder<R: io::Read> {
+    /// The sources that have not been started yet.
+    sources: std::vec::IntoIter<(Format, R)>,
+    /// The source currently being read from, if any.
+    cur: Option<ChainSource<R>>,
+    /// A raw Snappy decoder that we reuse for every raw source.
+    dec: Decoder,
+}
+
+/// A single source in a `ChainDecoder` that is being read from.
+enum ChainSource<R: io::Read> {
+    Frame(FrameDecoder<R>),
+    Raw(io::Cursor<Vec<u8>>),
+}
+
+impl<R: io::Read> ChainDecoder<R> {
+    /// Create a new reader that decompresses each of the given sources in
+    /// order.
+    pub fn new<I>(sources: I) -> ChainDecoder<R>
+    where
+        I: IntoIterator<Item = (Format, R)>,
+    {
+        ChainDecoder {
+            sources: sources.into_iter().collect::<Vec<_>>().into_iter(),
+            cur: None,
+            dec: Decoder::new(),
+        }
+    }
+
+    /// Starts reading from the next source, if one exists. If there are no
+    /// more sources, then this returns false.
+    fn next_source(&mut self) -> io::Result<bool> {
+        let (format, mut rdr) = match self.sources.next() {
+            None => {
+                self.cur = None;
+                return Ok(false);
+            }
+            Some(source) => source,
+        };
+        self.cur = Some(match format {
+            Format::Frame => ChainSource::Frame(FrameDecoder::new(rdr)),
+            Format::Raw => {
+                let mut src = vec![];
+                rdr.read_to_end(&mut src)?;
+                let dst = self.dec.decompress_vec(&src)?;
+                ChainSource::Raw(io::Cursor::new(dst))
+            }
+        });
+        Ok(true)
+    }
+}
+
+impl<R: io::Read> io::Read for ChainDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = match self.cur {
+                None => 0,
+                Some(ChainSource::Frame(ref mut rdr)) => rdr.read(buf)?,
+                Some(ChainSource::Raw(ref mut rdr)) => rdr.read(buf)?,
+            };
+            if n > 0 {
+                return Ok(n);
+            }
+            if !self.next_source()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for ChainDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChainDecoder")
+            .field("sources", &self.sources)
+            .field("cur", &self.cur)
+            .field("dec", &self.dec)
+            .finish()
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for ChainSource<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainSource::Frame(ref rdr) => {
+                f.debug_tuple("Frame").field(rdr).finish()
+            }
+            ChainSource::Raw(_) => {
+                f.debug_tuple("Raw").field(&"[...]").finish()
+            }
+        }
+    }
+}
+
+/// A reader for decompressing a sequence of concatenated blocks in the raw
+/// Snappy format.
+///
+/// Each block is a complete raw Snappy block, as produced by
+/// [`raw::Encoder`](../raw/struct.Encoder.html), with its own header, and
+/// the next block starts immediately after the previous one ends. There is
+/// no other framing. The decompressed bytes of all blocks are presented as a
+/// single `std::io::Read` implementation.
+///
+/// Since a raw block doesn't record its compressed length, its end is only
+/// known once it has been decompressed. Each block is therefore read and
+/// decompressed as a whole, so memory usage is proportional to the size of
+/// the largest block. Reading stops at the first EOF of the underlying
+/// reader that falls between two blocks. An EOF in the middle of a block
+/// results in an error.
+pub struct RawConcatDecoder<R: io::Read> {
+    /// The underlying reader.
+    r: R,
+    /// A raw Snappy decoder that we reuse for every block.
+    dec: Decoder,
+    /// Compressed bytes read from `r` that haven't been decompressed yet
+    /// start at `src[srcs..]`.
+    src: Vec<u8>,
+    /// The start of the bytes in `src` that haven't been decompressed yet.
+    srcs: usize,
+    /// Set when `r` has returned EOF.
+    eof: bool,
+    /// The decompressed bytes of the current block.
+    dst: Vec<u8>,
+    /// The number of bytes in `dst` that have been returned to the caller.
+    dsts: usize,
+}
+
+impl<R: io::Read> RawConcatDecoder<R> {
+    /// Create a new reader that decompresses the concatenated raw Snappy
+    /// blocks in `rdr`.
+    pub fn new(rdr: R) -> RawConcatDecoder<R> {
+        RawConcatDecoder {
+            r: rdr,
+            dec: Decoder::new(),
+            src: vec![],
+            srcs: 0,
+            eof: false,
+            dst: vec![],
+            dsts: 0,
+        }
+    }
+
+    /// Gets a reference to the underlying reader in this decoder.
+    pub fn get_ref(&self) -> &R {
+        &self.r
+    }
+
+    /// Gets the underlying reader of this decoder.
+    ///
+    /// Note that the underlying reader may have been read past the end of
+    /// the blocks decompressed so far.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    /// Reads from the underlying reader until at least `len` undecompressed
+    /// bytes are buffered in `src`, or until EOF.
+    ///
+    /// At most `MAX_BLOCK_SIZE` more bytes are read at a time, so that `src`
+    /// only grows as compressed bytes actually arrive, no matter how long a
+    /// corrupt header claims a block is. Callers that need more call this
+    /// again.
+    fn fill_src(&mut self, len: usize) -> io::Result<()> {
+        if self.srcs > 0 {
+            self.src.drain(..self.srcs);
+            self.srcs = 0;
+        }
+        let mut pos = self.src.len();
+        if self.eof || pos >= len {
+            return Ok(());
+        }
+        let len = cmp::min(len, pos + MAX_BLOCK_SIZE);
+        self.src.resize(len, 0);
+        let full = fill(&mut self.r, &mut self.src, &mut pos, len);
+        self.src.truncate(pos);
+        self.eof = !full?;
+        Ok(())
+    }
+
+    /// Decompresses the next block into `dst`. If there are no more blocks,
+    /// then this returns false.
+    fn next_block(&mut self) -> io::Result<bool> {
+        // A varint is never longer than 10 bytes.
+        self.fill_src(10)?;
+        if self.src.is_empty() {
+            return Ok(false);
+        }
+        let (dlen, hlen) = match decompress_len(&self.src) {
+            Ok(dlen) => (dlen, bytes::read_varu64(&self.src).1),
+            Err(err) => return Err(err.into()),
+        };
+        // A conforming encoder never produces a block longer than this, so
+        // reading this much up front usually suffices. Otherwise, e.g., for
+        // big blocks, decompression picks up where it stopped each time more
+        // of the bytes that it's missing have been read.
+        self.fill_src(hlen.saturating_add(max_compress_len(dlen).max(dlen)))?;
+        self.dst.clear();
+        self.dsts = 0;
+        let mut progress = Progress::default();
+        loop {
+            let body = &self.src[hlen..];
+            let more = !self.eof;
+            match self.dec.decompress_partial(
+                body,
+                dlen,
+                &mut self.dst,
+                &mut progress,
+                more,
+            )? {
+                Partial::Done(len) => {
+                    self.srcs = hlen + len;
+                    return Ok(true);
+                }
+                Partial::Needs(n) => {
+                    self.fill_src(self.src.len().saturating_add(n))?
+                }
+            }
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for RawConcatDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.dsts == self.dst.len() {
+            if !self.next_block()? {
+                return Ok(0);
+            }
+        }
+        let n = cmp::min(buf.len(), self.dst.len() - self.dsts);
+        buf[..n].copy_from_slice(&self.dst[self.dsts..self.dsts + n]);
+        self.dsts += n;
+        Ok(n)
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for RawConcatDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawConcatDecoder")
+            .field("r", &self.r)
+            .field("dec", &self.dec)
+            .field("src", &"[...]")
+            .field("srcs", &self.srcs)
+            .field("eof", &self.eof)
+            .field("dst", &"[...]")
+            .field("dsts", &self.dsts)
+            .finish()
+    }
+}
+
+/// A reader that decompresses its source if it's a stream in the Snappy frame
+/// format, and otherwise passes it through unchanged.
+///
+/// This is useful for programs that accept both compressed and uncompressed
+/// input, e.g., on `stdin`, without requiring the user to say which it is.
+/// Whether the source is compressed is determined by reading its first few
+/// bytes and comparing them with the stream identifier that starts every
+/// Snappy frame formatted stream. In either case, none of the bytes read for
+/// this purpose are lost.
+///
+/// Since plain data could, in principle, start with the stream identifier,
+/// this should only be used when that's known not to happen, e.g., for
+/// text.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Read;
+///
+/// let mut compressed = vec![];
+/// snap::write::compress_into_vec(b"hello", &mut compressed).unwrap();
+/// for src in [&compressed[..], &b"hello"[..]] {
+///     let mut rdr = snap::read::AutoDecoder::new(src);
+///     let mut got = vec![];
+///     rdr.read_to_end(&mut got).unwrap();
+///     assert_eq!(b"hello", &*got);
+/// }
+/// ```
+pub struct AutoDecoder<R: io::Read> {
+    /// The decoder used when the source is compressed. It owns the source
+    /// even when it isn't, in which case it's read from directly.
+    dec: FrameDecoder<R>,
+    /// Whether the source is compressed, or None if that isn't known yet.
+    compressed: Option<bool>,
+    /// The bytes read from the start of the source to determine whether it's
+    /// compressed.
+    peeked: [u8; STREAM_IDENTIFIER.len()],
+    /// The number of bytes in `peeked`.
+    peekn: usize,
+    /// The number of bytes in `peeked` that have been read by the caller when
+    /// the source isn't compressed.
+    peeks: usize,
+}
+
+impl<R: io::Read> AutoDecoder<R> {
+    /// Create a new reader that decompresses `rdr` if it's in the Snappy
+    /// frame format, and otherwise passes it through unchanged.
+    pub fn new(rdr: R) -> AutoDecoder<R> {
+        AutoDecoder {
+            dec: FrameDecoder::new(rdr),
+            compressed: None,
+            peeked: [0; STREAM_IDENTIFIER.len()],
+            peekn: 0,
+            peeks: 0,
+        }
+    }
+
+    /// Returns true if the source is in the Snappy frame format.
+    ///
+    /// If this isn't known yet, then the first few bytes of the source are
+    /// read to find out. They are still returned by subsequent reads.
+    pub fn is_compressed(&mut self) -> io::Result<bool> {
+        if let Some(compressed) = self.compressed {
+            return Ok(compressed);
+        }
+        let end = self.peeked.len();
+        // An EOF before the end means the source is too short to be
+        // compressed, which is caught by the comparison below.
+        fill(&mut self.dec.r, &mut self.peeked, &mut self.peekn, end)?;
+        let compressed = self.peeked[..self.peekn] == *STREAM_IDENTIFIER;
+        if compressed {
+            // Put the stream identifier back, so that it's read like any
+            // other chunk.
+            self.dec.src[..end].copy_from_slice(STREAM_IDENTIFIER);
+            self.dec.srcn = end;
+        }
+        self.compressed = Some(compressed);
+        Ok(compressed)
+    }
+
+    /// Gets a reference to the underlying reader in this decoder.
+    pub fn get_ref(&self) -> &R {
+        &self.dec.r
+    }
+}
+
+impl<R: io::Read> io::Read for AutoDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.is_compressed()? {
+            return self.dec.read(buf);
+        }
+        if self.peeks < self.peekn {
+            let peeked = &self.peeked[self.peeks..self.peekn];
+            let n = cmp::min(peeked.len(), buf.len());
+            buf[..n].copy_from_slice(&peeked[..n]);
+            self.peeks += n;
+            return Ok(n);
+        }
+        self.dec.r.read(buf)
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for AutoDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AutoDecoder")
+            .field("dec", &self.dec)
+            .field("compressed", &self.compressed)
+            .field("peeked", &&self.peeked[..self.peekn])
+            .field("peeks", &self.peeks)
+            .finish()
+    }
+}
+
+/// A reader that decompresses a Snappy frame formatted stream prefixed by its
+/// length.
+///
+/// The stream must be prefixed by its compressed length as an 8 byte little
+/// endian integer, as written by
+/// [`write::LengthPrefixedEncoder`](../write/struct.LengthPrefixedEncoder.html).
+/// Exactly that many bytes are read from the underlying reader and
+/// decompressed, so that the underlying reader is left positioned just past
+/// the stream once it has been read in its entirety. This makes it possible
+/// to read several length prefixed streams one after the other.
+///
+/// If the underlying reader ends before the stream does, then reading
+/// returns an error, just like it does for a truncated stream.
+pub struct LengthPrefixedDecoder<R: io::Read> {
+    /// The decoder for the stream, which can't read past its end.
+    dec: FrameDecoder<io::Take<R>>,
+    /// The compressed length of the stream, as read from its prefix.
+    len: u64,
+}
+
+impl<R: io::Read> LengthPrefixedDecoder<R> {
+    /// Create a new reader that decompresses the length prefixed stream at
+    /// the start of `rdr`.
+    ///
+    /// The length prefix is read immediately. If that fails, e.g., because
+    /// `rdr` is at EOF, then an error is returned.
+    pub fn new(mut rdr: R) -> io::Result<LengthPrefixedDecoder<R>> {
+        let mut prefix = [0; 8];
+        rdr.read_exact(&mut prefix)?;
+        let len = u64::from_le_bytes(prefix);
+        Ok(LengthPrefixedDecoder {
+            dec: FrameDecoder::new(rdr.take(len)),
+            len,
+        })
+    }
+
+    /// Returns the compressed length of the stream, not including its length
+    /// prefix.
+    pub fn compressed_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Gets a reference to the underlying reader in this decoder.
+    pub fn get_ref(&self) -> &R {
+        self.dec.get_ref().get_ref()
+    }
+
+    /// Gets the underlying reader of this decoder.
+    ///
+    /// If the stream has been read in its entirety, then the underlying
+    /// reader is positioned just past it.
+    pub fn into_inner(self) -> R {
+        self.dec.into_inner().into_inner()
+    }
+}
+
+impl<R: io::Read> io::Read for LengthPrefixedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.dec.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.dec.get_ref().limit() > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "length prefixed stream ended early",
+            ));
+        }
+        Ok(n)
+    }
+}
+
+impl<R: fmt::Debug + io::Read> fmt::Debug for LengthPrefixedDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LengthPrefixedDecoder")
+            .field("dec", &self.dec)
+            .field("len", &self.len)
             .finish()
     }
 }
 
-// read_exact_eof is like Read::read_exact, except it detects EOF
-// and returns Ok(false) instead of an error.
+// walk_decompressed_len returns the total number of bytes that the chunks
+// read from rdr decompress to, without decompressing them. The bodies of
+// chunks are skipped by seeking over them.
+//
+// read_stream_ident should be true if the stream identifier has already been
+// read from rdr.
+fn walk_decompressed_len<R: io::Read + io::Seek>(
+    rdr: &mut R,
+    mut read_stream_ident: bool,
+) -> io::Result<u64> {
+    // Enough room for a chunk header, a checksum and the longest varint that
+    // can start a compressed block.
+    let mut buf = [0; CHUNK_HEADER_AND_CRC_SIZE + 5];
+    let mut total = 0;
+    loop {
+        let mut n = 0;
+        if !fill(rdr, &mut buf, &mut n, CHUNK_HEADER_SIZE)? {
+            if n == 0 {
+                return Ok(total);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        }
+        let len = check_chunk_header(&buf, &mut read_stream_ident)?;
+        let mut skip = len;
+        match ChunkType::from_u8(buf[0]) {
+            Ok(ChunkType::Uncompressed) => total += (len - 4) as u64,
+            Ok(ChunkType::Compressed) => {
+                let end = cmp::min(CHUNK_HEADER_SIZE + len, buf.len());
+                if !fill(rdr, &mut buf, &mut n, end)? {
+                    return Err(truncated_chunk_error(&buf[..n]));
+                }
+                let block = &buf[CHUNK_HEADER_AND_CRC_SIZE..end];
+                total += decompress_len(block)? as u64;
+                skip -= end - CHUNK_HEADER_SIZE;
+            }
+            _ => {}
+        }
+        rdr.seek(io::SeekFrom::Current(skip as i64))?;
+    }
+}
+
+// fill reads from rdr into buf[*pos..end] until *pos reaches end, advancing
+// *pos as bytes are read. Reads that are interrupted are retried.
 //
-// If buf was read successfully, it returns Ok(true).
-fn read_exact_eof<R: io::Read>(
+// If rdr reaches EOF first, this returns Ok(false). If reading fails, then
+// the error is returned. In either case, the bytes read so far are kept and
+// *pos reflects them, so that calling this again resumes where it left off.
+fn fill<R: io::Read>(
     rdr: &mut R,
     buf: &mut [u8],
+    pos: &mut usize,
+    end: usize,
 ) -> io::Result<bool> {
-    match rdr.read(buf) {
-        // EOF
-        Ok(0) => Ok(false),
-        // Read everything w/ the read call
-        Ok(i) if i == buf.len() => Ok(true),
-        // There's some bytes left to fill, which can be deferred to read_exact
-        Ok(i) => {
-            rdr.read_exact(&mut buf[i..])?;
-            Ok(true)
-        }
-        Err(e) => Err(e),
+    while *pos < end {
+        match rdr.read(&mut buf[*pos..end]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => *pos += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
     }
+    Ok(true)
 }
@@ -1,26 +1,66 @@
 /*!
-This module provides a `std::io::Write` implementation:
+This module provides two `std::io::Write` implementations:
 
 - `write::FrameEncoder` wraps another `std::io::Write` implemenation, and
   compresses data encoded using the Snappy frame format. Use this if you have
   uncompressed data source and wish to write it as compressed data.
+- `write::FrameDecoder` wraps another `std::io::Write` implemenation, and
+  decompresses data encoded using the Snappy frame format. Use this if you
+  receive compressed data in pieces (e.g., from a socket) and wish to write
+  it out as uncompressed data.
 
-It would also be possible to provide a `write::FrameDecoder`, which decompresses
-data as it writes it, but it hasn't been implemented yet.
+It also provides [`write::compress_into_vec`](fn.compress_into_vec.html) as a
+convenience for compressing a buffer that is already in memory, and
+[`write::EncoderPool`](struct.EncoderPool.html) for doing the same from many
+threads without allocating new compression state for every buffer. To
+compress bytes that arrive in pieces into memory,
+[`write::VecEncoder`](struct.VecEncoder.html) appends to a `Vec<u8>` without
+going through `std::io::Write`.
+
+[`write::LengthPrefixedEncoder`](struct.LengthPrefixedEncoder.html) writes a
+stream prefixed by its length, so that readers can skip it without parsing
+its chunks.
 */
 
+use std::cmp;
 use std::fmt;
 use std::io::{self, Write};
+use std::mem;
+use std::sync::Mutex;
 
-use crate::compress::Encoder;
+use crate::bytes;
+use crate::compress::{Encoder, DEFAULT_SEARCH_SKIP, DEFAULT_STORE_DIVISOR};
 use crate::crc32::CheckSummer;
+use crate::decompress::Decoder;
 pub use crate::error::IntoInnerError;
 use crate::frame::{
-    compress_frame, CHUNK_HEADER_AND_CRC_SIZE, MAX_COMPRESS_BLOCK_SIZE,
-    STREAM_IDENTIFIER,
+    compress_frame, decode_chunk, max_frame_len, ChunkType, EncoderState,
+    VerifyCrc, CHUNK_HEADER_AND_CRC_SIZE, CHUNK_HEADER_SIZE,
+    MAX_COMPRESS_BLOCK_SIZE, STREAM_IDENTIFIER,
 };
 use crate::MAX_BLOCK_SIZE;
 
+/// Compresses all bytes in `input` using the Snappy frame format and appends
+/// the result to `output`.
+///
+/// Before compressing, this reserves enough capacity in `output` for the
+/// largest possible compressed stream. Therefore, `output` is reallocated at
+/// most once, which avoids incremental reallocations for large inputs. If
+/// `output` already has enough spare capacity, then it is never reallocated.
+///
+/// The bytes appended are identical to what
+/// [`write::FrameEncoder`](struct.FrameEncoder.html)
+/// produces when `input` is written to it in one call.
+pub fn compress_into_vec(
+    input: &[u8],
+    output: &mut Vec<u8>,
+) -> io::Result<()> {
+    output.reserve(max_frame_len(input.len()));
+    let mut wtr = FrameEncoder::new(output);
+    wtr.write_all(input)?;
+    wtr.flush()
+}
+
 /// A writer for compressing a Snappy stream.
 ///
 /// This `FrameEncoder` wraps any other writer that implements `io::Write`.
@@ -37,7 +77,7 @@ pub struct FrameEncoder<W: io::Write> {
     /// Our main internal state, split out for borrowck reasons (happily paid).
     ///
     /// Also, it's an `Option` so we can move out of it even though
-    /// `FrameEncoder` impls `Drop`.
+    /// `FrameEncoder` impls `Drop`. Use `inner` or `inner_mut` to get at it.
     inner: Option<Inner<W>>,
     /// Our buffer of uncompressed bytes. This isn't part of `inner` because
     /// we may write bytes directly from the caller if the given buffer was
@@ -66,6 +106,89 @@ struct Inner<W> {
     /// Space for writing the header of a chunk before writing it to the
     /// underlying writer.
     chunk_header: [u8; 8],
+    /// The total number of uncompressed bytes that have been compressed and
+    /// written to the underlying writer.
+    total_in: u64,
+    /// The total number of bytes written to the underlying writer, including
+    /// the stream identifier and chunk headers.
+    total_out: u64,
+    /// The maximum value of total_out, if any. Chunks that would exceed it
+    /// aren't written.
+    output_limit: Option<u64>,
+    /// When true, flushing writes the stream identifier even if no data has
+    /// been written yet.
+    emit_empty_stream: bool,
+    /// The minimum number of bytes written for each data chunk, including
+    /// any padding chunks that follow it.
+    min_chunk_total: usize,
+}
+
+/// A hint about how to trade compression speed for compression ratio, given
+/// to [`FrameEncoder::write_with_hint`](struct.FrameEncoder.html#method.write_with_hint).
+///
+/// Snappy has no compression levels as such. Instead, a hint adjusts how
+/// hard the encoder searches for matches and how much space compressing a
+/// chunk has to save before it's kept compressed rather than stored as is.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CompressHint {
+    /// Search for matches less thoroughly, and store chunks uncompressed
+    /// unless compressing them saves at least a quarter of their size. This
+    /// is useful for data that is known to compress poorly, e.g., media.
+    Fast,
+    /// The settings used by `FrameEncoder` when writing through
+    /// `std::io::Write`.
+    #[default]
+    Default,
+    /// Search for matches as thoroughly as possible, and keep chunks
+    /// compressed whenever that saves any space at all. This is useful for
+    /// data that is known to compress well, e.g., text.
+    Best,
+}
+
+impl CompressHint {
+    /// Returns the search skip and store divisor for the raw encoder.
+    fn settings(self) -> (u32, usize) {
+        match self {
+            CompressHint::Fast => (128, 4),
+            CompressHint::Default => {
+                (DEFAULT_SEARCH_SKIP, DEFAULT_STORE_DIVISOR)
+            }
+            CompressHint::Best => (1, usize::MAX),
+        }
+    }
+}
+
+/// A snapshot of the state of a [`FrameEncoder`](struct.FrameEncoder.html),
+/// created by
+/// [`FrameEncoder::checkpoint`](struct.FrameEncoder.html#method.checkpoint).
+///
+/// Since every chunk is compressed independently of all others, a checkpoint
+/// only needs to record the uncompressed bytes that are buffered but not yet
+/// written, and whether the stream identifier has been written.
+#[derive(Clone, Debug)]
+pub struct EncoderCheckpoint {
+    src: Vec<u8>,
+    wrote_stream_ident: bool,
+    total_in: u64,
+    total_out: u64,
+}
+
+impl EncoderCheckpoint {
+    /// Returns the uncompressed bytes that were buffered by the encoder, but
+    /// not yet written to the underlying writer, when this checkpoint was
+    /// created.
+    pub fn buffered(&self) -> &[u8] {
+        &self.src
+    }
+
+    /// Returns the total number of bytes that had been written to the
+    /// underlying writer when this checkpoint was created.
+    ///
+    /// After restoring this checkpoint, the underlying writer should be
+    /// truncated to this length.
+    pub fn written(&self) -> u64 {
+        self.total_out
+    }
 }
 
 impl<W: io::Write> FrameEncoder<W> {
@@ -79,17 +202,99 @@ impl<W: io::Write> FrameEncoder<W> {
                 dst: vec![0; MAX_COMPRESS_BLOCK_SIZE],
                 wrote_stream_ident: false,
                 chunk_header: [0; CHUNK_HEADER_AND_CRC_SIZE],
+                total_in: 0,
+                total_out: 0,
+                output_limit: None,
+                emit_empty_stream: false,
+                min_chunk_total: 0,
             }),
             src: Vec::with_capacity(MAX_BLOCK_SIZE),
         }
     }
 
+    /// Create a new writer for streaming Snappy compression that writes the
+    /// stream identifier to `wtr` immediately.
+    ///
+    /// Normally, the stream identifier is only written along with the first
+    /// compressed chunk. Writing it eagerly is useful for protocols where
+    /// the receiver expects it as soon as a connection is established,
+    /// before any data is sent.
+    ///
+    /// If writing the stream identifier fails, then an error is returned.
+    pub fn new_eager(wtr: W) -> io::Result<FrameEncoder<W>> {
+        let mut enc = FrameEncoder::new(wtr);
+        enc.inner_mut().write_stream_ident()?;
+        Ok(enc)
+    }
+
+    /// Compresses the bytes produced by `fill` into a new stream written to
+    /// `wtr`, and returns `wtr` once all of them have been written.
+    ///
+    /// This inverts control compared to writing through `std::io::Write`:
+    /// rather than pushing bytes into the encoder, the encoder repeatedly
+    /// asks `fill` for more. Each time, `fill` is given an empty buffer to
+    /// append the next bytes to, and returns `false` once there are no more
+    /// bytes after the ones it appended. Each buffer is compressed into its
+    /// own chunk, so ideally `fill` appends about a block's worth (65536
+    /// bytes) at a time. Longer buffers are split into several chunks, while
+    /// empty buffers are skipped.
+    ///
+    /// The underlying stream is not flushed.
+    ///
+    /// # Errors
+    ///
+    /// Any error returned by `fill` or by writing to `wtr` is returned as is,
+    /// in which case `wtr` is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use snap::{read::FrameDecoder, write::FrameEncoder};
+    ///
+    /// let mut records = vec!["one\n", "two\n", "three\n"].into_iter();
+    /// let compressed = FrameEncoder::compress_from(vec![], |buf| {
+    ///     match records.next() {
+    ///         None => Ok(false),
+    ///         Some(record) => {
+    ///             buf.extend_from_slice(record.as_bytes());
+    ///             Ok(true)
+    ///         }
+    ///     }
+    /// })
+    /// .unwrap();
+    /// let got = FrameDecoder::decompress_all(&compressed).unwrap();
+    /// assert_eq!(b"one\ntwo\nthree\n", &*got);
+    /// ```
+    pub fn compress_from<F>(wtr: W, mut fill: F) -> io::Result<W>
+    where
+        F: FnMut(&mut Vec<u8>) -> io::Result<bool>,
+    {
+        let mut enc = FrameEncoder::new(wtr);
+        let mut buf = Vec::with_capacity(MAX_BLOCK_SIZE);
+        loop {
+            buf.clear();
+            let more = fill(&mut buf)?;
+            if !buf.is_empty() {
+                enc.inner_mut().write(&buf)?;
+            }
+            if !more {
+                break;
+            }
+        }
+        enc.into_inner().map_err(IntoInnerError::into_error)
+    }
+
     /// Returns the underlying stream, consuming and flushing this writer.
     ///
+    /// Any buffered bytes are written to the underlying stream, but the
+    /// underlying stream itself is not flushed.
+    ///
     /// If flushing the writer caused an error, then an `IntoInnerError` is
     /// returned, which contains both the writer and the original writer.
+    #[allow(clippy::result_large_err)]
+    #[must_use = "flushing errors are only reported by the returned Result"]
     pub fn into_inner(mut self) -> Result<W, IntoInnerError<FrameEncoder<W>>> {
-        match self.flush() {
+        match self.flush_block() {
             Ok(()) => Ok(self.inner.take().unwrap().w),
             Err(err) => Err(IntoInnerError::new(self, err)),
         }
@@ -97,7 +302,7 @@ impl<W: io::Write> FrameEncoder<W> {
 
     /// Gets a reference to the underlying writer in this encoder.
     pub fn get_ref(&self) -> &W {
-        &self.inner.as_ref().unwrap().w
+        &self.inner().w
     }
 
     /// Gets a reference to the underlying writer in this encoder.
@@ -105,7 +310,365 @@ impl<W: io::Write> FrameEncoder<W> {
     /// Note that mutating the output/input state of the stream may corrupt
     /// this encoder, so care must be taken when using this method.
     pub fn get_mut(&mut self) -> &mut W {
-        &mut self.inner.as_mut().unwrap().w
+        &mut self.inner_mut().w
+    }
+
+    /// Compresses any buffered bytes and writes them to the underlying writer
+    /// as a single chunk, without flushing the underlying writer.
+    ///
+    /// This makes it possible to control where chunk boundaries occur, e.g.,
+    /// at the end of each record, without forcing the underlying writer to
+    /// flush (which might issue a system call) every time. If no bytes are
+    /// buffered, then this does nothing.
+    ///
+    /// In contrast, `flush` does the same thing and then also flushes the
+    /// underlying writer.
+    pub fn flush_block(&mut self) -> io::Result<()> {
+        if self.src.is_empty() {
+            let inner = self.inner_mut();
+            if inner.emit_empty_stream {
+                inner.write_stream_ident()?;
+            }
+            return Ok(());
+        }
+        // Move the buffer out while writing it, since writing needs a
+        // mutable borrow of the rest of the encoder.
+        let src = mem::take(&mut self.src);
+        let result = self.inner_mut().write(&src);
+        self.src = src;
+        result?;
+        self.src.truncate(0);
+        Ok(())
+    }
+
+    /// Calls `should_flush` with the number of bytes currently buffered, and
+    /// if it returns true, writes them as a chunk like
+    /// [`flush_block`](#method.flush_block) does.
+    ///
+    /// Buffered bytes are normally only written once a whole block has
+    /// accumulated, which may take arbitrarily long when bytes arrive
+    /// slowly. This makes it possible to bound that latency without this
+    /// encoder knowing anything about time, e.g., by deciding based on a
+    /// timer that is started when bytes are first buffered. `should_flush`
+    /// is called even if nothing is buffered, in which case it's given `0`.
+    pub fn maybe_flush<F>(&mut self, should_flush: F) -> io::Result<()>
+    where
+        F: FnOnce(usize) -> bool,
+    {
+        if should_flush(self.src.len()) {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Compresses `block` and writes it to the underlying writer as exactly
+    /// one chunk.
+    ///
+    /// Unlike writing through `std::io::Write`, `block` is never split or
+    /// combined with other bytes, which gives precise control over chunk
+    /// boundaries, e.g., for aligning chunks with records. An empty `block`
+    /// is written as an empty chunk.
+    ///
+    /// Any buffered bytes are written as a chunk first, so that `block`
+    /// appears in the stream at the position it was written.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if `block` is longer than
+    /// the maximum block size of the Snappy frame format (65536 bytes).
+    /// Otherwise, an error is returned if writing to the underlying writer
+    /// fails.
+    pub fn write_block(&mut self, block: &[u8]) -> io::Result<()> {
+        if block.len() > MAX_BLOCK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "block of {} bytes exceeds maximum of {}",
+                    block.len(),
+                    MAX_BLOCK_SIZE
+                ),
+            ));
+        }
+        self.flush_block()?;
+        self.inner_mut().write_stream_ident()?;
+        self.inner_mut().write_chunk(block)
+    }
+
+    /// Compresses `buf` using the given hint and writes it to the underlying
+    /// writer.
+    ///
+    /// This makes it possible to pick a different trade off between speed
+    /// and ratio for different parts of a stream, e.g., when it's known that
+    /// some of them contain text and others contain media. The hint only
+    /// applies to `buf`, so `buf` is written as one or more chunks right
+    /// away, without being buffered or combined with other bytes. Any
+    /// buffered bytes are written as a chunk first, with the default
+    /// settings.
+    ///
+    /// The stream produced is valid regardless of the hint.
+    pub fn write_with_hint(
+        &mut self,
+        buf: &[u8],
+        hint: CompressHint,
+    ) -> io::Result<()> {
+        self.flush_block()?;
+        let (search_skip, store_divisor) = hint.settings();
+        let inner = self.inner_mut();
+        inner.enc.set_search_skip(search_skip);
+        inner.enc.store_divisor = store_divisor;
+        let result = inner.write(buf);
+        let (search_skip, store_divisor) = CompressHint::Default.settings();
+        inner.enc.set_search_skip(search_skip);
+        inner.enc.store_divisor = store_divisor;
+        result.map(|_| ())
+    }
+
+    /// Writes a reserved but skippable chunk containing `data` to the
+    /// underlying writer.
+    ///
+    /// The Snappy frame format reserves chunk types `0x80` through `0xFD`
+    /// for chunks that decoders must skip, which is useful for embedding
+    /// application metadata in a stream. Such chunks are written as is, with
+    /// no checksum. They can be inspected while decoding with
+    /// [`read::FrameDecoder::set_on_skippable`](../read/struct.FrameDecoder.html#method.set_on_skippable).
+    ///
+    /// Any buffered bytes are written as a chunk first, so that the
+    /// skippable chunk appears in the stream at the position it was written.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if `chunk_type` is not in
+    /// the range `0x80..=0xFD`, or if `data` is longer than the longest
+    /// chunk that this crate's decoders accept (76490 bytes). Otherwise, an
+    /// error is returned if writing to the underlying writer fails.
+    pub fn write_skippable_chunk(
+        &mut self,
+        chunk_type: u8,
+        data: &[u8],
+    ) -> io::Result<()> {
+        if !(0x80..=0xFD).contains(&chunk_type) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("chunk type {:#04X} is not skippable", chunk_type),
+            ));
+        }
+        if data.len() > MAX_COMPRESS_BLOCK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "skippable chunk of {} bytes exceeds maximum of {}",
+                    data.len(),
+                    MAX_COMPRESS_BLOCK_SIZE
+                ),
+            ));
+        }
+        self.flush_block()?;
+        let inner = self.inner_mut();
+        inner.write_stream_ident()?;
+        let mut header = [chunk_type, 0, 0, 0];
+        bytes::write_u24_le(data.len() as u32, &mut header[1..]);
+        let n = header.len() + data.len();
+        check_output_limit(inner.total_out, inner.output_limit, n)?;
+        inner.w.write_all(&header)?;
+        inner.w.write_all(data)?;
+        inner.total_out += n as u64;
+        Ok(())
+    }
+
+    /// Returns the ratio of compressed bytes to uncompressed bytes achieved
+    /// by this encoder so far.
+    ///
+    /// Only bytes that have been written to the underlying writer are
+    /// considered, so bytes that are still buffered don't count until the
+    /// next chunk is written. The compressed size includes the overhead of
+    /// the frame format, i.e., the stream identifier and chunk headers.
+    ///
+    /// A ratio less than `1.0` means that compression is saving space, while
+    /// a ratio greater than `1.0` means it's costing space. If no chunks have
+    /// been written yet, then `1.0` is returned.
+    pub fn ratio(&self) -> f64 {
+        let inner = self.inner();
+        if inner.total_in == 0 {
+            return 1.0;
+        }
+        inner.total_out as f64 / inner.total_in as f64
+    }
+
+    /// Set the maximum number of bytes that this encoder will write to the
+    /// underlying writer.
+    ///
+    /// This is useful when writing into a region of fixed size, e.g., a slot
+    /// in a memory mapped file. Every byte written to the underlying writer
+    /// counts towards the limit, including the stream identifier, chunk
+    /// headers and bytes written before the limit was set. If writing the
+    /// next chunk would exceed the limit, then an error of kind `WriteZero`
+    /// is returned and nothing is written. Thus, the bytes written so far
+    /// always form a valid stream. Note that since bytes are buffered until
+    /// a whole chunk is written, the error may only be returned by a later
+    /// call, e.g., to `flush`.
+    ///
+    /// The count of bytes written starts over when the underlying writer is
+    /// replaced by [`reset`](#method.reset). By default, there is no limit.
+    pub fn set_output_limit(&mut self, max: u64) {
+        self.inner_mut().output_limit = Some(max);
+    }
+
+    /// Set whether a stream that never received any data is written as just
+    /// the stream identifier, rather than as nothing at all.
+    ///
+    /// By default, the stream identifier is only written along with the
+    /// first compressed chunk, so an encoder that receives no data writes
+    /// nothing. This is the most compact output, but some consumers need at
+    /// least the stream identifier to recognize the format. When this is
+    /// enabled, flushing this encoder (including via `into_inner`, `reset`
+    /// or dropping it) writes the stream identifier if it hasn't been
+    /// written yet, so that the output is always a valid stream, even if it
+    /// decompresses to nothing.
+    pub fn set_emit_empty_stream(&mut self, yes: bool) {
+        self.inner_mut().emit_empty_stream = yes;
+    }
+
+    /// Returns a snapshot of this encoder's state, which can later be
+    /// restored with [`restore`](#method.restore).
+    ///
+    /// The snapshot includes a copy of all buffered uncompressed bytes, so
+    /// it is at most one block in size.
+    pub fn checkpoint(&self) -> EncoderCheckpoint {
+        let inner = self.inner();
+        EncoderCheckpoint {
+            src: self.src.clone(),
+            wrote_stream_ident: inner.wrote_stream_ident,
+            total_in: inner.total_in,
+            total_out: inner.total_out,
+        }
+    }
+
+    /// Restores this encoder to the state recorded by the given checkpoint,
+    /// discarding any bytes buffered since.
+    ///
+    /// Nothing is written to or removed from the underlying writer. It's the
+    /// caller's responsibility to ensure the underlying writer contains
+    /// exactly the bytes that had been written when the checkpoint was
+    /// created, e.g., by truncating it to
+    /// [`EncoderCheckpoint::written`](struct.EncoderCheckpoint.html#method.written).
+    /// Otherwise, subsequent output will not form a valid stream.
+    pub fn restore(&mut self, cp: EncoderCheckpoint) {
+        self.src.clear();
+        self.src.extend_from_slice(&cp.src);
+        let inner = self.inner_mut();
+        inner.wrote_stream_ident = cp.wrote_stream_ident;
+        inner.total_in = cp.total_in;
+        inner.total_out = cp.total_out;
+    }
+
+    /// Set the minimum number of bytes written for each chunk of compressed
+    /// data.
+    ///
+    /// The size of each chunk depends on how well its data compresses, and
+    /// thus leaks information about the uncompressed data. When this is set,
+    /// every data chunk shorter than `min` bytes (including its header) is
+    /// followed by padding chunks, which decoders skip, so that the total
+    /// written for it is at least `min` bytes. Since a padding chunk is at
+    /// least as long as a chunk header, the total may exceed `min` by up to
+    /// 3 bytes when a data chunk falls just short of it. Chunks that are
+    /// already at least `min` bytes are left as is.
+    ///
+    /// Padding counts towards the
+    /// [output limit](#method.set_output_limit), and a data chunk is never
+    /// written without its padding. By default, the minimum is `0`, so that
+    /// no padding is written.
+    ///
+    /// Since padding beyond the largest possible data chunk hides nothing,
+    /// `min` is capped at that size, which is a little over 64 KiB.
+    pub fn set_min_chunk_total(&mut self, min: usize) {
+        self.inner_mut().min_chunk_total =
+            cmp::min(min, CHUNK_HEADER_AND_CRC_SIZE + MAX_COMPRESS_BLOCK_SIZE);
+    }
+
+    /// Set the constant used to mask the checksum of each chunk.
+    ///
+    /// The Snappy frame format stores the CRC32C checksum of each chunk's
+    /// uncompressed bytes rotated right by 15 bits and added to the constant
+    /// `0xA282EAD8`. Some nonstandard implementations use a different
+    /// constant. Setting it here makes it possible to produce streams that
+    /// they can read, e.g., for interoperability testing.
+    ///
+    /// **Streams written with any constant other than the default don't
+    /// conform to the Snappy frame format.** Conforming decoders will reject
+    /// them with a checksum error. They can only be read by a decoder
+    /// configured with the same constant, e.g., via
+    /// [`read::FrameDecoder::set_crc_mask_constant`](../read/struct.FrameDecoder.html#method.set_crc_mask_constant).
+    pub fn set_crc_mask_constant(&mut self, delta: u32) {
+        self.inner_mut().checksummer.set_mask_constant(delta);
+    }
+
+    /// Flushes this encoder and replaces its underlying writer with `wtr`,
+    /// returning the previous writer.
+    ///
+    /// The encoder is reset to the state of a newly created encoder. In
+    /// particular, the stream identifier is written to `wtr` before any
+    /// subsequently written data. The encoder's internal buffers are reused.
+    pub fn reset(&mut self, wtr: W) -> io::Result<W> {
+        self.flush_block()?;
+        let inner = self.inner_mut();
+        inner.wrote_stream_ident = false;
+        inner.total_in = 0;
+        inner.total_out = 0;
+        Ok(mem::replace(&mut inner.w, wtr))
+    }
+
+    /// Like [`reset`](#method.reset), except the stream identifier is never
+    /// written to `wtr`.
+    ///
+    /// This is useful for appending more compressed chunks to the end of an
+    /// existing Snappy frame formatted stream, e.g., a log file opened in
+    /// append mode. The caller is responsible for ensuring that `wtr` is
+    /// positioned at the end of a valid stream that already starts with the
+    /// stream identifier. Otherwise, the result will not be a valid stream.
+    pub fn reset_appending(&mut self, wtr: W) -> io::Result<W> {
+        let old = self.reset(wtr)?;
+        self.inner_mut().wrote_stream_ident = true;
+        Ok(old)
+    }
+
+    /// Returns our main internal state.
+    ///
+    /// It's only ever missing after `into_inner` has taken it, at which
+    /// point the encoder is never used again.
+    fn inner(&self) -> &Inner<W> {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Like `inner`, but returns the state mutably.
+    fn inner_mut(&mut self) -> &mut Inner<W> {
+        self.inner.as_mut().unwrap()
+    }
+}
+
+impl FrameEncoder<Vec<u8>> {
+    /// Compresses all bytes in `input` using the Snappy frame format and
+    /// returns the result.
+    ///
+    /// This is a convenience for the common case of compressing a buffer
+    /// that is already in memory. It's equivalent to
+    /// [`compress_into_vec`](fn.compress_into_vec.html) with an empty
+    /// `Vec`, which can't fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use snap::{read::FrameDecoder, write::FrameEncoder};
+    ///
+    /// let compressed = FrameEncoder::compress_all(b"hello");
+    /// let got = FrameDecoder::decompress_all(&compressed).unwrap();
+    /// assert_eq!(b"hello", &*got);
+    /// ```
+    #[must_use = "the compressed bytes are only returned"]
+    pub fn compress_all(input: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        compress_into_vec(input, &mut output)
+            .expect("compressing into a Vec never fails");
+        output
     }
 }
 
@@ -114,7 +677,7 @@ impl<W: io::Write> Drop for FrameEncoder<W> {
         if self.inner.is_some() {
             // Ignore errors because we can't conceivably return an error and
             // panicing in a dtor is bad juju.
-            let _ = self.flush();
+            let _ = self.flush_block();
         }
     }
 }
@@ -122,24 +685,31 @@ impl<W: io::Write> Drop for FrameEncoder<W> {
 impl<W: io::Write> io::Write for FrameEncoder<W> {
     fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
         let mut total = 0;
-        // If there isn't enough room to add buf to src, then add only a piece
-        // of it, flush it and mush on.
-        loop {
-            let free = self.src.capacity() - self.src.len();
-            // n is the number of bytes extracted from buf.
-            let n = if buf.len() <= free {
-                break;
-            } else if self.src.is_empty() {
-                // If buf is bigger than our entire buffer then avoid
-                // the indirection and write the buffer directly.
-                self.inner.as_mut().unwrap().write(buf)?
-            } else {
-                self.src.extend_from_slice(&buf[0..free]);
-                self.flush()?;
-                free
-            };
-            buf = &buf[n..];
-            total += n;
+        // If there isn't enough room to add buf to src, then top src off with
+        // a piece of buf and flush it. This copies at most one block's worth
+        // of bytes from buf, regardless of how big buf is.
+        let free = self.src.capacity() - self.src.len();
+        if !self.src.is_empty() && buf.len() > free {
+            self.src.extend_from_slice(&buf[0..free]);
+            self.flush_block()?;
+            buf = &buf[free..];
+            total += free;
+        }
+        // If what remains of buf is bigger than our entire buffer, then avoid
+        // the indirection and compress it directly from the caller's buffer.
+        // Since src was just drained above (or was already empty), no further
+        // copies are made.
+        //
+        // Similarly, if src is empty and buf is made up of whole blocks, then
+        // buffering it would only produce the same blocks after a copy, so
+        // compress it directly too.
+        let aligned = self.src.is_empty()
+            && !buf.is_empty()
+            && buf.len() % MAX_BLOCK_SIZE == 0;
+        if aligned || buf.len() > self.src.capacity() - self.src.len() {
+            debug_assert!(self.src.is_empty());
+            total += self.inner_mut().write(buf)?;
+            return Ok(total);
         }
         // We're only here if buf.len() will fit within the available space of
         // self.src.
@@ -151,23 +721,21 @@ impl<W: io::Write> io::Write for FrameEncoder<W> {
         Ok(total)
     }
 
+    /// Writes any buffered bytes as a chunk, like `flush_block`, and then
+    /// flushes the underlying writer.
+    ///
+    /// Earlier releases didn't flush the underlying writer here. Use
+    /// `flush_block` to end a chunk without doing so.
     fn flush(&mut self) -> io::Result<()> {
-        if self.src.is_empty() {
-            return Ok(());
-        }
-        self.inner.as_mut().unwrap().write(&self.src)?;
-        self.src.truncate(0);
-        Ok(())
+        self.flush_block()?;
+        self.inner_mut().w.flush()
     }
 }
 
 impl<W: io::Write> Inner<W> {
     fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
         let mut total = 0;
-        if !self.wrote_stream_ident {
-            self.wrote_stream_ident = true;
-            self.w.write_all(STREAM_IDENTIFIER)?;
-        }
+        self.write_stream_ident()?;
         while !buf.is_empty() {
             // Advance buf and get our block.
             let mut src = buf;
@@ -176,20 +744,73 @@ impl<W: io::Write> Inner<W> {
             }
             buf = &buf[src.len()..];
 
-            let frame_data = compress_frame(
-                &mut self.enc,
-                self.checksummer,
-                src,
-                &mut self.chunk_header,
-                &mut self.dst,
-                false,
-            )?;
-            self.w.write_all(&self.chunk_header)?;
-            self.w.write_all(frame_data)?;
+            self.write_chunk(src)?;
             total += src.len();
         }
         Ok(total)
     }
+
+    /// Compresses `src` and writes it to the underlying writer as a single
+    /// chunk. The stream identifier must have already been written.
+    fn write_chunk(&mut self, src: &[u8]) -> io::Result<()> {
+        let frame_data = compress_frame(
+            &mut self.enc,
+            self.checksummer,
+            src,
+            &mut self.chunk_header,
+            &mut self.dst,
+            false,
+        )?;
+        let n = self.chunk_header.len() + frame_data.len();
+        let pad = match self.min_chunk_total.checked_sub(n) {
+            None | Some(0) => 0,
+            Some(pad) => cmp::max(pad, CHUNK_HEADER_SIZE),
+        };
+        check_output_limit(self.total_out, self.output_limit, n + pad)?;
+        self.w.write_all(&self.chunk_header)?;
+        self.w.write_all(frame_data)?;
+        self.total_in += src.len() as u64;
+        self.total_out += n as u64;
+        self.write_padding(pad)
+    }
+
+    /// Writes padding chunks totalling exactly `len` bytes to the underlying
+    /// writer. `len` must be either zero or at least `CHUNK_HEADER_SIZE`.
+    fn write_padding(&mut self, mut len: usize) -> io::Result<()> {
+        debug_assert!(len == 0 || len >= CHUNK_HEADER_SIZE);
+        let max = CHUNK_HEADER_SIZE + MAX_COMPRESS_BLOCK_SIZE;
+        while len > 0 {
+            let mut n = cmp::min(len, max);
+            // Don't leave a remainder too short to be a chunk of its own.
+            if len - n > 0 && len - n < CHUNK_HEADER_SIZE {
+                n -= CHUNK_HEADER_SIZE;
+            }
+            let mut header = [ChunkType::Padding.as_u8(), 0, 0, 0];
+            bytes::write_u24_le(
+                (n - CHUNK_HEADER_SIZE) as u32,
+                &mut header[1..],
+            );
+            self.w.write_all(&header)?;
+            let zeros = (n - CHUNK_HEADER_SIZE) as u64;
+            io::copy(&mut io::Read::take(io::repeat(0), zeros), &mut self.w)?;
+            self.total_out += n as u64;
+            len -= n;
+        }
+        Ok(())
+    }
+
+    /// Writes the stream identifier to the underlying writer, unless it has
+    /// already been written.
+    fn write_stream_ident(&mut self) -> io::Result<()> {
+        if !self.wrote_stream_ident {
+            let n = STREAM_IDENTIFIER.len();
+            check_output_limit(self.total_out, self.output_limit, n)?;
+            self.wrote_stream_ident = true;
+            self.w.write_all(STREAM_IDENTIFIER)?;
+            self.total_out += STREAM_IDENTIFIER.len() as u64;
+        }
+        Ok(())
+    }
 }
 
 impl<W: fmt::Debug + io::Write> fmt::Debug for FrameEncoder<W> {
@@ -210,6 +831,515 @@ impl<W: fmt::Debug + io::Write> fmt::Debug for Inner<W> {
             .field("dst", &"[...]")
             .field("wrote_stream_ident", &self.wrote_stream_ident)
             .field("chunk_header", &self.chunk_header)
+            .field("total_in", &self.total_in)
+            .field("total_out", &self.total_out)
+            .field("output_limit", &self.output_limit)
+            .field("emit_empty_stream", &self.emit_empty_stream)
+            .field("min_chunk_total", &self.min_chunk_total)
+            .finish()
+    }
+}
+
+/// Returns an error if writing `n` more bytes after `total_out` bytes have
+/// been written would exceed `limit`.
+fn check_output_limit(
+    total_out: u64,
+    limit: Option<u64>,
+    n: usize,
+) -> io::Result<()> {
+    match limit {
+        Some(max) if total_out + n as u64 > max => Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            format!(
+                "writing {} bytes would exceed output limit of {} bytes",
+                n, max
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A writer for decompressing a Snappy stream.
+///
+/// This `FrameDecoder` wraps any other writer that implements `io::Write`.
+/// Bytes written to this writer are decompressed using the [Snappy frame
+/// format](https://github.com/google/snappy/blob/master/framing_format.txt)
+/// (file extension `sz`, MIME type `application/x-snappy-framed`), and the
+/// decompressed bytes are written to the underlying writer.
+///
+/// Compressed bytes may be written in pieces of any size. Bytes that don't
+/// yet form a complete chunk are buffered until the rest of the chunk is
+/// written. Each complete chunk is decompressed and written to the underlying
+/// writer immediately.
+///
+/// If an error occurs, then the stream is corrupt and this decoder should not
+/// be used any further.
+pub struct FrameDecoder<W: io::Write> {
+    /// The underlying writer.
+    w: W,
+    /// A Snappy decoder that we reuse that does the actual block based
+    /// decompression.
+    dec: Decoder,
+    /// A CRC32 checksummer that is configured to either use the portable
+    /// fallback version or the SSE4.2 accelerated version when the right CPU
+    /// features are available.
+    checksummer: CheckSummer,
+    /// The compressed bytes buffer. This holds at most one chunk, including
+    /// its header.
+    src: Vec<u8>,
+    /// Index into src: starting point of bytes not yet decoded.
+    srcs: usize,
+    /// Index into src: ending point of bytes not yet decoded.
+    srce: usize,
+    /// The decompressed bytes buffer. Bytes are decompressed from src to dst
+    /// before being written to w.
+    dst: Vec<u8>,
+    /// Whether we've read the special stream header or not.
+    read_stream_ident: bool,
+}
+
+impl<W: io::Write> FrameDecoder<W> {
+    /// Create a new writer for streaming Snappy decompression.
+    pub fn new(wtr: W) -> FrameDecoder<W> {
+        FrameDecoder {
+            w: wtr,
+            dec: Decoder::new(),
+            checksummer: CheckSummer::new(),
+            src: vec![0; CHUNK_HEADER_SIZE + MAX_COMPRESS_BLOCK_SIZE],
+            srcs: 0,
+            srce: 0,
+            dst: vec![0; MAX_BLOCK_SIZE],
+            read_stream_ident: false,
+        }
+    }
+
+    /// Gets a reference to the underlying writer in this decoder.
+    pub fn get_ref(&self) -> &W {
+        &self.w
+    }
+
+    /// Gets a mutable reference to the underlying writer in this decoder.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt
+    /// this decoder, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// Returns the underlying writer, consuming this decoder.
+    ///
+    /// Any bytes of an incomplete chunk that are buffered are discarded. Use
+    /// [`is_stream_complete`](#method.is_stream_complete) to check whether
+    /// there are any.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    /// Set the constant used to mask the checksum of each chunk, in place of
+    /// the one defined by the Snappy frame format.
+    ///
+    /// This makes it possible to read streams written by nonstandard
+    /// implementations, but streams that conform to the Snappy frame format
+    /// will then fail with a checksum error. See
+    /// [`FrameEncoder::set_crc_mask_constant`](struct.FrameEncoder.html#method.set_crc_mask_constant)
+    /// for details.
+    pub fn set_crc_mask_constant(&mut self, delta: u32) {
+        self.checksummer.set_mask_constant(delta);
+    }
+
+    /// Returns the number of compressed bytes written to this decoder that
+    /// have not been decoded yet, because they don't form a complete chunk.
+    pub fn pending_input(&self) -> usize {
+        self.srce - self.srcs
+    }
+
+    /// Returns true if the bytes written to this decoder so far form a
+    /// complete Snappy stream.
+    ///
+    /// That is, the stream identifier has been seen and every chunk written
+    /// has been decoded, such that no partial chunk is pending. This is
+    /// useful for determining whether the sender of a stream finished
+    /// sending it, e.g., when a connection is closed.
+    pub fn is_stream_complete(&self) -> bool {
+        self.read_stream_ident && self.srcs == self.srce
+    }
+
+    /// Decodes every complete chunk in `src[srcs..srce]` and writes the
+    /// decompressed bytes to the underlying writer. Any trailing incomplete
+    /// chunk is moved to the start of `src`.
+    fn decode_chunks(&mut self) -> io::Result<()> {
+        while let Some((data, consumed)) = decode_chunk(
+            &mut self.dec,
+            self.checksummer,
+            &self.src[self.srcs..self.srce],
+            &mut self.dst,
+            &mut self.read_stream_ident,
+            VerifyCrc::Yes,
+        )? {
+            self.w.write_all(data)?;
+            self.srcs += consumed;
+        }
+        // Move whatever is left of an incomplete chunk to the front, so that
+        // there is always room for the rest of it.
+        self.src.copy_within(self.srcs..self.srce, 0);
+        self.srce -= self.srcs;
+        self.srcs = 0;
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for FrameDecoder<W> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let total = buf.len();
+        // Since decode_chunks always consumes every complete chunk, there is
+        // always room for more bytes after it returns.
+        while !buf.is_empty() {
+            let n = cmp::min(buf.len(), self.src.len() - self.srce);
+            self.src[self.srce..self.srce + n].copy_from_slice(&buf[..n]);
+            self.srce += n;
+            buf = &buf[n..];
+            self.decode_chunks()?;
+        }
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W: fmt::Debug + io::Write> fmt::Debug for FrameDecoder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameDecoder")
+            .field("w", &self.w)
+            .field("dec", &self.dec)
+            .field("checksummer", &self.checksummer)
+            .field("src", &"[...]")
+            .field("srcs", &self.srcs)
+            .field("srce", &self.srce)
+            .field("dst", &"[...]")
+            .field("read_stream_ident", &self.read_stream_ident)
+            .finish()
+    }
+}
+
+/// A pool of reusable state for compressing in-memory buffers to the Snappy
+/// frame format.
+///
+/// Compressing a buffer requires an [`Encoder`](../raw/struct.Encoder.html),
+/// a checksummer and a buffer for compressed blocks. Allocating these for
+/// every buffer is wasteful when compressing many small buffers, e.g., from
+/// the tasks of a thread pool. An `EncoderPool` can be shared between
+/// threads, and lends out its state via [`get`](#method.get). The state is
+/// returned to the pool when the [`PooledEncoder`](struct.PooledEncoder.html)
+/// guard is dropped.
+///
+/// The pool grows to the maximum number of states that are lent out at the
+/// same time, and never shrinks.
+///
+/// # Example
+///
+/// ```
+/// use snap::write::EncoderPool;
+///
+/// let pool = EncoderPool::new();
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| {
+///             let compressed =
+///                 pool.get().compress_frame_vec(b"Hello, Hello!").unwrap();
+///             assert!(!compressed.is_empty());
+///         });
+///     }
+/// });
+/// ```
+#[derive(Debug, Default)]
+pub struct EncoderPool {
+    /// The states that aren't currently lent out.
+    states: Mutex<Vec<EncoderState>>,
+}
+
+impl EncoderPool {
+    /// Create a new empty pool.
+    pub fn new() -> EncoderPool {
+        EncoderPool::default()
+    }
+
+    /// Lends out compression state from this pool, allocating new state if
+    /// none is available.
+    ///
+    /// The state is returned to this pool when the guard is dropped.
+    pub fn get(&self) -> PooledEncoder<'_> {
+        let state = self.lock().pop().unwrap_or_else(EncoderState::new);
+        PooledEncoder { pool: self, state: Some(state) }
+    }
+
+    /// Returns the number of states in this pool that aren't currently lent
+    /// out.
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Locks the idle states. A panic while holding the lock can't leave the
+    /// states in an inconsistent state, so poisoning is ignored.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<EncoderState>> {
+        self.states.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Compression state lent out by an
+/// [`EncoderPool`](struct.EncoderPool.html).
+///
+/// The state is returned to the pool when this guard is dropped.
+#[derive(Debug)]
+pub struct PooledEncoder<'a> {
+    /// The pool that the state is returned to.
+    pool: &'a EncoderPool,
+    /// The lent state. It's an `Option` so we can move it back into the pool
+    /// when dropped.
+    state: Option<EncoderState>,
+}
+
+impl<'a> PooledEncoder<'a> {
+    /// Compresses all bytes in `input` using the Snappy frame format, and
+    /// returns the compressed bytes as a new `Vec`.
+    ///
+    /// The bytes returned are identical to what
+    /// [`compress_into_vec`](fn.compress_into_vec.html) appends for the same
+    /// `input`.
+    pub fn compress_frame_vec(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let st = self.state.as_mut().unwrap();
+        let mut output = Vec::with_capacity(max_frame_len(input.len()));
+        if input.is_empty() {
+            return Ok(output);
+        }
+        output.extend_from_slice(STREAM_IDENTIFIER);
+        for src in input.chunks(MAX_BLOCK_SIZE) {
+            st.push_chunk(src, &mut output)?;
+        }
+        Ok(output)
+    }
+}
+
+impl<'a> Drop for PooledEncoder<'a> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            self.pool.lock().push(state);
+        }
+    }
+}
+
+/// A compressor that appends a Snappy frame formatted stream to a `Vec<u8>`.
+///
+/// This is like a [`FrameEncoder`](struct.FrameEncoder.html) that writes to
+/// a `Vec<u8>`, except compressed chunks are appended to the `Vec` directly
+/// instead of going through `std::io::Write`. Since appending to a `Vec`
+/// can't fail, neither can compressing with this type.
+///
+/// Bytes are compressed in blocks of the maximum size, regardless of how they
+/// are split up between calls to
+/// [`extend_from_slice`](#method.extend_from_slice). Therefore, the stream
+/// produced is identical to what `FrameEncoder` produces when all of the
+/// same bytes are written to it in one call.
+///
+/// # Example
+///
+/// ```
+/// use snap::write::VecEncoder;
+///
+/// let mut enc = VecEncoder::new();
+/// enc.extend_from_slice(b"Hello, ");
+/// enc.extend_from_slice(b"world!");
+/// let compressed = enc.finish();
+/// let got = snap::read::decompress_slice(&compressed).unwrap();
+/// assert_eq!(b"Hello, world!", &*got);
+/// ```
+#[derive(Debug)]
+pub struct VecEncoder {
+    /// The compressed stream so far.
+    output: Vec<u8>,
+    /// The state used to compress each block.
+    state: EncoderState,
+    /// Uncompressed bytes that don't make up a whole block yet.
+    src: Vec<u8>,
+    /// Whether the stream identifier has been appended yet.
+    wrote_stream_ident: bool,
+}
+
+impl VecEncoder {
+    /// Create a new compressor with an empty output buffer.
+    pub fn new() -> VecEncoder {
+        VecEncoder::with_vec(vec![])
+    }
+
+    /// Create a new compressor that appends the compressed stream to
+    /// `output`, which may already contain other bytes.
+    pub fn with_vec(output: Vec<u8>) -> VecEncoder {
+        VecEncoder {
+            output,
+            state: EncoderState::new(),
+            src: Vec::with_capacity(MAX_BLOCK_SIZE),
+            wrote_stream_ident: false,
+        }
+    }
+
+    /// Compresses the bytes in `buf`.
+    ///
+    /// Whole blocks are compressed and appended to the output buffer right
+    /// away, while bytes that don't make up a whole block are buffered until
+    /// more bytes are given or [`finish`](#method.finish) is called.
+    pub fn extend_from_slice(&mut self, mut buf: &[u8]) {
+        if !self.src.is_empty() {
+            let n = cmp::min(buf.len(), MAX_BLOCK_SIZE - self.src.len());
+            self.src.extend_from_slice(&buf[..n]);
+            buf = &buf[n..];
+            if self.src.len() < MAX_BLOCK_SIZE {
+                return;
+            }
+            let src = mem::take(&mut self.src);
+            self.push_chunk(&src);
+            self.src = src;
+            self.src.clear();
+        }
+        while buf.len() >= MAX_BLOCK_SIZE {
+            self.push_chunk(&buf[..MAX_BLOCK_SIZE]);
+            buf = &buf[MAX_BLOCK_SIZE..];
+        }
+        self.src.extend_from_slice(buf);
+    }
+
+    /// Compresses any buffered bytes and returns the output buffer.
+    ///
+    /// If no bytes were given at all, then nothing, not even the stream
+    /// identifier, is appended to the output buffer.
+    #[must_use = "the compressed bytes are only returned"]
+    pub fn finish(mut self) -> Vec<u8> {
+        if !self.src.is_empty() {
+            let src = mem::take(&mut self.src);
+            self.push_chunk(&src);
+        }
+        self.output
+    }
+
+    /// Returns the compressed bytes appended to the output buffer so far.
+    ///
+    /// This doesn't include any bytes that are still buffered.
+    pub fn get_ref(&self) -> &Vec<u8> {
+        &self.output
+    }
+
+    /// Compresses `src` and appends it to the output buffer as one chunk,
+    /// preceded by the stream identifier if this is the first chunk.
+    fn push_chunk(&mut self, src: &[u8]) {
+        if !self.wrote_stream_ident {
+            self.wrote_stream_ident = true;
+            self.output.extend_from_slice(STREAM_IDENTIFIER);
+        }
+        self.state
+            .push_chunk(src, &mut self.output)
+            .expect("compressing a block never fails");
+    }
+}
+
+impl Default for VecEncoder {
+    fn default() -> VecEncoder {
+        VecEncoder::new()
+    }
+}
+
+/// A writer that compresses a Snappy frame formatted stream, and writes it
+/// prefixed by its length.
+///
+/// The entire compressed stream is buffered in memory. When
+/// [`finish`](#method.finish) is called, the length of the compressed stream
+/// is written to the underlying writer as an 8 byte little endian integer,
+/// followed by the compressed stream itself. This makes it possible for a
+/// reader to skip the stream without parsing its chunks, e.g., in a storage
+/// format that holds many streams. Use
+/// [`read::LengthPrefixedDecoder`](../read/struct.LengthPrefixedDecoder.html)
+/// to decompress it.
+///
+/// Nothing is written to the underlying writer until `finish` is called, so
+/// dropping this writer without calling `finish` discards everything written
+/// to it. Since the compressed stream is buffered, the amount of memory used
+/// grows with the input. Use [`set_max_len`](#method.set_max_len) to bound
+/// it.
+///
+/// # Example
+///
+/// ```
+/// use std::io::{Read, Write};
+///
+/// let mut wtr = snap::write::LengthPrefixedEncoder::new(vec![]);
+/// wtr.write_all(b"hello").unwrap();
+/// let stored = wtr.finish().unwrap();
+///
+/// let mut rdr = snap::read::LengthPrefixedDecoder::new(&stored[..]).unwrap();
+/// let mut got = vec![];
+/// rdr.read_to_end(&mut got).unwrap();
+/// assert_eq!(b"hello", &*got);
+/// ```
+pub struct LengthPrefixedEncoder<W: io::Write> {
+    /// The underlying writer.
+    wtr: W,
+    /// The encoder that compresses into an in memory buffer.
+    enc: FrameEncoder<Vec<u8>>,
+}
+
+impl<W: io::Write> LengthPrefixedEncoder<W> {
+    /// Create a new writer that writes a length prefixed Snappy frame
+    /// formatted stream to `wtr`.
+    pub fn new(wtr: W) -> LengthPrefixedEncoder<W> {
+        LengthPrefixedEncoder { wtr, enc: FrameEncoder::new(vec![]) }
+    }
+
+    /// Set the maximum length of the compressed stream, not including its
+    /// length prefix.
+    ///
+    /// Once a write would make the buffered compressed stream longer than
+    /// `max`, an error of kind `WriteZero` is returned instead. This bounds
+    /// the memory used for buffering. By default, there is no limit.
+    pub fn set_max_len(&mut self, max: u64) {
+        self.enc.set_output_limit(max);
+    }
+
+    /// Gets a reference to the underlying writer in this encoder.
+    pub fn get_ref(&self) -> &W {
+        &self.wtr
+    }
+
+    /// Writes the length prefix followed by the compressed stream to the
+    /// underlying writer, and returns it.
+    ///
+    /// The underlying writer is flushed. If compressing the last of the
+    /// buffered bytes, writing or flushing fails, then the error is returned
+    /// and the underlying writer is lost.
+    pub fn finish(mut self) -> io::Result<W> {
+        let stream = self.enc.into_inner().map_err(|err| err.into_error())?;
+        self.wtr.write_all(&(stream.len() as u64).to_le_bytes())?;
+        self.wtr.write_all(&stream)?;
+        self.wtr.flush()?;
+        Ok(self.wtr)
+    }
+}
+
+impl<W: io::Write> io::Write for LengthPrefixedEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.enc.write(buf)
+    }
+
+    /// This does nothing, since the length prefix can't be written until
+    /// the stream is finished.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: fmt::Debug + io::Write> fmt::Debug for LengthPrefixedEncoder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LengthPrefixedEncoder")
+            .field("wtr", &self.wtr)
+            .field("enc", &self.enc)
             .finish()
     }
 }
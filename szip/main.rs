@@ -5,7 +5,7 @@ use std::path::{Path, PathBuf};
 use anyhow::bail;
 use filetime::{set_file_times, FileTime};
 
-const ABOUT: &'static str = "
+const ABOUT: &str = "
 szip compresses and decompresses data in the Snappy format.
 
 szip works similarly to gzip. It takes files as parameters, compresses them to
@@ -148,8 +148,7 @@ impl Args {
             if name.len() <= 3 || !name.ends_with(".sz") {
                 bail!("skipping uncompressed file");
             }
-            Ok(old_path
-                .with_file_name(format!("{}", &name[0..name.len() - 3])))
+            Ok(old_path.with_file_name(&name[0..name.len() - 3]))
         } else {
             let name = name.to_string_lossy();
             if name.ends_with(".sz") {
@@ -0,0 +1,77 @@
+// Conformance tests against golden compressed files.
+//
+// Each golden vector is a pair of files: `.comp` contains a block in the raw
+// Snappy format and `.uncomp` contains exactly what it must decompress to.
+// `Mark.Twain-Tom.Sawyer.txt` and the `baddata` files come from the test data
+// in Google's Snappy repository. The vectors in `data/golden` were assembled
+// by hand from the format description, and exercise encodings that our own
+// compressor never emits, e.g., non-minimal literal lengths and copies with
+// 4 byte offsets.
+
+use snap::raw::{decompress_len, Decoder, Encoder};
+
+macro_rules! golden {
+    ($name:ident, $comp:expr, $uncomp:expr) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn decompress() {
+                let (comp, uncomp) = (&$comp[..], &$uncomp[..]);
+                assert_eq!(uncomp.len(), decompress_len(comp).unwrap());
+                let got = Decoder::new().decompress_vec(comp).unwrap();
+                assert!(uncomp == &*got, "decompressed bytes differ");
+                assert_eq!(
+                    uncomp.len(),
+                    Decoder::new().validate(comp).unwrap()
+                );
+            }
+
+            #[test]
+            fn roundtrip() {
+                let uncomp = &$uncomp[..];
+                let comp = Encoder::new().compress_vec(uncomp).unwrap();
+                let got = Decoder::new().decompress_vec(&comp).unwrap();
+                assert!(uncomp == &*got, "round tripped bytes differ");
+            }
+        }
+    };
+}
+
+macro_rules! golden_file {
+    ($name:ident, $path:expr) => {
+        golden!(
+            $name,
+            include_bytes!(concat!("../data/golden/", $path, ".comp")),
+            include_bytes!(concat!("../data/golden/", $path, ".uncomp"))
+        );
+    };
+}
+
+golden!(
+    mark_twain,
+    include_bytes!("../data/Mark.Twain-Tom.Sawyer.txt.rawsnappy"),
+    include_bytes!("../data/Mark.Twain-Tom.Sawyer.txt")
+);
+golden_file!(literals, "literals");
+golden_file!(copies, "copies");
+golden_file!(far_copies, "far_copies");
+
+// Corrupt inputs must be rejected rather than decompressed into garbage.
+#[test]
+fn baddata() {
+    let bad: &[&[u8]] = &[
+        include_bytes!("../data/baddata1.snappy"),
+        include_bytes!("../data/baddata2.snappy"),
+        include_bytes!("../data/baddata3.snappy"),
+    ];
+    for (i, data) in bad.iter().enumerate() {
+        let mut buf = vec![0; decompress_len(data).unwrap_or(0)];
+        assert!(
+            Decoder::new().decompress(data, &mut buf).is_err(),
+            "baddata{}.snappy decompressed successfully",
+            i + 1
+        );
+        assert!(Decoder::new().validate(data).is_err());
+    }
+}
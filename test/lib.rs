@@ -1,2 +1,4 @@
 #[cfg(test)]
+mod golden;
+#[cfg(test)]
 mod tests;
@@ -1,3 +1,7 @@
+// The binary literals below are grouped to mirror the bit fields of Snappy
+// tag bytes, which is clearer than clippy's preferred grouping.
+#![allow(clippy::unusual_byte_groupings)]
+
 use quickcheck::{QuickCheck, StdGen, TestResult};
 use snap::raw::{decompress_len, Decoder, Encoder};
 use snap::Error;
@@ -29,6 +33,7 @@ macro_rules! errored {
         } else {
             vec![0; decompress_len(d).unwrap()]
         };
+        assert_eq!(Err($err), Decoder::new().validate(d));
         match Decoder::new().decompress(d, &mut buf) {
             Err(ref err) if err == &$err => {}
             Err(ref err) => panic!(
@@ -80,6 +85,13 @@ macro_rules! testtrip {
                 assert_eq!(d, &*read_frame_depress(&write_frame_press(d)));
             }
 
+            #[test]
+            fn roundtrip_write_frame_decoder() {
+                use super::{write_frame_depress, write_frame_press};
+                let d = &$data[..];
+                assert_eq!(d, &*write_frame_depress(&write_frame_press(d)));
+            }
+
             #[test]
             fn read_and_write_frame_encoder_match() {
                 use super::{read_frame_press, write_frame_press};
@@ -207,10 +219,8 @@ fn data_golden_rev() {
 // Miscellaneous tests.
 #[test]
 fn small_copy() {
-    use std::iter::repeat;
-
     for i in 0..32 {
-        let inner: String = repeat('b').take(i).collect();
+        let inner = "b".repeat(i);
         roundtrip!(format!("aaaa{}aaaabbbb", inner).into_bytes());
     }
 }
@@ -228,6 +238,31 @@ fn small_regular() {
     }
 }
 
+// Blocks made of a single repeated byte take a fast path when compressing.
+#[test]
+fn runs() {
+    let lens = [1, 16, 17, 63, 64, 65, 67, 68, 69, 100, 4097, 65535, 65536];
+    for &len in lens.iter().chain(&[65537, 65536 * 2 + 5, 1_000_003]) {
+        for &byte in &[0, b'a', 0xFF] {
+            let buf = vec![byte; len];
+            roundtrip!(buf);
+            // A literal of one byte plus copies of at most 64 bytes each,
+            // for every block.
+            if len >= 64 {
+                let blocks = (len + 65535) / 65536;
+                let max = 5 + blocks * (2 + 6) + len / 64 * 3;
+                assert!(press(&buf).len() <= max, "{}", len);
+            }
+
+            // Runs that are broken at the very end of a block.
+            let mut buf = buf;
+            let last = std::cmp::min(len, 65536) - 1;
+            buf[last] ^= 1;
+            roundtrip!(buf);
+        }
+    }
+}
+
 // Test that triggered an out of bounds write.
 #[test]
 fn decompress_copy_close_to_end_1() {
@@ -339,245 +374,2675 @@ fn read_frame_encoder_big_and_little_buffers() {
     assert_eq!(big_out, little_out);
 }
 
-// Tests decompression on malformed data.
-
-// An empty buffer.
-testerrored!(err_empty, &b""[..], Error::Empty);
-
-// Decompress fewer bytes than the header reports.
-testerrored!(
-    err_header_mismatch,
-    &b"\x05\x00a"[..],
-    Error::HeaderMismatch { expected_len: 5, got_len: 1 }
-);
-
-// An invalid varint (final byte has continuation bit set).
-testerrored!(err_varint1, &b"\xFF"[..], Error::Header, true);
+// Locks down the exact bytes emitted by `write::FrameEncoder` for a small
+// input, including chunk splitting and the decision to store a chunk
+// uncompressed. If the encoder changes on purpose, update this golden.
+#[test]
+fn write_frame_encoder_golden() {
+    use snap::write;
+    use std::io::Write;
 
-// A varint that overflows u64.
-testerrored!(
-    err_varint2,
-    &b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x00"[..],
-    Error::Header,
-    true
-);
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"snappy snappy snappy snappy snappy snappy snappy snappy")
+        .unwrap();
+    wtr.flush().unwrap();
+    wtr.write_all(b"sNaPpY").unwrap();
+    let got = wtr.into_inner().unwrap();
+
+    let mut expected = vec![];
+    // Stream identifier.
+    expected.extend_from_slice(b"\xFF\x06\x00\x00sNaPpY");
+    // Compressed chunk: header, masked checksum, then a raw Snappy block
+    // containing a 7 byte literal followed by a copy 2 of length 48.
+    expected.extend_from_slice(b"\x00\x10\x00\x00\xD7\x72\xCB\xB8");
+    expected.extend_from_slice(b"\x37\x18snappy \xBE\x07\x00");
+    // Uncompressed chunk: header, masked checksum, then the bytes as is.
+    expected.extend_from_slice(b"\x01\x0A\x00\x00\xC0\x80\x04\xAA");
+    expected.extend_from_slice(b"sNaPpY");
+    assert_eq!(expected, got);
+}
 
-// A varint that fits in u64 but overflows u32.
-testerrored!(
-    err_varint3,
-    &b"\x80\x80\x80\x80\x10"[..],
-    Error::TooBig { given: 4294967296, max: 4294967295 },
-    true
-);
+// Compressing into a Vec with enough spare capacity should never reallocate
+// it, and the output should match `write::FrameEncoder`.
+#[test]
+fn write_compress_into_vec() {
+    use snap::write;
 
-// A literal whose length is too small.
-// Since the literal length is 1, 'h' is read as a literal and 'i' is
-// interpreted as a copy 1 operation missing its offset byte.
-testerrored!(
-    err_lit,
-    &b"\x02\x00hi"[..],
-    Error::CopyRead { len: 1, src_len: 0 }
-);
-// A literal whose length is too big.
-testerrored!(
-    err_lit_big1,
-    &b"\x02\xechi"[..],
-    Error::Literal { len: 60, src_len: 2, dst_len: 2 }
-);
-// A literal whose length is too big, requires 1 extra byte to be read, and
-// src is too short to read that byte.
-testerrored!(
-    err_lit_big2a,
-    &b"\x02\xf0hi"[..],
-    Error::Literal { len: 4, src_len: 2, dst_len: 2 }
-);
-// A literal whose length is too big, requires 1 extra byte to be read,
-// src is too short to read the full literal.
-testerrored!(
-    err_lit_big2b,
-    &b"\x02\xf0hi\x00\x00\x00"[..],
-    Error::Literal {
-        len: 105, // because 105 == 'h' as u8 + 1
-        src_len: 4,
-        dst_len: 2,
-    }
-);
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let expected = write_frame_press(data);
 
-// A copy 1 operation that stops at the tag byte. This fails because there's
-// no byte to read for the copy offset.
-testerrored!(
-    err_copy1,
-    &b"\x02\x00a\x01"[..],
-    Error::CopyRead { len: 1, src_len: 0 }
-);
-// A copy 2 operation that stops at the tag byte and another copy 2 operation
-// that stops after the first byte in the offset.
-testerrored!(
-    err_copy2a,
-    &b"\x11\x00a\x3e"[..],
-    Error::CopyRead { len: 2, src_len: 0 }
-);
-testerrored!(
-    err_copy2b,
-    &b"\x11\x00a\x3e\x01"[..],
-    Error::CopyRead { len: 2, src_len: 1 }
-);
-// Same as copy 2, but for copy 4.
-testerrored!(
-    err_copy3a,
-    &b"\x11\x00a\x3f"[..],
-    Error::CopyRead { len: 4, src_len: 0 }
-);
-testerrored!(
-    err_copy3b,
-    &b"\x11\x00a\x3f\x00"[..],
-    Error::CopyRead { len: 4, src_len: 1 }
-);
-testerrored!(
-    err_copy3c,
-    &b"\x11\x00a\x3f\x00\x00"[..],
-    Error::CopyRead { len: 4, src_len: 2 }
-);
-testerrored!(
-    err_copy3d,
-    &b"\x11\x00a\x3f\x00\x00\x00"[..],
-    Error::CopyRead { len: 4, src_len: 3 }
-);
+    let mut got = vec![];
+    write::compress_into_vec(data, &mut got).unwrap();
+    assert_eq!(expected, got);
 
-// A copy operation whose offset is zero.
-testerrored!(
-    err_copy_offset_zero,
-    &b"\x11\x00a\x01\x00"[..],
-    Error::Offset { offset: 0, dst_pos: 1 }
-);
+    let mut got = Vec::with_capacity(data.len() * 2);
+    got.extend_from_slice(b"prefix");
+    let ptr = got.as_ptr();
+    write::compress_into_vec(data, &mut got).unwrap();
+    assert_eq!(ptr, got.as_ptr());
+    assert_eq!(b"prefix", &got[..6]);
+    assert_eq!(expected, &got[6..]);
+}
 
-// A copy operation whose offset is too big.
-testerrored!(
-    err_copy_offset_big,
-    &b"\x11\x00a\x01\xFF"[..],
-    Error::Offset { offset: 255, dst_pos: 1 }
-);
+// Concatenating every chunk yielded by `read::FrameEncoder::chunks` should
+// give a valid stream that matches reading from the encoder directly.
+#[test]
+fn read_frame_encoder_chunks() {
+    use snap::read;
 
-// A copy operation whose length is too big.
-testerrored!(
-    err_copy_len_big,
-    &b"\x05\x00a\x1d\x01"[..],
-    Error::CopyWrite { len: 11, dst_len: 4 }
-);
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let chunks = read::FrameEncoder::new(data)
+        .chunks()
+        .collect::<std::io::Result<Vec<Vec<u8>>>>()
+        .unwrap();
+    assert!(chunks.len() > 1);
+    assert!(chunks[0].starts_with(b"\xFF\x06\x00\x00sNaPpY"));
+    assert!(!chunks[1].starts_with(b"\xFF\x06\x00\x00sNaPpY"));
+
+    let compressed = chunks.concat();
+    assert_eq!(read_frame_press(data), compressed);
+    assert_eq!(data, &*read_frame_depress(&compressed));
+}
 
-// Selected random inputs pulled from quickcheck failure witnesses.
-testtrip!(
-    random1,
-    &[
-        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0,
-        1, 1, 0, 0, 1, 2, 0, 0, 2, 1, 0, 0, 2, 2, 0, 0, 0, 6, 0, 0, 3, 1, 0,
-        0, 0, 7, 0, 0, 1, 3, 0, 0, 0, 8, 0, 0, 2, 3, 0, 0, 0, 9, 0, 0, 1, 4,
-        0, 0, 1, 0, 0, 3, 0, 0, 1, 0, 1, 0, 0, 0, 10, 0, 0, 0, 0, 2, 4, 0, 0,
-        2, 0, 0, 3, 0, 1, 0, 0, 1, 5, 0, 0, 6, 0, 0, 0, 0, 11, 0, 0, 1, 6, 0,
-        0, 1, 7, 0, 0, 0, 12, 0, 0, 3, 2, 0, 0, 0, 13, 0, 0, 2, 5, 0, 0, 0, 3,
-        3, 0, 0, 0, 1, 8, 0, 0, 1, 0, 1, 0, 0, 0, 4, 1, 0, 0, 0, 0, 14, 0, 0,
-        0, 1, 9, 0, 0, 0, 1, 10, 0, 0, 0, 0, 1, 11, 0, 0, 0, 1, 0, 2, 0, 0, 0,
-        1, 1, 1, 0, 0, 0, 0, 5, 1, 0, 0, 0, 1, 2, 1, 0, 0, 0, 0, 0, 2, 6, 0,
-        0, 0, 0, 0, 1, 12, 0, 0, 0, 0, 0, 3, 4, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0,
-        0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0,
-    ]
-);
-testtrip!(
-    random2,
-    &[
-        10, 2, 14, 13, 0, 8, 2, 10, 2, 14, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0,
-    ]
-);
-testtrip!(
-    random3,
-    &[0, 0, 0, 4, 1, 4, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]
-);
-testtrip!(
-    random4,
-    &[
-        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0,
-        1, 1, 0, 0, 1, 2, 0, 0, 1, 3, 0, 0, 1, 4, 0, 0, 2, 1, 0, 0, 0, 4, 0,
-        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
-    ]
-);
+#[test]
+fn read_frame_encoder_read_chunk_into() {
+    use snap::read;
+    use std::io::Read;
 
-// QuickCheck properties for testing that random data roundtrips.
-// These properties tend to produce the inputs for the "random" tests above.
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut enc = read::FrameEncoder::new(data);
+    // Read a few bytes first so that a partial chunk is buffered.
+    let mut compressed = vec![0; 5];
+    enc.read_exact(&mut compressed).unwrap();
+
+    let mut chunk = vec![b'x'; 3];
+    let mut count = 0;
+    while enc.read_chunk_into(&mut chunk).unwrap() {
+        compressed.extend_from_slice(&chunk);
+        count += 1;
+    }
+    assert!(chunk.is_empty());
+    assert!(!enc.read_chunk_into(&mut chunk).unwrap());
+    assert!(count > 1);
+    assert_eq!(read_frame_press(data), compressed);
+    assert_eq!(data, &*read_frame_depress(&compressed));
+}
 
+// A read size bigger than a block is clamped, so every read from the
+// underlying reader still produces exactly one chunk of at most one block.
 #[test]
-fn qc_roundtrip() {
-    fn p(bytes: Vec<u8>) -> bool {
-        depress(&press(&bytes)) == bytes
+fn read_frame_encoder_one_block_per_read() {
+    use snap::read;
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut enc = read::FrameEncoder::new(data);
+    enc.set_read_size(1 << 20);
+    let chunks = enc.chunks().collect::<std::io::Result<Vec<_>>>().unwrap();
+    assert_eq!((data.len() + 65535) / 65536, chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let chunk = if i == 0 { &chunk[10..] } else { &chunk[..] };
+        let len = u32::from_le_bytes([chunk[1], chunk[2], chunk[3], 0]);
+        assert_eq!(4 + len as usize, chunk.len());
+        let (block, n) = snap::frame::decompress_chunk(chunk, true).unwrap();
+        assert_eq!(chunk.len(), n);
+        assert!(block.len() <= 65536);
     }
-    QuickCheck::new()
-        .gen(StdGen::new(rand::thread_rng(), 10_000))
-        .tests(1_000)
-        .quickcheck(p as fn(_) -> _);
+    let compressed = chunks.concat();
+    assert_eq!(read_frame_press(data), compressed);
+    assert_eq!(data, &*read_frame_depress(&compressed));
 }
 
+// Once the underlying reader returns EOF, `read::FrameEncoder` should end the
+// stream for good, even if the underlying reader would produce more bytes.
 #[test]
-fn qc_roundtrip_stream() {
-    fn p(bytes: Vec<u8>) -> TestResult {
-        if bytes.is_empty() {
-            return TestResult::discard();
+fn read_frame_encoder_eof() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    // A reader that returns EOF once between two pieces of data.
+    struct Gappy(Vec<&'static [u8]>);
+
+    impl io::Read for Gappy {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let piece = self.0.remove(0);
+            buf[..piece.len()].copy_from_slice(piece);
+            Ok(piece.len())
         }
-        TestResult::from_bool(
-            read_frame_depress(&write_frame_press(&bytes)) == bytes,
-        )
     }
-    QuickCheck::new()
-        .gen(StdGen::new(rand::thread_rng(), 10_000))
-        .tests(1_000)
-        .quickcheck(p as fn(_) -> _);
+
+    let mut enc = read::FrameEncoder::new(Gappy(vec![b"abc", b"", b"def"]));
+    let mut compressed = vec![];
+    enc.read_to_end(&mut compressed).unwrap();
+    assert_eq!(read_frame_press(b"abc"), compressed);
+    let mut buf = [0; 1024];
+    assert_eq!(0, enc.read(&mut buf).unwrap());
+    assert_eq!(0, enc.read(&mut buf).unwrap());
+    let mut chunk = vec![];
+    assert!(!enc.read_chunk_into(&mut chunk).unwrap());
+    // The bytes after the EOF were never read.
+    assert_eq!(1, enc.get_ref().0.len());
+
+    // An empty source produces an empty stream, without even a stream
+    // identifier or an empty chunk.
+    let mut enc = read::FrameEncoder::new(&b""[..]);
+    let mut compressed = vec![];
+    enc.read_to_end(&mut compressed).unwrap();
+    assert!(compressed.is_empty());
+    assert_eq!(0, enc.read(&mut buf).unwrap());
+    assert_eq!(0, read::FrameEncoder::new(&b""[..]).chunks().count());
 }
 
+// A smaller read size should bound the size of every chunk emitted by
+// `read::FrameEncoder`.
 #[test]
-fn test_short_input() {
-    // Regression test for https://github.com/BurntSushi/rust-snappy/issues/42
+fn read_frame_encoder_read_size() {
     use snap::read;
     use std::io::Read;
 
-    let err =
-        read::FrameDecoder::new(&b"123"[..]).read_to_end(&mut Vec::new());
-    assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut rdr = read::FrameEncoder::new(data);
+    rdr.set_read_size(4096);
+    let mut compressed = vec![];
+    rdr.read_to_end(&mut compressed).unwrap();
+    assert_eq!(data, &*read_frame_depress(&compressed));
+
+    // Skip the stream identifier and walk each chunk header. Each chunk
+    // holds a 4 byte checksum followed by at most 4096 uncompressed bytes.
+    let mut chunks = 0;
+    let mut rest = &compressed[10..];
+    while !rest.is_empty() {
+        let len = rest[1] as usize
+            | (rest[2] as usize) << 8
+            | (rest[3] as usize) << 16;
+        assert!(len <= 4 + 4096);
+        rest = &rest[4 + len..];
+        chunks += 1;
+    }
+    assert_eq!((data.len() + 4095) / 4096, chunks);
 }
 
+// A small write followed by a huge write should top off the internal buffer
+// once and then compress the rest straight from the caller's buffer. Since
+// the block boundaries don't change, the output should be identical to a
+// single write of the concatenation.
 #[test]
-#[cfg(feature = "cpp")]
-fn qc_cpp_decompresses_rust() {
-    fn p(bytes: Vec<u8>) -> bool {
-        let comp_rust = press(&bytes);
-        let decomp_cpp = depress_cpp(&comp_rust);
-        bytes == decomp_cpp
+fn write_frame_encoder_small_then_huge_write() {
+    use snap::write;
+    use std::io::Write;
+
+    let small = b"abc";
+    let huge: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(small).unwrap();
+    assert_eq!(huge.len(), wtr.write(&huge).unwrap());
+    let got = wtr.into_inner().unwrap();
+
+    let mut all = small.to_vec();
+    all.extend_from_slice(&huge);
+    assert_eq!(all, read_frame_depress(&got));
+    assert_eq!(write_frame_press(&all), got);
+}
+
+// Validating compressed data should report the decompressed length without
+// needing an output buffer.
+#[test]
+fn validate_good_data() {
+    for data in [
+        &b""[..],
+        &b"a"[..],
+        &include_bytes!("../data/html")[..],
+        &include_bytes!("../data/fireworks.jpeg")[..],
+        &include_bytes!("../data/Mark.Twain-Tom.Sawyer.txt")[..],
+    ] {
+        assert_eq!(Ok(data.len()), Decoder::new().validate(&press(data)));
     }
-    QuickCheck::new()
-        .gen(StdGen::new(rand::thread_rng(), 10_000))
-        .tests(10_000)
-        .quickcheck(p as fn(_) -> _);
 }
 
+// Compressing a record with a dictionary of similar records should do much
+// better than compressing the record on its own.
 #[test]
-#[cfg(feature = "cpp")]
-fn qc_rust_decompresses_cpp() {
-    fn p(bytes: Vec<u8>) -> bool {
-        let comp_cpp = press_cpp(&bytes);
-        let decomp_rust = depress(&comp_cpp);
-        bytes == decomp_rust
+fn compress_with_dict() {
+    fn record(i: usize) -> Vec<u8> {
+        format!(
+            "{{\"id\":{},\"name\":\"user{}\",\"email\":\"user{}@example.com\",\
+             \"active\":true,\"roles\":[\"reader\",\"writer\"]}}\n",
+            i, i, i
+        )
+        .into_bytes()
     }
-    QuickCheck::new()
-        .gen(StdGen::new(rand::thread_rng(), 10_000))
-        .tests(10_000)
-        .quickcheck(p as fn(_) -> _);
+
+    let dict: Vec<u8> = (0..100).flat_map(record).collect();
+    let mut enc = Encoder::new();
+    for input in
+        [record(1000), record(12345), (0..500).flat_map(record).collect()]
+    {
+        let mut buf = vec![0; snap::raw::max_compress_len(input.len())];
+        let n = enc.compress_with_dict(&dict, &input, &mut buf).unwrap();
+        let with_dict = &buf[..n];
+        assert!(with_dict.len() < press(&input).len());
+        assert_eq!(input, depress_with_dict_spliced(&dict, with_dict));
+    }
+    let input = record(1000);
+    assert!(
+        enc.compress_with_dict(&dict, &input, &mut vec![0; 1024]).unwrap() * 2
+            < press(&input).len()
+    );
+
+    // An empty dictionary is the same as no dictionary.
+    let input = &include_bytes!("../data/html")[..];
+    let mut buf = vec![0; snap::raw::max_compress_len(input.len())];
+    let n = enc.compress_with_dict(b"", input, &mut buf).unwrap();
+    assert_eq!(press(input), &buf[..n]);
 }
 
-// Regression tests.
+// A single Vec should be reusable across many compressions, and another
+// across many decompressions.
+#[test]
+fn compress_into_and_decompress_into() {
+    let inputs: &[&[u8]] = &[
+        include_bytes!("../data/html"),
+        b"",
+        b"abc",
+        include_bytes!("../data/alice29.txt"),
+        include_bytes!("../data/urls.10K"),
+    ];
+    let mut enc = Encoder::new();
+    let mut dec = Decoder::new();
+    let mut comp = vec![];
+    let mut decomp = b"garbage".to_vec();
+    for input in inputs {
+        comp = enc.compress_into(input, comp).unwrap();
+        assert_eq!(press(input), comp);
+        decomp = dec.decompress_into(&comp, decomp).unwrap();
+        assert_eq!(*input, &*decomp);
+    }
 
-// See: https://github.com/BurntSushi/rust-snappy/issues/3
-#[cfg(target_pointer_width = "32")]
-testerrored!(
-    err_lit_len_overflow1,
+    // The allocation is reused when it's already big enough.
+    let cap = comp.capacity();
+    let ptr = comp.as_ptr();
+    comp = enc.compress_into(b"abc", comp).unwrap();
+    assert_eq!((cap, ptr), (comp.capacity(), comp.as_ptr()));
+}
+
+// Decompressing to a writer should produce the same bytes as decompressing
+// into a single buffer, even when the output is much bigger than the window.
+#[test]
+fn decompress_to_writer() {
+    let mut input = vec![];
+    for _ in 0..20 {
+        input.extend_from_slice(include_bytes!("../data/alice29.txt"));
+        input.extend_from_slice(include_bytes!("../data/fireworks.jpeg"));
+    }
+    let comp = Encoder::new().compress_vec(&input).unwrap();
+    let mut dec = Decoder::new();
+    let mut got = vec![];
+    let n = dec.decompress_to_writer(&comp, &mut got).unwrap();
+    assert_eq!(input.len() as u64, n);
+    assert_eq!(dec.decompress_vec(&comp).unwrap(), got);
+
+    // A literal bigger than the window, followed by a copy into its tail.
+    let lit: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let mut comp = vec![0xA5, 0x8D, 0x06, 62 << 2, 0x9F, 0x86, 0x01];
+    comp.extend_from_slice(&lit);
+    comp.extend_from_slice(&[0b000_001_01, 251]);
+    let mut got = vec![];
+    dec.decompress_to_writer(&comp, &mut got).unwrap();
+    assert_eq!(dec.decompress_vec(&comp).unwrap(), got);
+
+    // Errors are the same as for decompressing into a buffer.
+    let err = dec.decompress_to_writer(b"\x05\x00a", vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(dec.decompress_vec(b"\x05\x00a").unwrap_err(), err);
+}
+
+// Copies may reach back exactly 64KB when decompressing to a writer, and no
+// further, regardless of how many bytes the window holds at the time.
+#[test]
+fn decompress_to_writer_window_offset() {
+    // Two literals of 50,000 bytes each, which stay in the window together,
+    // followed by a copy with a 4 byte offset.
+    let block = |offset: u32| {
+        let lit: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let mut comp = vec![0xA4, 0x8D, 0x06];
+        for _ in 0..2 {
+            comp.extend_from_slice(&[61 << 2, 0x4F, 0xC3]);
+            comp.extend_from_slice(&lit);
+        }
+        comp.push(0b000011_11);
+        comp.extend_from_slice(&offset.to_le_bytes());
+        comp
+    };
+    let mut dec = Decoder::new();
+
+    let comp = block(1 << 16);
+    let mut got = vec![];
+    dec.decompress_to_writer(&comp, &mut got).unwrap();
+    assert_eq!(dec.decompress_vec(&comp).unwrap(), got);
+
+    let comp = block((1 << 16) + 1);
+    assert_eq!(100_004, dec.decompress_vec(&comp).unwrap().len());
+    let err = dec.decompress_to_writer(&comp, vec![]).unwrap_err();
+    assert_eq!(
+        Error::Offset { offset: (1 << 16) + 1, dst_pos: 100_000 },
+        unwrap_snap_err(err),
+    );
+}
+
+// Searching while decompressing finds needles anywhere, including ones that
+// straddle the batches of bytes that are searched at a time.
+#[test]
+fn decompress_and_contains() {
+    let html = include_bytes!("../data/html");
+    let needle = b"\x00NEEDLE\x00";
+    let mut dec = Decoder::new();
+    // Decompressed bytes are searched in batches of about 128KB.
+    for at in (131_040..131_160).step_by(3) {
+        let mut input = html.repeat(3);
+        input.splice(at..at, needle.iter().copied());
+        let comp = Encoder::new().compress_vec(&input).unwrap();
+        assert!(dec.decompress_and_contains(&comp, needle).unwrap(), "{}", at);
+    }
+    let comp = Encoder::new().compress_vec(&html.repeat(3)).unwrap();
+    assert!(!dec.decompress_and_contains(&comp, needle).unwrap());
+    assert!(dec.decompress_and_contains(&comp, b"").unwrap());
+    assert!(dec.decompress_and_contains(b"\x00", b"").unwrap());
+    assert!(!dec.decompress_and_contains(b"\x00", b"a").unwrap());
+    assert_eq!(
+        Error::Empty,
+        dec.decompress_and_contains(b"", needle).unwrap_err()
+    );
+    assert_eq!(
+        dec.decompress_vec(b"\x05\x00a").unwrap_err(),
+        dec.decompress_and_contains(b"\x05\x00a", needle).unwrap_err()
+    );
+
+    // The frame format variant searches each chunk in turn.
+    for at in 65_530..65_537 {
+        let mut input = html.repeat(2);
+        input.splice(at..at, needle.iter().copied());
+        let comp = write_frame_press(&input);
+        let found = snap::read::decompress_and_contains(&comp[..], needle);
+        assert!(found.unwrap(), "{}", at);
+    }
+    let comp = write_frame_press(&html.repeat(2));
+    assert!(!snap::read::decompress_and_contains(&comp[..], needle).unwrap());
+    let err = snap::read::decompress_and_contains(&comp[..20], needle);
+    assert!(err.is_err());
+}
+
+// A denser search for matches compresses at least as well as a sparser one,
+// and both roundtrip.
+#[test]
+fn compress_search_skip() {
+    let mut data = include_bytes!("../data/paper-100k.pdf").to_vec();
+    data.extend_from_slice(include_bytes!("../data/alice29.txt"));
+    let press_with_skip = |skip| {
+        let mut enc = Encoder::new();
+        enc.set_search_skip(skip);
+        enc.compress_vec(&data).unwrap()
+    };
+
+    let dense = press_with_skip(1);
+    let default = press_with_skip(32);
+    let sparse = press_with_skip(1024);
+    assert_eq!(press(&data), default);
+    assert!(dense.len() <= default.len());
+    assert!(default.len() <= sparse.len());
+    assert!(dense.len() < sparse.len());
+    for comp in [&dense, &sparse] {
+        assert_eq!(data, depress(comp));
+    }
+    // Values out of range are clamped.
+    assert_eq!(dense, press_with_skip(0));
+    assert_eq!(sparse, press_with_skip(u32::MAX));
+}
+
+// Bounded compression never produces more than 10 bytes more than its input,
+// and otherwise matches ordinary compression.
+#[test]
+fn compress_bounded() {
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let random: Vec<u8> = (0..200_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        })
+        .collect();
+    let mut enc = Encoder::new();
+    let mut buf = vec![0; snap::raw::max_compress_len(random.len())];
+    for len in [0, 1, 60, 61, 256, 257, 65536, 65537, 200_000] {
+        let input = &random[..len];
+        let n = enc.compress_bounded(input, &mut buf).unwrap();
+        assert!(n <= len + 10, "{} bytes compressed to {}", len, n);
+        assert!(n <= press(input).len());
+        assert_eq!(input, &*depress(&buf[..n]));
+    }
+    // Random bytes are expanded by ordinary compression.
+    assert!(press(&random).len() > random.len() + 10);
+
+    let data = &include_bytes!("../data/html")[..];
+    let n = enc.compress_bounded(data, &mut buf).unwrap();
+    assert_eq!(press(data), &buf[..n]);
+}
+
+// Decompressing with statistics counts each literal and copy in a block.
+#[test]
+fn decompress_stats() {
+    // A literal "abcd", a copy of it, a literal "xy" and a copy of that.
+    let comp = b"\x0c\x0cabcd\x01\x04\x04xy\x06\x02\x00";
+    let mut buf = [0; 20];
+    let (n, stats) = Decoder::new().decompress_stats(comp, &mut buf).unwrap();
+    assert_eq!(b"abcdabcdxyxy", &buf[..n]);
+    assert_eq!(2, stats.literals());
+    assert_eq!(6, stats.literal_bytes());
+    assert_eq!(2, stats.copies());
+    assert_eq!(6, stats.copy_bytes());
+    assert_eq!(3.0, stats.average_copy_len());
+
+    // Statistics account for every decompressed byte.
+    let data = include_bytes!("../data/html");
+    let comp = Encoder::new().compress_vec(data).unwrap();
+    let mut buf = vec![0; data.len()];
+    let (n, stats) = Decoder::new().decompress_stats(&comp, &mut buf).unwrap();
+    assert_eq!(&data[..], &buf[..n]);
+    assert_eq!(n as u64, stats.literal_bytes() + stats.copy_bytes());
+
+    let (_, stats) =
+        Decoder::new().decompress_stats(b"\x00", &mut []).unwrap();
+    assert_eq!(snap::raw::DecodeStats::default(), stats);
+    assert_eq!(0.0, stats.average_copy_len());
+    assert_eq!(
+        Decoder::new().decompress(b"\x05\x00a", &mut buf).unwrap_err(),
+        Decoder::new().decompress_stats(b"\x05\x00a", &mut buf).unwrap_err()
+    );
+}
+
+// Compressing at an offset should leave the prefix alone and produce the
+// same bytes as compressing into a separate buffer.
+#[test]
+fn compress_at() {
+    let input = &include_bytes!("../data/html")[..];
+    let mut enc = Encoder::new();
+    let mut buf = vec![0xAB; 8 + snap::raw::max_compress_len(input.len())];
+    let n = enc.compress_at(input, &mut buf, 8).unwrap();
+    assert_eq!(&[0xAB; 8], &buf[..8]);
+    assert_eq!(press(input), &buf[8..8 + n]);
+    assert_eq!(input, &*depress(&buf[8..8 + n]));
+
+    // There must be room for the maximum compressed length after the offset.
+    let len = buf.len();
+    assert_eq!(
+        Error::BufferTooSmall {
+            given: 7,
+            min: snap::raw::max_compress_len(input.len()) as u64,
+        },
+        enc.compress_at(input, &mut buf, len - 7).unwrap_err()
+    );
+    assert_eq!(
+        Error::BufferTooSmall { given: 0, min: 32 },
+        enc.compress_at(b"", &mut buf, len + 1).unwrap_err()
+    );
+}
+
+#[test]
+fn compress_or_store() {
+    use snap::raw::CompressOutcome;
+
+    let mut enc = Encoder::new();
+    let mut buf = vec![0; snap::raw::max_compress_len(1 << 20)];
+
+    let html = &include_bytes!("../data/html")[..];
+    match enc.compress_or_store(html, &mut buf).unwrap() {
+        CompressOutcome::Compressed(n) => assert_eq!(press(html), &buf[..n]),
+        CompressOutcome::Store => panic!("expected html to compress"),
+    }
+
+    let jpeg = &include_bytes!("../data/fireworks.jpeg")[..];
+    assert_eq!(
+        CompressOutcome::Store,
+        enc.compress_or_store(jpeg, &mut buf).unwrap()
+    );
+}
+
+// Decompressing with a dictionary should invert compressing with the same
+// dictionary, and copies may straddle the end of the dictionary.
+#[test]
+fn decompress_with_dict() {
+    let text = &include_bytes!("../data/alice29.txt")[..];
+    let mut enc = Encoder::new();
+    let mut dec = Decoder::new();
+    for (dict, input) in [
+        (&text[..20_000], &text[20_000..60_000]),
+        // The input is mostly a repeat of the dictionary, so nearly every
+        // copy in the first block refers back into it.
+        (&text[..30_000], &text[..40_000]),
+        (&text[..1_000], &text[500..600]),
+    ] {
+        let mut comp = vec![0; snap::raw::max_compress_len(input.len())];
+        let n = enc.compress_with_dict(dict, input, &mut comp).unwrap();
+        let mut out = vec![0; input.len()];
+        assert_eq!(
+            input.len(),
+            dec.decompress_with_dict(dict, &comp[..n], &mut out).unwrap()
+        );
+        assert_eq!(input, &*out);
+    }
+
+    // A copy of 8 bytes at offset 4 takes its first 4 bytes from the end of
+    // the dictionary and its last 4 bytes from the output.
+    let comp = [8, 0b000_100_01, 4];
+    let mut out = [0; 8];
+    dec.decompress_with_dict(b"abcdefgh", &comp, &mut out).unwrap();
+    assert_eq!(b"efghefgh", &out);
+
+    // Copies can't reach before the start of the dictionary.
+    let comp = [8, 0b000_100_01, 9];
+    assert_eq!(
+        Error::Offset { offset: 9, dst_pos: 0 },
+        dec.decompress_with_dict(b"abcdefgh", &comp, &mut out).unwrap_err()
+    );
+}
+
+// Decompressing into a buffer that is bigger than necessary should report
+// the exact decompressed length and leave the rest of the buffer untouched.
+#[test]
+fn decompress_into_oversized_buffer() {
+    let data = &include_bytes!("../data/html")[..];
+    let compressed = press(data);
+
+    let mut buf = vec![0xAB; data.len() * 2];
+    let n = Decoder::new().decompress(&compressed, &mut buf).unwrap();
+    assert_eq!(data.len(), n);
+    assert_eq!(data, &buf[..n]);
+    assert!(buf[n..].iter().all(|&b| b == 0xAB));
+}
+
+// Writes made up of whole blocks skip the internal buffer, but must produce
+// exactly the same output as if they had been buffered.
+#[test]
+fn write_frame_encoder_block_aligned_writes() {
+    use snap::write;
+    use std::io::Write;
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let block = 1 << 16;
+    let aligned = &data[..(data.len() / block) * block];
+    assert!(aligned.len() >= 2 * block);
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(&aligned[..block]).unwrap();
+    wtr.write_all(&aligned[block..]).unwrap();
+    wtr.write_all(&data[aligned.len()..]).unwrap();
+    let got = wtr.into_inner().unwrap();
+
+    // Small writes always go through the internal buffer.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for chunk in data.chunks(1000) {
+        wtr.write_all(chunk).unwrap();
+    }
+    let expected = wtr.into_inner().unwrap();
+    assert_eq!(expected, got);
+}
+
+// Each call to flush_block should end a chunk without flushing the underlying
+// writer, while flush should do both.
+#[test]
+fn write_frame_encoder_flush_block() {
+    use snap::write;
+    use std::io::{self, Write};
+
+    struct CountFlushes {
+        buf: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for CountFlushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    let records: &[&[u8]] = &[b"record one\n", b"record two\n", b"three\n"];
+    let mut wtr =
+        write::FrameEncoder::new(CountFlushes { buf: vec![], flushes: 0 });
+    for record in records {
+        wtr.write_all(record).unwrap();
+        wtr.flush_block().unwrap();
+        // Flushing an empty buffer shouldn't produce an empty chunk.
+        wtr.flush_block().unwrap();
+    }
+    assert_eq!(0, wtr.get_ref().flushes);
+
+    // The stream identifier is followed by one chunk for each record.
+    let mut expected = write_frame_press(records[0]);
+    for record in &records[1..] {
+        expected.extend_from_slice(&write_frame_press(record)[10..]);
+    }
+    assert_eq!(expected, wtr.get_ref().buf);
+    assert_eq!(records.concat(), read_frame_depress(&wtr.get_ref().buf));
+
+    wtr.write_all(b"four\n").unwrap();
+    wtr.flush().unwrap();
+    assert_eq!(1, wtr.get_ref().flushes);
+    assert_eq!(
+        [records.concat(), b"four\n".to_vec()].concat(),
+        read_frame_depress(&wtr.get_ref().buf)
+    );
+}
+
+// Dropping an encoder flushes its buffered bytes exactly once, and consuming
+// it with into_inner doesn't flush them again when it's gone.
+#[test]
+fn write_frame_encoder_drop_flushes_once() {
+    use snap::write;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let out = Rc::new(RefCell::new(vec![]));
+    let mut wtr = write::FrameEncoder::new(Shared(out.clone()));
+    wtr.write_all(b"first chunk, ").unwrap();
+    wtr.flush_block().unwrap();
+    wtr.write_all(b"buffered").unwrap();
+    drop(wtr);
+    assert_eq!(b"first chunk, buffered", &*read_frame_depress(&out.borrow()));
+
+    let out = Rc::new(RefCell::new(vec![]));
+    let mut wtr = write::FrameEncoder::new(Shared(out.clone()));
+    wtr.write_all(b"buffered").unwrap();
+    let inner = wtr.into_inner().unwrap();
+    drop(inner);
+    assert_eq!(b"buffered", &*read_frame_depress(&out.borrow()));
+}
+
+// The streaming types are Send and Sync whenever what they wrap is.
+#[test]
+fn send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<snap::write::FrameEncoder<Vec<u8>>>();
+    assert_send_sync::<snap::write::FrameDecoder<Vec<u8>>>();
+    assert_send_sync::<snap::read::FrameEncoder<&[u8]>>();
+    assert_send_sync::<snap::read::FrameDecoder<&[u8]>>();
+    assert_send_sync::<snap::raw::Encoder>();
+    assert_send_sync::<snap::raw::Decoder>();
+}
+
+// The running compression ratio should drop while writing compressible data
+// and rise again once incompressible data is written.
+#[test]
+fn write_frame_encoder_ratio() {
+    use snap::write;
+    use std::io::Write;
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    assert_eq!(1.0, wtr.ratio());
+
+    wtr.write_all(include_bytes!("../data/html")).unwrap();
+    wtr.flush().unwrap();
+    let compressible = wtr.ratio();
+    assert!(compressible < 0.5, "ratio: {}", compressible);
+    assert_eq!(
+        wtr.get_ref().len() as f64
+            / include_bytes!("../data/html").len() as f64,
+        compressible
+    );
+
+    wtr.write_all(include_bytes!("../data/fireworks.jpeg")).unwrap();
+    wtr.flush().unwrap();
+    let mixed = wtr.ratio();
+    assert!(mixed > compressible, "{} <= {}", mixed, compressible);
+
+    wtr.reset(vec![]).unwrap();
+    assert_eq!(1.0, wtr.ratio());
+}
+
+// Inputs right around the maximum block size should be split into the fewest
+// blocks possible, in both the raw and frame formats.
+#[test]
+fn max_block_size_boundaries() {
+    use snap::write;
+    use std::io::Write;
+
+    // Returns the uncompressed length of each data chunk in a frame stream.
+    fn chunk_lens(stream: &[u8]) -> Vec<usize> {
+        let mut lens = vec![];
+        let mut rest = &stream[10..];
+        while !rest.is_empty() {
+            let len = rest[1] as usize
+                | (rest[2] as usize) << 8
+                | (rest[3] as usize) << 16;
+            let body = &rest[8..4 + len];
+            lens.push(match rest[0] {
+                0x00 => decompress_len(body).unwrap(),
+                0x01 => body.len(),
+                ty => panic!("unexpected chunk type: {}", ty),
+            });
+            rest = &rest[4 + len..];
+        }
+        lens
+    }
+
+    const BLOCK: usize = 1 << 16;
+    let data = &include_bytes!("../data/html_x_4")[..];
+    for (size, expected) in [
+        (BLOCK - 1, vec![BLOCK - 1]),
+        (BLOCK, vec![BLOCK]),
+        (BLOCK + 1, vec![BLOCK, 1]),
+        (2 * BLOCK, vec![BLOCK, BLOCK]),
+    ] {
+        let input = &data[..size];
+        roundtrip!(input);
+
+        let compressed = write_frame_press(input);
+        assert_eq!(expected, chunk_lens(&compressed), "size: {}", size);
+        assert_eq!(input, &*read_frame_depress(&compressed));
+        assert_eq!(compressed, read_frame_press(input));
+
+        // Splitting the write shouldn't change where blocks end.
+        let mut wtr = write::FrameEncoder::new(vec![]);
+        wtr.write_all(&input[..1]).unwrap();
+        wtr.write_all(&input[1..]).unwrap();
+        assert_eq!(compressed, wtr.into_inner().unwrap(), "size: {}", size);
+    }
+}
+
+// A write::FrameDecoder should only report a complete stream when no partial
+// chunk is pending.
+#[test]
+fn write_frame_decoder_stream_complete() {
+    use snap::write;
+    use std::io::Write;
+
+    let data = &include_bytes!("../data/html")[..];
+    let compressed = write_frame_press(data);
+
+    let mut wtr = write::FrameDecoder::new(vec![]);
+    assert!(!wtr.is_stream_complete());
+    // Just the first part of the stream identifier.
+    wtr.write_all(&compressed[..3]).unwrap();
+    assert!(!wtr.is_stream_complete());
+    assert_eq!(3, wtr.pending_input());
+    wtr.write_all(&compressed[3..]).unwrap();
+    assert!(wtr.is_stream_complete());
+    assert_eq!(0, wtr.pending_input());
+    assert_eq!(data, &**wtr.get_ref());
+
+    // Start a second stream, but only write part of its first data chunk.
+    let next = write_frame_press(b"more data");
+    wtr.write_all(&next[..next.len() - 2]).unwrap();
+    assert!(!wtr.is_stream_complete());
+    assert_eq!(next.len() - 10 - 2, wtr.pending_input());
+    assert_eq!(data, &**wtr.get_ref());
+    wtr.write_all(&next[next.len() - 2..]).unwrap();
+    assert!(wtr.is_stream_complete());
+    assert_eq!([data, b"more data"].concat(), wtr.into_inner());
+}
+
+// Concatenated streams decode through a write::FrameDecoder just like they do
+// through a read::FrameDecoder, no matter how the writes are split up.
+#[test]
+fn write_frame_decoder_concatenated_streams() {
+    use snap::write;
+    use std::io::Write;
+
+    let first = &include_bytes!("../data/html")[..];
+    let second = &b"second stream"[..];
+    let compressed =
+        [write_frame_press(first), write_frame_press(second)].concat();
+    let expected = [first, second].concat();
+    assert_eq!(expected, read_frame_depress(&compressed));
+    for size in 1..=17 {
+        let mut wtr = write::FrameDecoder::new(vec![]);
+        for piece in compressed.chunks(size) {
+            wtr.write_all(piece).unwrap();
+        }
+        assert!(wtr.is_stream_complete());
+        assert_eq!(expected, wtr.into_inner(), "{}", size);
+    }
+
+    // A corrupt stream identifier in the middle is an error for both.
+    let mut corrupt = compressed.clone();
+    let at = compressed.len() - second.len() - 8 - 10 + 4;
+    corrupt[at] = b'X';
+    let mut wtr = write::FrameDecoder::new(vec![]);
+    let err = wtr.write_all(&corrupt).unwrap_err();
+    let err = unwrap_snap_err(err);
+    let bytes = b"XNaPpY".to_vec();
+    assert_eq!(Error::StreamHeaderMismatch { bytes }, err);
+    assert_eq!(first, &**wtr.get_ref());
+}
+
+#[test]
+fn write_frame_encoder_skippable_chunk() {
+    use snap::write;
+    use std::io::{ErrorKind, Write};
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"before ").unwrap();
+    wtr.write_skippable_chunk(0x80, b"metadata").unwrap();
+    wtr.write_all(b"after").unwrap();
+    let err = wtr.write_skippable_chunk(0xFE, b"padding").unwrap_err();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    let err = wtr.write_skippable_chunk(0x7F, b"").unwrap_err();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    let got = wtr.into_inner().unwrap();
+
+    let mut expected = write_frame_press(b"before ");
+    expected.extend_from_slice(b"\x80\x08\x00\x00metadata");
+    expected.extend_from_slice(&write_frame_press(b"after")[10..]);
+    assert_eq!(expected, got);
+    assert_eq!(b"before after", &*read_frame_depress(&got));
+    assert_eq!(b"before after", &*write_frame_depress(&got));
+}
+
+// An output limit stops the encoder before the chunk that would exceed it,
+// leaving a valid stream of the chunks written before it.
+#[test]
+fn write_frame_encoder_output_limit() {
+    use snap::write;
+    use std::io::{ErrorKind, Write};
+
+    let data = include_bytes!("../data/html");
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_block(&data[..1000]).unwrap();
+    wtr.write_block(&data[1000..2000]).unwrap();
+    let prefix = wtr.into_inner().unwrap();
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_output_limit(prefix.len() as u64 + 7);
+    wtr.write_block(&data[..1000]).unwrap();
+    wtr.write_block(&data[1000..2000]).unwrap();
+    let err = wtr.write_block(&data[2000..3000]).unwrap_err();
+    assert_eq!(ErrorKind::WriteZero, err.kind());
+    // Buffered bytes are checked when they're written as a chunk.
+    wtr.write_all(&data[2000..3000]).unwrap();
+    let err = wtr.flush().unwrap_err();
+    assert_eq!(ErrorKind::WriteZero, err.kind());
+    assert_eq!(prefix, *wtr.get_ref());
+    assert_eq!(&data[..2000], &*read_frame_depress(wtr.get_ref()));
+
+    // Even the stream identifier counts towards the limit.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_output_limit(9);
+    let err = wtr.write_block(b"abc").unwrap_err();
+    assert_eq!(ErrorKind::WriteZero, err.kind());
+    assert!(wtr.get_ref().is_empty());
+
+    // The count of bytes written starts over when the writer is replaced.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_output_limit(prefix.len() as u64);
+    wtr.write_block(&data[..1000]).unwrap();
+    wtr.write_block(&data[1000..2000]).unwrap();
+    assert_eq!(prefix, wtr.reset(vec![]).unwrap());
+    wtr.write_block(&data[..1000]).unwrap();
+    wtr.write_block(&data[1000..2000]).unwrap();
+    assert_eq!(prefix, wtr.into_inner().unwrap());
+}
+
+// Each block written with write_block becomes exactly one chunk.
+#[test]
+fn write_frame_encoder_write_block() {
+    use snap::write;
+    use std::io::{ErrorKind, Write};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let blocks = [&data[..100], &data[100..65636], &data[65636..66636]];
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for block in blocks {
+        wtr.write_block(block).unwrap();
+    }
+    let err = wtr.write_block(&data[..65537]).unwrap_err();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    let got = wtr.into_inner().unwrap();
+
+    assert_eq!(&data[..66636], &*read_frame_depress(&got));
+    let mut lens = vec![];
+    let mut pos = 10;
+    while pos < got.len() {
+        let len =
+            u32::from_le_bytes([got[pos + 1], got[pos + 2], got[pos + 3], 0]);
+        let body = &got[pos + 4..pos + 4 + len as usize];
+        lens.push(match got[pos] {
+            0x00 => snap::raw::decompress_len(&body[4..]).unwrap(),
+            0x01 => body.len() - 4,
+            ty => panic!("unexpected chunk type {:#X}", ty),
+        });
+        pos += 4 + len as usize;
+    }
+    assert_eq!(vec![100, 65536, 1000], lens);
+
+    // Buffered bytes are written as their own chunk first.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"abc").unwrap();
+    wtr.write_block(b"").unwrap();
+    wtr.write_block(b"def").unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(b"abcdef", &*read_frame_depress(&got));
+    assert_eq!(10 + 3 * 8 + 6, got.len());
+}
+
+// Hints apply only to the bytes they're given, and every hint produces a
+// valid stream.
+#[test]
+fn write_frame_encoder_write_with_hint() {
+    use snap::write::{CompressHint, FrameEncoder};
+    use std::io::Write;
+
+    let text = &include_bytes!("../data/alice29.txt")[..];
+    let jpeg = &include_bytes!("../data/fireworks.jpeg")[..];
+    let mut wtr = FrameEncoder::new(vec![]);
+    wtr.write_all(b"abc").unwrap();
+    wtr.write_with_hint(jpeg, CompressHint::Fast).unwrap();
+    wtr.write_with_hint(text, CompressHint::Best).unwrap();
+    wtr.write_all(b"xyz").unwrap();
+    let got = wtr.into_inner().unwrap();
+
+    let mut want = b"abc".to_vec();
+    want.extend_from_slice(jpeg);
+    want.extend_from_slice(text);
+    want.extend_from_slice(b"xyz");
+    assert_eq!(want, read_frame_depress(&got));
+
+    let with_hint = |data: &[u8], hint: CompressHint| -> Vec<u8> {
+        let mut wtr = FrameEncoder::new(vec![]);
+        wtr.write_with_hint(data, hint).unwrap();
+        wtr.into_inner().unwrap()
+    };
+    let fast = with_hint(text, CompressHint::Fast);
+    let default = with_hint(text, CompressHint::Default);
+    let best = with_hint(text, CompressHint::Best);
+    assert_eq!(default, write_frame_press(text));
+    assert!(best.len() <= default.len());
+    assert!(default.len() <= fast.len());
+    for got in [fast, default, best] {
+        assert_eq!(text, &*read_frame_depress(&got));
+    }
+}
+
+// A VecEncoder produces the same bytes as FrameEncoder, however its input is
+// split up.
+#[test]
+fn write_vec_encoder() {
+    use snap::write::VecEncoder;
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let want = write_frame_press(data);
+    for size in [1, 1000, 65535, 65536, 65537, 200_000] {
+        let mut enc = VecEncoder::new();
+        for piece in data.chunks(size) {
+            enc.extend_from_slice(piece);
+        }
+        assert_eq!(want, enc.finish(), "pieces of {} bytes", size);
+    }
+
+    let mut enc = VecEncoder::with_vec(b"prefix".to_vec());
+    enc.extend_from_slice(b"");
+    assert_eq!(b"prefix", &**enc.get_ref());
+    enc.extend_from_slice(b"abc");
+    assert_eq!(b"prefix", &**enc.get_ref());
+    let got = enc.finish();
+    assert_eq!(b"prefix", &got[..6]);
+    assert_eq!(write_frame_press(b"abc"), &got[6..]);
+
+    assert!(VecEncoder::new().finish().is_empty());
+}
+
+// An encoder that receives no data writes nothing by default, and just the
+// stream identifier when asked to emit empty streams.
+#[test]
+fn write_frame_encoder_emit_empty_stream() {
+    use snap::write;
+    use std::io::Write;
+
+    let wtr = write::FrameEncoder::new(vec![]);
+    assert!(wtr.into_inner().unwrap().is_empty());
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_emit_empty_stream(true);
+    wtr.write_all(b"").unwrap();
+    wtr.flush().unwrap();
+    wtr.flush().unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(b"\xFF\x06\x00\x00sNaPpY", &*got);
+    assert!(read_frame_depress(&got).is_empty());
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_emit_empty_stream(true);
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(b"\xFF\x06\x00\x00sNaPpY", &*got);
+
+    // Streams with data are unaffected.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_emit_empty_stream(true);
+    wtr.write_all(b"abc").unwrap();
+    assert_eq!(write_frame_press(b"abc"), wtr.into_inner().unwrap());
+}
+
+// A length prefixed stream roundtrips, its prefix is the length of the frame
+// stream that follows it, and several can be read back to back.
+#[test]
+fn length_prefixed_roundtrip() {
+    use snap::{read, write};
+    use std::io::{ErrorKind, Read, Write};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut wtr = write::LengthPrefixedEncoder::new(vec![]);
+    wtr.write_all(data).unwrap();
+    wtr.flush().unwrap();
+    assert!(wtr.get_ref().is_empty());
+    let mut stored = wtr.finish().unwrap();
+    let frame = write_frame_press(data);
+    assert_eq!((frame.len() as u64).to_le_bytes(), stored[..8]);
+    assert_eq!(frame, &stored[8..]);
+
+    let mut wtr = write::LengthPrefixedEncoder::new(stored);
+    wtr.write_all(b"second").unwrap();
+    stored = wtr.finish().unwrap();
+    stored.extend_from_slice(b"trailer");
+
+    let mut rdr = read::LengthPrefixedDecoder::new(&stored[..]).unwrap();
+    assert_eq!(frame.len() as u64, rdr.compressed_len());
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+    let mut rdr = read::LengthPrefixedDecoder::new(rdr.into_inner()).unwrap();
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(b"second", &*got);
+    assert_eq!(b"trailer", rdr.into_inner());
+
+    // A stream that is cut short is an error.
+    let err = read::LengthPrefixedDecoder::new(&stored[..4]).unwrap_err();
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    let mut rdr = read::LengthPrefixedDecoder::new(&stored[..20]).unwrap();
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+
+    // The buffered stream can be bounded.
+    let mut wtr = write::LengthPrefixedEncoder::new(vec![]);
+    wtr.set_max_len(1000);
+    let err = wtr.write_all(data).unwrap_err();
+    assert_eq!(ErrorKind::WriteZero, err.kind());
+}
+
+// An eager encoder should write the stream identifier before any data, and
+// only once.
+#[test]
+fn write_frame_encoder_new_eager() {
+    use snap::write;
+    use std::io::Write;
+
+    let mut wtr = write::FrameEncoder::new_eager(vec![]).unwrap();
+    assert_eq!(b"\xFF\x06\x00\x00sNaPpY", &**wtr.get_ref());
+    wtr.flush().unwrap();
+    assert_eq!(b"\xFF\x06\x00\x00sNaPpY", &**wtr.get_ref());
+
+    wtr.write_all(b"abc").unwrap();
+    assert_eq!(write_frame_press(b"abc"), wtr.into_inner().unwrap());
+}
+
+// The in-memory conveniences on the frame types match the streaming APIs.
+#[test]
+fn frame_compress_all_decompress_all() {
+    use snap::{read::FrameDecoder, write::FrameEncoder};
+
+    let data = include_bytes!("../data/html_x_4");
+    let compressed = FrameEncoder::compress_all(data);
+    assert_eq!(write_frame_press(data), compressed);
+    assert_eq!(
+        &data[..],
+        &*FrameDecoder::decompress_all(&compressed).unwrap()
+    );
+    assert!(FrameDecoder::decompress_all(&FrameEncoder::compress_all(b""))
+        .unwrap()
+        .is_empty());
+    FrameDecoder::decompress_all(&compressed[..20]).unwrap_err();
+}
+
+// Decompressing a stream that is already in memory should give the same
+// result as reading it through a FrameDecoder.
+#[test]
+fn read_decompress_slice() {
+    use snap::read;
+
+    let inputs: &[&[u8]] = &[
+        b"",
+        b"abc",
+        include_bytes!("../data/html"),
+        include_bytes!("../data/fireworks.jpeg"),
+        include_bytes!("../data/paper-100k.pdf"),
+        include_bytes!("../data/alice29.txt"),
+        include_bytes!("../data/kppkn.gtb"),
+    ];
+    for input in inputs {
+        let comp = write_frame_press(input);
+        let got = read::decompress_slice(&comp).unwrap();
+        assert_eq!(read_frame_depress(&comp), got);
+        assert_eq!(*input, &*got);
+
+        let mut got = vec![];
+        let n = read::decompress_slice_to_writer(&comp, &mut got).unwrap();
+        assert_eq!(input.len() as u64, n);
+        assert_eq!(*input, &*got);
+    }
+
+    // Truncated streams are reported just like FrameDecoder reports them.
+    let comp = write_frame_press(b"abcdefghijklmnopqrstuvwxyz");
+    let err = read::decompress_slice(&comp[..12]).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+    let err = read::decompress_slice(&comp[..comp.len() - 1]).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+    assert!(err.to_string().contains("expected 30 bytes but got 29"));
+}
+
+// Many threads compressing through one pool should all get correct output,
+// and the pool should keep the states they return.
+#[test]
+fn write_encoder_pool() {
+    use snap::write::EncoderPool;
+
+    let inputs: &[&[u8]] = &[
+        include_bytes!("../data/html"),
+        b"",
+        b"abc",
+        include_bytes!("../data/alice29.txt"),
+        include_bytes!("../data/fireworks.jpeg"),
+        include_bytes!("../data/urls.10K"),
+    ];
+    let pool = EncoderPool::new();
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for input in inputs {
+                    let got = pool.get().compress_frame_vec(input).unwrap();
+                    assert_eq!(write_frame_press(input), got);
+                    assert_eq!(*input, &*read_frame_depress(&got));
+                }
+            });
+        }
+    });
+    let available = pool.available();
+    assert!((1..=4).contains(&available));
+
+    // Holding a state means the next one is taken from what's left.
+    let held = pool.get();
+    assert_eq!(available - 1, pool.available());
+    drop(held);
+    assert_eq!(available, pool.available());
+}
+
+// Appending to an existing stream should not repeat the stream identifier,
+// and the result should decode as one continuous stream.
+#[test]
+fn write_frame_encoder_reset_appending() {
+    use snap::write;
+    use std::io::Write;
+
+    let existing = write_frame_press(b"the first part, ");
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"unrelated").unwrap();
+    let unrelated = wtr.reset_appending(existing).unwrap();
+    assert_eq!(write_frame_press(b"unrelated"), unrelated);
+
+    wtr.write_all(b"and the second part").unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(
+        1,
+        got.windows(10).filter(|w| w == b"\xFF\x06\x00\x00sNaPpY").count()
+    );
+    assert_eq!(
+        &b"the first part, and the second part"[..],
+        &*read_frame_depress(&got)
+    );
+
+    // A regular reset writes the stream identifier again.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"a").unwrap();
+    assert_eq!(write_frame_press(b"a"), wtr.reset(vec![]).unwrap());
+    wtr.write_all(b"b").unwrap();
+    assert_eq!(write_frame_press(b"b"), wtr.into_inner().unwrap());
+}
+
+// Tests decompression on malformed data.
+
+// An empty buffer.
+testerrored!(err_empty, &b""[..], Error::Empty);
+
+// Decompress fewer bytes than the header reports.
+testerrored!(
+    err_header_mismatch,
+    &b"\x05\x00a"[..],
+    Error::HeaderMismatch { expected_len: 5, got_len: 1 }
+);
+
+// A header reporting no decompressed bytes, followed by more bytes.
+testerrored!(
+    err_header_mismatch_empty,
+    &b"\x00extra"[..],
+    Error::HeaderMismatch { expected_len: 0, got_len: 5 }
+);
+
+// An invalid varint (final byte has continuation bit set).
+testerrored!(err_varint1, &b"\xFF"[..], Error::Header, true);
+
+// A varint that overflows u64.
+testerrored!(
+    err_varint2,
+    &b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x00"[..],
+    Error::Header,
+    true
+);
+
+// A varint that fits in u64 but overflows u32.
+testerrored!(
+    err_varint3,
+    &b"\x80\x80\x80\x80\x10"[..],
+    Error::TooBig { given: 4294967296, max: 4294967295 },
+    true
+);
+
+// A literal whose length is too small.
+// Since the literal length is 1, 'h' is read as a literal and 'i' is
+// interpreted as a copy 1 operation missing its offset byte.
+testerrored!(
+    err_lit,
+    &b"\x02\x00hi"[..],
+    Error::CopyRead { len: 1, src_len: 0 }
+);
+// A literal whose length is too big.
+testerrored!(
+    err_lit_big1,
+    &b"\x02\xechi"[..],
+    Error::Literal { len: 60, src_len: 2, dst_len: 2 }
+);
+// A literal whose length is too big, requires 1 extra byte to be read, and
+// src is too short to read that byte.
+testerrored!(
+    err_lit_big2a,
+    &b"\x02\xf0hi"[..],
+    Error::Literal { len: 4, src_len: 2, dst_len: 2 }
+);
+// A literal whose length is too big, requires 1 extra byte to be read,
+// src is too short to read the full literal.
+testerrored!(
+    err_lit_big2b,
+    &b"\x02\xf0hi\x00\x00\x00"[..],
+    Error::Literal {
+        len: 105, // because 105 == 'h' as u8 + 1
+        src_len: 4,
+        dst_len: 2,
+    }
+);
+
+// A copy 1 operation that stops at the tag byte. This fails because there's
+// no byte to read for the copy offset.
+testerrored!(
+    err_copy1,
+    &b"\x02\x00a\x01"[..],
+    Error::CopyRead { len: 1, src_len: 0 }
+);
+// A copy 2 operation that stops at the tag byte and another copy 2 operation
+// that stops after the first byte in the offset.
+testerrored!(
+    err_copy2a,
+    &b"\x11\x00a\x3e"[..],
+    Error::CopyRead { len: 2, src_len: 0 }
+);
+testerrored!(
+    err_copy2b,
+    &b"\x11\x00a\x3e\x01"[..],
+    Error::CopyRead { len: 2, src_len: 1 }
+);
+// Same as copy 2, but for copy 4.
+testerrored!(
+    err_copy3a,
+    &b"\x11\x00a\x3f"[..],
+    Error::CopyRead { len: 4, src_len: 0 }
+);
+testerrored!(
+    err_copy3b,
+    &b"\x11\x00a\x3f\x00"[..],
+    Error::CopyRead { len: 4, src_len: 1 }
+);
+testerrored!(
+    err_copy3c,
+    &b"\x11\x00a\x3f\x00\x00"[..],
+    Error::CopyRead { len: 4, src_len: 2 }
+);
+testerrored!(
+    err_copy3d,
+    &b"\x11\x00a\x3f\x00\x00\x00"[..],
+    Error::CopyRead { len: 4, src_len: 3 }
+);
+
+// A copy operation whose offset is zero.
+testerrored!(
+    err_copy_offset_zero,
+    &b"\x11\x00a\x01\x00"[..],
+    Error::Offset { offset: 0, dst_pos: 1 }
+);
+
+// A copy operation whose offset is too big.
+testerrored!(
+    err_copy_offset_big,
+    &b"\x11\x00a\x01\xFF"[..],
+    Error::Offset { offset: 255, dst_pos: 1 }
+);
+
+// A copy operation whose length is too big.
+testerrored!(
+    err_copy_len_big,
+    &b"\x05\x00a\x1d\x01"[..],
+    Error::CopyWrite { len: 11, dst_len: 4 }
+);
+
+// The message for an unsupported chunk type says which range of reserved
+// chunk types it falls in.
+#[test]
+fn err_unsupported_chunk_type_display() {
+    use snap::read;
+    use std::io::Read;
+
+    let mut stream = b"\xFF\x06\x00\x00sNaPpY".to_vec();
+    stream.extend_from_slice(b"\x10\x01\x00\x00x");
+    let mut got = vec![];
+    let err = read::FrameDecoder::new(&stream[..])
+        .read_to_end(&mut got)
+        .unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(Error::UnsupportedChunkType { byte: 0x10 }, err);
+    let msg = err.to_string();
+    assert!(msg.contains("0x10"), "{}", msg);
+    assert!(msg.contains("reserved unskippable range 0x02-0x7F"), "{}", msg);
+
+    let msg = Error::UnsupportedChunkType { byte: 0xE0 }.to_string();
+    assert!(msg.contains("224 (0xE0)"), "{}", msg);
+    assert!(msg.contains("reserved skippable range 0x80-0xFD"), "{}", msg);
+}
+
+// Selected random inputs pulled from quickcheck failure witnesses.
+testtrip!(
+    random1,
+    &[
+        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0,
+        1, 1, 0, 0, 1, 2, 0, 0, 2, 1, 0, 0, 2, 2, 0, 0, 0, 6, 0, 0, 3, 1, 0,
+        0, 0, 7, 0, 0, 1, 3, 0, 0, 0, 8, 0, 0, 2, 3, 0, 0, 0, 9, 0, 0, 1, 4,
+        0, 0, 1, 0, 0, 3, 0, 0, 1, 0, 1, 0, 0, 0, 10, 0, 0, 0, 0, 2, 4, 0, 0,
+        2, 0, 0, 3, 0, 1, 0, 0, 1, 5, 0, 0, 6, 0, 0, 0, 0, 11, 0, 0, 1, 6, 0,
+        0, 1, 7, 0, 0, 0, 12, 0, 0, 3, 2, 0, 0, 0, 13, 0, 0, 2, 5, 0, 0, 0, 3,
+        3, 0, 0, 0, 1, 8, 0, 0, 1, 0, 1, 0, 0, 0, 4, 1, 0, 0, 0, 0, 14, 0, 0,
+        0, 1, 9, 0, 0, 0, 1, 10, 0, 0, 0, 0, 1, 11, 0, 0, 0, 1, 0, 2, 0, 0, 0,
+        1, 1, 1, 0, 0, 0, 0, 5, 1, 0, 0, 0, 1, 2, 1, 0, 0, 0, 0, 0, 2, 6, 0,
+        0, 0, 0, 0, 1, 12, 0, 0, 0, 0, 0, 3, 4, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0,
+        0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0,
+    ]
+);
+testtrip!(
+    random2,
+    &[
+        10, 2, 14, 13, 0, 8, 2, 10, 2, 14, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0,
+    ]
+);
+testtrip!(
+    random3,
+    &[0, 0, 0, 4, 1, 4, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]
+);
+testtrip!(
+    random4,
+    &[
+        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0,
+        1, 1, 0, 0, 1, 2, 0, 0, 1, 3, 0, 0, 1, 4, 0, 0, 2, 1, 0, 0, 0, 4, 0,
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+    ]
+);
+
+// QuickCheck properties for testing that random data roundtrips.
+// These properties tend to produce the inputs for the "random" tests above.
+
+#[test]
+fn qc_roundtrip() {
+    fn p(bytes: Vec<u8>) -> bool {
+        depress(&press(&bytes)) == bytes
+    }
+    QuickCheck::new()
+        .gen(StdGen::new(rand::thread_rng(), 10_000))
+        .tests(1_000)
+        .quickcheck(p as fn(_) -> _);
+}
+
+#[test]
+fn qc_roundtrip_stream() {
+    fn p(bytes: Vec<u8>) -> TestResult {
+        if bytes.is_empty() {
+            return TestResult::discard();
+        }
+        TestResult::from_bool(
+            read_frame_depress(&write_frame_press(&bytes)) == bytes,
+        )
+    }
+    QuickCheck::new()
+        .gen(StdGen::new(rand::thread_rng(), 10_000))
+        .tests(1_000)
+        .quickcheck(p as fn(_) -> _);
+}
+
+#[test]
+fn chunk_type_roundtrip() {
+    use snap::frame::ChunkType;
+    use std::convert::TryFrom;
+
+    for &(ty, byte) in &[
+        (ChunkType::Stream, 0xFF),
+        (ChunkType::Compressed, 0x00),
+        (ChunkType::Uncompressed, 0x01),
+        (ChunkType::Padding, 0xFE),
+    ] {
+        assert_eq!(byte, ty.as_u8());
+        assert_eq!(Ok(ty), ChunkType::try_from(ty.as_u8()));
+    }
+    for byte in 0x02..=0xFD {
+        assert_eq!(Err(byte), ChunkType::try_from(byte));
+    }
+}
+
+// Decoding a stream one chunk at a time gives the same bytes as decoding it
+// all at once.
+#[test]
+fn frame_decompress_chunk() {
+    use snap::frame::decompress_chunk;
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let jpeg = &include_bytes!("../data/fireworks.jpeg")[..1000];
+    let mut stream = write_frame_press(data);
+    // A padding chunk, then another stream with an uncompressed chunk.
+    stream.extend_from_slice(b"\xFE\x02\x00\x00ab");
+    stream.extend_from_slice(&write_frame_press(jpeg));
+
+    let mut got = vec![];
+    let mut input = &stream[..];
+    let mut chunks = 0;
+    while !input.is_empty() {
+        let (chunk, n) = decompress_chunk(input, true).unwrap();
+        got.extend_from_slice(&chunk);
+        input = &input[n..];
+        chunks += 1;
+    }
+    assert_eq!([data, jpeg].concat(), got);
+    assert_eq!(1 + (data.len() + 65535) / 65536 + 1 + 1 + 1, chunks);
+
+    // A chunk need not follow a stream identifier.
+    let (chunk, n) = decompress_chunk(&stream[10..], true).unwrap();
+    assert_eq!(&data[..65536], &*chunk);
+
+    // Checksums are only checked when asked to.
+    let mut corrupt = stream[10..10 + n].to_vec();
+    corrupt[4] ^= 0xFF;
+    let err = decompress_chunk(&corrupt, true).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert!(matches!(err, Error::Checksum { .. }));
+    assert_eq!(chunk, decompress_chunk(&corrupt, false).unwrap().0);
+
+    let err = decompress_chunk(&stream[10..10 + n - 1], true).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+    let want = format!("expected {} bytes but got {}", n - 4, n - 5);
+    assert!(err.to_string().contains(&want));
+    let err = decompress_chunk(&stream[..3], true).unwrap_err();
+    assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+    assert!(err.to_string().contains("expected 4 bytes but got 3"));
+}
+
+// Initializing ahead of time is optional and may be done any number of times.
+#[test]
+fn init() {
+    snap::init();
+    let accelerated = snap::frame::crc32_hardware_accelerated();
+    snap::init();
+    assert_eq!(accelerated, snap::frame::crc32_hardware_accelerated());
+}
+
+// With the no-simd feature, only portable code paths are used, and they
+// still roundtrip.
+#[cfg(feature = "no-simd")]
+#[test]
+fn no_simd_portable() {
+    assert!(!snap::frame::crc32_hardware_accelerated());
+    let data = &include_bytes!("../data/html")[..];
+    assert_eq!(data, &*depress(&press(data)));
+    assert_eq!(data, &*read_frame_depress(&write_frame_press(data)));
+}
+
+// Every CRC algorithm must produce the same checksums.
+// Feeding bytes to an incremental checksum in pieces gives the same result
+// as feeding them all at once, for every algorithm.
+#[test]
+fn crc32c_incremental() {
+    use snap::crc32::{crc32c_masked, Crc32c, CrcAlgo};
+
+    let data = &include_bytes!("../data/alice29.txt")[..];
+    for algo in [
+        CrcAlgo::Auto,
+        CrcAlgo::Slice16,
+        CrcAlgo::Bitwise,
+        CrcAlgo::Sse42,
+        CrcAlgo::Pclmulqdq,
+    ] {
+        let mut whole = Crc32c::with_algorithm(algo);
+        whole.update(data);
+        assert_eq!(crc32c_masked(data), whole.finalize_masked());
+
+        for size in [1, 7, 16, 1000, 65536] {
+            let mut pieces = Crc32c::with_algorithm(algo);
+            for piece in data.chunks(size) {
+                pieces.update(piece);
+            }
+            pieces.update(b"");
+            assert_eq!(whole.finalize(), pieces.finalize(), "{:?}", algo);
+        }
+    }
+
+    let crc = Crc32c::new();
+    assert_eq!(0, crc.finalize());
+    assert_eq!(crc32c_masked(b""), crc.finalize_masked());
+}
+
+// The one-shot checksums in the public crc32 module agree with the
+// incremental checksum and with the checksums in the frame format.
+#[test]
+fn crc32_module() {
+    use snap::crc32::{crc32c, crc32c_masked, Crc32c};
+
+    assert_eq!(0xE3069283, crc32c(b"123456789"));
+    assert_eq!(0, crc32c(b""));
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut crc = Crc32c::new();
+    crc.update(data);
+    assert_eq!(crc.finalize(), crc32c(data));
+    assert_eq!(crc.finalize_masked(), crc32c_masked(data));
+}
+
+#[test]
+fn crc_algorithms_agree() {
+    use snap::crc32::{Crc32c, CrcAlgo};
+
+    let masked_crc32c_with_algorithm = |algo, data: &[u8]| {
+        let mut crc = Crc32c::with_algorithm(algo);
+        crc.update(data);
+        crc.finalize_masked()
+    };
+
+    let corpora: &[&[u8]] = &[
+        b"",
+        b"a",
+        include_bytes!("../data/html"),
+        include_bytes!("../data/urls.10K"),
+        include_bytes!("../data/fireworks.jpeg"),
+        include_bytes!("../data/paper-100k.pdf"),
+        include_bytes!("../data/alice29.txt"),
+        include_bytes!("../data/geo.protodata"),
+        include_bytes!("../data/kppkn.gtb"),
+    ];
+    for data in corpora {
+        // Try a few unaligned suffixes to exercise the tail handling of the
+        // faster algorithms.
+        for start in 0..std::cmp::min(data.len(), 17) {
+            let data = &data[start..];
+            let expected =
+                masked_crc32c_with_algorithm(CrcAlgo::Bitwise, data);
+            for algo in [
+                CrcAlgo::Auto,
+                CrcAlgo::Slice16,
+                CrcAlgo::Sse42,
+                CrcAlgo::Pclmulqdq,
+            ] {
+                assert_eq!(
+                    expected,
+                    masked_crc32c_with_algorithm(algo, data),
+                    "{:?} on {} bytes",
+                    algo,
+                    data.len()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn detect_format() {
+    use snap::format::{detect_format, Format};
+
+    let data = &include_bytes!("../data/html")[..];
+    assert_eq!(Some(Format::Frame), detect_format(&write_frame_press(data)));
+    assert_eq!(Some(Format::Frame), detect_format(&read_frame_press(data)));
+    assert_eq!(Some(Format::Raw), detect_format(&press(data)));
+    assert_eq!(Some(Format::Raw), detect_format(&press(b"")));
+    assert_eq!(Some(Format::Raw), detect_format(&press(b"a")));
+
+    assert_eq!(None, detect_format(b""));
+    assert_eq!(None, detect_format(b"\xFF\xFF\xFF\xFF\xFF"));
+    assert_eq!(None, detect_format(b"\x00\x00"));
+    assert_eq!(None, detect_format(include_bytes!("../data/fireworks.jpeg")));
+    assert_eq!(None, detect_format(include_bytes!("../data/paper-100k.pdf")));
+    assert_eq!(None, detect_format(include_bytes!("../data/kppkn.gtb")));
+}
+
+// The top-level convenience functions should match the format specific APIs.
+#[test]
+fn compress_and_decompress_with_format() {
+    use snap::Format;
+
+    for input in [&b""[..], b"abc", include_bytes!("../data/html")] {
+        let comp = snap::compress(input, Format::Raw).unwrap();
+        assert_eq!(press(input), comp);
+        assert_eq!(input, &*snap::decompress(&comp, Format::Raw).unwrap());
+
+        let comp = snap::compress(input, Format::Frame).unwrap();
+        assert_eq!(write_frame_press(input), comp);
+        assert_eq!(input, &*snap::decompress(&comp, Format::Frame).unwrap());
+    }
+
+    let err = snap::decompress(b"\x05\x00a", Format::Raw).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(Error::HeaderMismatch { expected_len: 5, got_len: 1 }, err);
+    let err =
+        snap::decompress(b"\x00\x01\x00\x00", Format::Frame).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(Error::StreamHeader { byte: 0 }, err);
+}
+
+#[test]
+fn read_chain_decoder() {
+    use snap::format::Format;
+    use snap::read;
+    use std::io::Read;
+
+    let head = &include_bytes!("../data/html")[..];
+    let tail = &include_bytes!("../data/urls.10K")[..];
+    let head_press = write_frame_press(head);
+    let tail_press = press(tail);
+
+    let mut rdr = read::ChainDecoder::new(vec![
+        (Format::Frame, &head_press[..]),
+        (Format::Raw, &tail_press[..]),
+        (Format::Frame, &b""[..]),
+    ]);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+
+    let mut expected = head.to_vec();
+    expected.extend_from_slice(tail);
+    assert_eq!(expected, got);
+}
+
+// AutoDecoder decompresses compressed streams and passes anything else
+// through, including the bytes it looked at to tell the difference.
+#[test]
+fn read_auto_decoder() {
+    use snap::read;
+    use std::io::Read;
+
+    let text = &include_bytes!("../data/alice29.txt")[..];
+    let compressed = write_frame_press(text);
+    let ident = &compressed[..10];
+    let plains: &[&[u8]] =
+        &[text, b"", b"short", &ident[..9], &[ident, b"sNaPpY"].concat()[1..]];
+    for &plain in plains {
+        let mut rdr = read::AutoDecoder::new(plain);
+        assert!(!rdr.is_compressed().unwrap());
+        let mut got = vec![];
+        rdr.read_to_end(&mut got).unwrap();
+        assert_eq!(plain, &*got);
+
+        // Peeked bytes are returned even when read one at a time.
+        let mut rdr = read::AutoDecoder::new(plain);
+        let mut got = vec![];
+        let mut buf = [0];
+        while rdr.read(&mut buf).unwrap() > 0 {
+            got.push(buf[0]);
+        }
+        assert_eq!(plain, &*got);
+    }
+
+    let mut rdr = read::AutoDecoder::new(&compressed[..]);
+    assert!(rdr.is_compressed().unwrap());
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(text, &*got);
+
+    // Errors in a compressed stream are reported.
+    let mut rdr = read::AutoDecoder::new(&compressed[..20]);
+    rdr.read_to_end(&mut vec![]).unwrap_err();
+}
+
+#[test]
+fn read_frame_decoder_max_chunks() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    // A stream identifier followed by 100 chunks, each with a single byte.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for _ in 0..100 {
+        wtr.write_all(b"a").unwrap();
+        wtr.flush().unwrap();
+    }
+    let compressed = wtr.into_inner().unwrap();
+
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    rdr.set_max_chunks(101);
+    let mut buf = vec![];
+    rdr.read_to_end(&mut buf).unwrap();
+    assert_eq!(vec![b'a'; 100], buf);
+
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    rdr.set_max_chunks(50);
+    let mut buf = vec![];
+    let err = rdr.read_to_end(&mut buf).unwrap_err();
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("too many chunks"));
+    assert_eq!(vec![b'a'; 49], buf);
+}
+
+// Interrupted reads of the underlying reader should be retried rather than
+// reported to the caller.
+#[test]
+fn read_frame_decoder_interrupted() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    // A reader that fails with ErrorKind::Interrupted before every read, and
+    // otherwise returns at most 7 bytes at a time.
+    struct Interrupting<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl<'a> Read for Interrupting<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(self.data.len()).min(7);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    let data = &include_bytes!("../data/html")[..];
+    let compressed = write_frame_press(data);
+    let mut rdr = read::FrameDecoder::new(Interrupting {
+        data: &compressed,
+        interrupt: false,
+    });
+    // Don't use read_to_end, since it retries interrupted reads itself.
+    let mut got = vec![];
+    let mut buf = [0; 1000];
+    loop {
+        let n = rdr.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        got.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(data, &*got);
+}
+
+// A reader that fails with ErrorKind::WouldBlock should be resumable, even
+// when the error occurs in the middle of a chunk.
+#[test]
+fn read_frame_decoder_would_block() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    // A reader that fails with ErrorKind::WouldBlock before every read, and
+    // otherwise returns at most 5 bytes at a time.
+    struct Nonblocking<'a> {
+        data: &'a [u8],
+        block: bool,
+    }
+
+    impl<'a> Read for Nonblocking<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.block = !self.block;
+            if self.block {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(self.data.len()).min(5);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    // Include an uncompressed chunk too.
+    let jpeg = &include_bytes!("../data/fireworks.jpeg")[..1000];
+    let mut compressed = write_frame_press(data);
+    compressed.extend_from_slice(&write_frame_press(jpeg));
+    let mut rdr = read::FrameDecoder::new(Nonblocking {
+        data: &compressed,
+        block: false,
+    });
+    let mut got = vec![];
+    let mut buf = [0; 1000];
+    let mut blocked = 0;
+    loop {
+        match rdr.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => got.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => blocked += 1,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+    assert!(blocked > 0);
+    assert_eq!([data, jpeg].concat(), got);
+}
+
+// A reader that returns one byte at a time splits the stream identifier and
+// every chunk header across many reads, which must be assembled correctly.
+#[test]
+fn read_frame_decoder_one_byte_reads() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    struct OneByte<'a>(&'a [u8]);
+
+    impl<'a> Read for OneByte<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    let data = &include_bytes!("../data/html")[..];
+    // Include an uncompressed chunk and a second stream identifier too.
+    let jpeg = &include_bytes!("../data/fireworks.jpeg")[..1000];
+    let mut compressed = write_frame_press(data);
+    compressed.extend_from_slice(&write_frame_press(jpeg));
+    let mut rdr = read::FrameDecoder::new(OneByte(&compressed));
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!([data, jpeg].concat(), got);
+
+    // A stream identifier that is cut short is still an error.
+    let mut rdr = read::FrameDecoder::new(OneByte(&compressed[..7]));
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+}
+
+#[test]
+fn read_frame_decoder_on_skippable() {
+    use snap::read;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    // A stream with a skippable chunk in between two data chunks.
+    let mut compressed = write_frame_press(b"before ");
+    compressed.extend_from_slice(b"\x80\x08\x00\x00metadata");
+    compressed.extend_from_slice(&write_frame_press(b"after")[10..]);
+
+    let seen = Arc::new(Mutex::new(vec![]));
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    rdr.set_on_skippable({
+        let seen = Arc::clone(&seen);
+        move |ty, payload| seen.lock().unwrap().push((ty, payload.to_vec()))
+    });
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(b"before after", &*got);
+    assert_eq!(vec![(0x80, b"metadata".to_vec())], *seen.lock().unwrap());
+}
+
+// A compressed chunk whose block claims to be bigger than 64KB is reported
+// as an unsupported chunk length by every frame decoder.
+#[test]
+fn chunk_block_too_big() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    let mut compressed = b"\xFF\x06\x00\x00sNaPpY".to_vec();
+    // A compressed chunk with a checksum, followed by a block whose header
+    // claims 65537 decompressed bytes.
+    compressed.extend_from_slice(b"\x00\x08\x00\x00\x00\x00\x00\x00");
+    compressed.extend_from_slice(b"\x81\x80\x04\x00");
+    let want = Error::UnsupportedChunkLength { len: 65537, header: false };
+
+    let err = read::FrameDecoder::new(&compressed[..])
+        .read_to_end(&mut vec![])
+        .unwrap_err();
+    assert_eq!(want, unwrap_snap_err(err));
+    let err = read::decompress_slice(&compressed).unwrap_err();
+    assert_eq!(want, unwrap_snap_err(err));
+    let err =
+        write::FrameDecoder::new(vec![]).write_all(&compressed).unwrap_err();
+    assert_eq!(want, unwrap_snap_err(err));
+}
+
+// A chunk with only a checksum and no payload is valid, and decodes to
+// nothing, in every frame decoder.
+#[test]
+fn empty_chunk_payloads() {
+    use snap::crc32::crc32c_masked;
+    use snap::read;
+    use std::io::Read;
+
+    let decode_all = |compressed: &[u8]| -> Vec<Result<Vec<u8>, Error>> {
+        let mut got = vec![];
+        let from_read = read::FrameDecoder::new(compressed)
+            .read_to_end(&mut got)
+            .map(|_| got)
+            .map_err(unwrap_snap_err);
+        let from_slice =
+            read::decompress_slice(compressed).map_err(unwrap_snap_err);
+        let mut wtr = snap::write::FrameDecoder::new(vec![]);
+        let from_write = std::io::Write::write_all(&mut wtr, compressed)
+            .map(|_| wtr.into_inner())
+            .map_err(unwrap_snap_err);
+        vec![from_read, from_slice, from_write]
+    };
+    let empty_sum = crc32c_masked(b"").to_le_bytes();
+    let stream = |chunk_type: u8, body: &[u8]| {
+        let mut compressed = b"\xFF\x06\x00\x00sNaPpY".to_vec();
+        compressed.push(chunk_type);
+        compressed.extend_from_slice(&(body.len() as u32).to_le_bytes()[..3]);
+        compressed.extend_from_slice(body);
+        compressed.extend_from_slice(&write_frame_press(b"abc")[10..]);
+        compressed
+    };
+
+    // An uncompressed chunk of length 4, i.e., an empty payload.
+    let compressed = stream(0x01, &empty_sum);
+    for got in decode_all(&compressed) {
+        assert_eq!(Ok(b"abc".to_vec()), got);
+    }
+    // The checksum of the empty payload is still verified.
+    let compressed = stream(0x01, b"\x00\x00\x00\x00");
+    for got in decode_all(&compressed) {
+        assert_eq!(
+            Err(Error::Checksum {
+                expected: 0,
+                got: u32::from_le_bytes(empty_sum)
+            }),
+            got
+        );
+    }
+    // A compressed chunk whose block decompresses to nothing.
+    let mut body = empty_sum.to_vec();
+    body.push(0);
+    let compressed = stream(0x00, &body);
+    for got in decode_all(&compressed) {
+        assert_eq!(Ok(b"abc".to_vec()), got);
+    }
+    // A compressed chunk of length 4 has no block at all, not even the
+    // header of an empty one, so it's invalid.
+    let compressed = stream(0x00, &empty_sum);
+    for got in decode_all(&compressed) {
+        assert_eq!(Err(Error::Empty), got);
+    }
+}
+
+// After an error, resyncing skips to the next stream in a concatenation.
+#[test]
+fn read_frame_decoder_resync_to_next_stream() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b"first part, ").unwrap();
+    wtr.flush_block().unwrap();
+    wtr.write_all(b"corrupt part").unwrap();
+    let mut compressed = wtr.into_inner().unwrap();
+    // Corrupt the checksum of the second chunk, which follows the stream
+    // identifier and the first chunk (8 + 12 bytes).
+    compressed[10 + 20 + 4] ^= 0xFF;
+    compressed.extend_from_slice(&write_frame_press(b"second stream"));
+
+    let mut rdr = read::FrameDecoder::buffered(&compressed[..]);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap_err();
+    assert_eq!(b"first part, ", &*got);
+
+    assert!(rdr.resync_to_next_stream().unwrap());
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(b"second stream", &*got);
+    assert!(!rdr.resync_to_next_stream().unwrap());
+
+    // Resyncing in the middle of a stream skips the rest of it, even if
+    // partial matches of the stream identifier come first.
+    let mut compressed = write_frame_press(b"skipped");
+    compressed.extend_from_slice(b"\xFF\x06\xFF\x06\x00\x00sNaP");
+    compressed.extend_from_slice(&write_frame_press(b"kept"));
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut buf = [0; 3];
+    rdr.read_exact(&mut buf).unwrap();
+    assert!(rdr.resync_to_next_stream().unwrap());
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(b"kept", &*got);
+}
+
+// A checksum mismatch can be let through by a callback, after which the
+// rest of the stream still decodes.
+#[test]
+fn read_frame_decoder_on_checksum_error() {
+    use snap::frame::ChunkType;
+    use snap::{read, write};
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for part in [&[b'a'; 100][..], &[b'b'; 100][..], b"c"] {
+        wtr.write_all(part).unwrap();
+        wtr.flush_block().unwrap();
+    }
+    let mut compressed = wtr.into_inner().unwrap();
+    // Corrupt the checksum of the second chunk.
+    let second = 10 + 4 + compressed[11] as usize;
+    assert_eq!(0x00, compressed[second]);
+    compressed[second + 4] ^= 0xFF;
+
+    let mut got = vec![];
+    let err = read::FrameDecoder::new(&compressed[..])
+        .read_to_end(&mut got)
+        .unwrap_err();
+    let err = unwrap_snap_err(err);
+    let (expected, computed) = match err {
+        Error::Checksum { expected, got } => (expected, got),
+        ref err => panic!("expected checksum error, but got {:?}", err),
+    };
+
+    let seen = Arc::new(Mutex::new(vec![]));
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    rdr.set_on_checksum_error({
+        let seen = Arc::clone(&seen);
+        move |ty, expected, got| {
+            seen.lock().unwrap().push((ty, expected, got));
+            true
+        }
+    });
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    let mut want = vec![b'a'; 100];
+    want.extend_from_slice(&[b'b'; 100]);
+    want.push(b'c');
+    assert_eq!(want, got);
+    assert_eq!(
+        vec![(ChunkType::Compressed, expected, computed)],
+        *seen.lock().unwrap()
+    );
+
+    // Refusing to continue is the same as having no callback.
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    rdr.set_on_checksum_error(|_, _, _| false);
+    let mut got = vec![];
+    let err = rdr.read_to_end(&mut got).unwrap_err();
+    assert_eq!(vec![b'a'; 100], got);
+    let err = unwrap_snap_err(err);
+    assert_eq!(Error::Checksum { expected, got: computed }, err);
+}
+
+// repair drops corrupt chunks and recovers the rest of the stream.
+#[test]
+fn read_repair() {
+    use snap::{read, write};
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    let mut starts = vec![];
+    for part in
+        [&b"aaaaaaaaaaaaaaaaaaaa"[..], b"bbbbbbbbbbbbbbbbbbbb", b"cccc"]
+    {
+        starts.push(std::cmp::max(wtr.get_ref().len(), 10));
+        wtr.write_block(part).unwrap();
+    }
+    let compressed = wtr.into_inner().unwrap();
+
+    let mut got = vec![];
+    let stats = read::repair(&compressed[..], &mut got).unwrap();
+    assert_eq!(b"aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbcccc", &*got);
+    assert_eq!((3, 0), (stats.recovered_chunks(), stats.dropped_chunks()));
+
+    // Corrupt the compressed block of the middle chunk.
+    let mut corrupt = compressed.clone();
+    corrupt[starts[1] + 8] = 0xFF;
+    let mut got = vec![];
+    let stats = read::repair(&corrupt[..], &mut got).unwrap();
+    assert_eq!(b"aaaaaaaaaaaaaaaaaaaacccc", &*got);
+    assert_eq!((2, 1), (stats.recovered_chunks(), stats.dropped_chunks()));
+
+    // An invalid chunk header loses everything up to the next stream.
+    let mut corrupt = compressed.clone();
+    corrupt[starts[1]] = 0x02;
+    corrupt.extend_from_slice(&write_frame_press(b"next stream"));
+    let mut got = vec![];
+    let stats = read::repair(&corrupt[..], &mut got).unwrap();
+    assert_eq!(b"aaaaaaaaaaaaaaaaaaaanext stream", &*got);
+    assert_eq!((2, 1), (stats.recovered_chunks(), stats.dropped_chunks()));
+
+    // A truncated chunk at the end is dropped.
+    let mut got = vec![];
+    let stats =
+        read::repair(&compressed[..compressed.len() - 1], &mut got).unwrap();
+    assert_eq!(b"aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbb", &*got);
+    assert_eq!((2, 1), (stats.recovered_chunks(), stats.dropped_chunks()));
+}
+
+// The estimated length of a file backed stream matches what's decoded, and
+// estimating it doesn't disturb decoding.
+#[test]
+fn read_frame_decoder_estimated_len() {
+    use snap::{read, write};
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+
+    let data = include_bytes!("../data/html_x_4");
+    let path = std::env::temp_dir()
+        .join(format!("snap-estimated-len-{}.sz", std::process::id()));
+    let mut wtr = write::FrameEncoder::new(File::create(&path).unwrap());
+    wtr.write_all(&data[..1000]).unwrap();
+    wtr.flush().unwrap();
+    wtr.write_skippable_chunk(0x80, b"metadata").unwrap();
+    wtr.write_all(&data[1000..]).unwrap();
+    wtr.flush().unwrap();
+    drop(wtr);
+
+    let mut rdr = read::FrameDecoder::new(File::open(&path).unwrap());
+    assert_eq!(data.len() as u64, rdr.estimated_len().unwrap());
+    let mut buf = vec![0; 1500];
+    rdr.read_exact(&mut buf).unwrap();
+    assert_eq!(data.len() as u64 - 1500, rdr.estimated_len().unwrap());
+    let mut got = Vec::with_capacity(rdr.estimated_len().unwrap() as usize);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(&data[1500..], &*got);
+    assert_eq!(0, rdr.estimated_len().unwrap());
+    fs::remove_file(&path).unwrap();
+
+    // Invalid chunk headers are reported.
+    let mut rdr = read::FrameDecoder::new(std::io::Cursor::new(b"\x00\x00"));
+    rdr.estimated_len().unwrap_err();
+    let mut rdr =
+        read::FrameDecoder::new(std::io::Cursor::new(b"\x00\x00\x00\x00"));
+    let err = rdr.estimated_len().unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(Error::StreamHeader { byte: 0 }, err);
+}
+
+// A decoder with a tee writes exactly the compressed bytes it reads to the
+// tee, and reports errors from writing to it.
+#[test]
+fn read_frame_decoder_with_tee() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let compressed = write_frame_press(data);
+    let mut rdr = read::FrameDecoder::with_tee(&compressed[..], vec![]);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+    let (rest, teed) = rdr.into_inner().into_inner();
+    assert!(rest.is_empty());
+    assert_eq!(compressed, teed);
+
+    // A tee that's too small to hold everything fails with WriteZero.
+    let mut buf = [0; 100];
+    let mut rdr = read::FrameDecoder::with_tee(&compressed[..], &mut buf[..]);
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(io::ErrorKind::WriteZero, err.kind());
+    assert_eq!(&compressed[..100], &buf[..]);
+}
+
+// Beginning a new stream requires the next chunk to be a stream identifier,
+// while still reading from the same underlying reader.
+#[test]
+fn read_frame_decoder_begin_new_stream() {
+    use snap::read;
+    use std::io::Read;
+
+    let first = &include_bytes!("../data/html")[..];
+    let second = &include_bytes!("../data/alice29.txt")[..];
+    let mut compressed = write_frame_press(first);
+    let first_len = compressed.len();
+    compressed.extend_from_slice(&write_frame_press(second));
+
+    // Reading all of the first stream consumes exactly its chunks.
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![0; first.len()];
+    rdr.read_exact(&mut got).unwrap();
+    assert_eq!(first, &*got);
+    assert_eq!(compressed.len() - first_len, rdr.get_ref().len());
+
+    rdr.begin_new_stream();
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(second, &*got);
+
+    // Without a stream identifier, the new stream is rejected.
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![0; first.len()];
+    rdr.read_exact(&mut got).unwrap();
+    *rdr.get_mut() = &compressed[first_len + 10..];
+    rdr.begin_new_stream();
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert!(matches!(err, Error::StreamHeader { .. }));
+}
+
+// Reading a prefix stops at the chunk that provides its last byte, so later
+// chunks are never read, even if they're corrupt.
+#[test]
+fn read_frame_decoder_read_prefix() {
+    use snap::{read, write};
+    use std::io::Read;
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_block(b"first chunk, ").unwrap();
+    wtr.write_block(b"second chunk, ").unwrap();
+    let good = wtr.get_ref().len();
+    wtr.write_block(b"corrupt chunk").unwrap();
+    let mut compressed = wtr.into_inner().unwrap();
+    compressed[good + 4] ^= 0xFF;
+
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    assert_eq!(b"first chun", &*rdr.read_prefix(10).unwrap());
+    assert_eq!(compressed.len() - 10 - 8 - 13, rdr.get_ref().len());
+    assert_eq!(b"k, second ", &*rdr.read_prefix(10).unwrap());
+    assert_eq!(compressed.len() - good, rdr.get_ref().len());
+    let mut buf = [0; 7];
+    rdr.read_exact(&mut buf).unwrap();
+    assert_eq!(b"chunk, ", &buf);
+    rdr.read_prefix(10).unwrap_err();
+
+    // A prefix longer than the stream is the whole stream.
+    let compressed = write_frame_press(b"short");
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    assert_eq!(b"short", &*rdr.read_prefix(100).unwrap());
+    assert!(rdr.read_prefix(100).unwrap().is_empty());
+    assert!(rdr.read_prefix(0).unwrap().is_empty());
+}
+
+// Decoding can be checkpointed at a chunk boundary and resumed from there
+// with a fresh decoder.
+#[test]
+fn read_frame_decoder_compressed_position() {
+    use snap::read;
+    use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let compressed = write_frame_press(data);
+
+    let mut rdr = read::FrameDecoder::new(Cursor::new(&compressed));
+    assert_eq!(0, rdr.compressed_position());
+    let mut got = vec![];
+    while got.len() < data.len() / 2 {
+        let buf = rdr.fill_buf().unwrap();
+        got.extend_from_slice(buf);
+        let n = buf.len();
+        rdr.consume(n);
+    }
+    let pos = rdr.compressed_position();
+    assert!(pos > 10 && pos < compressed.len() as u64);
+    // Reading any byte of the next chunk decodes all of it, which moves the
+    // position past it.
+    let mut byte = [0];
+    rdr.read_exact(&mut byte).unwrap();
+    assert!(rdr.compressed_position() > pos);
+    drop(rdr);
+
+    let mut src = Cursor::new(&compressed);
+    src.seek(SeekFrom::Start(pos)).unwrap();
+    let mut rdr = read::FrameDecoder::resume(src, pos);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+    assert_eq!(compressed.len() as u64, rdr.compressed_position());
+
+    let mut rdr = read::FrameDecoder::resume(&compressed[..], 0);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+}
+
+// Fixed size buffers are filled completely, except at the end of the stream,
+// and leftover bytes carry over to the next call.
+#[test]
+fn read_frame_decoder_read_into_fixed() {
+    use snap::read;
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let compressed = write_frame_press(data);
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![];
+    let mut size = 1;
+    loop {
+        let mut out = vec![0; size];
+        let n = rdr.read_into_fixed(&mut out).unwrap();
+        got.extend_from_slice(&out[..n]);
+        if n < size {
+            break;
+        }
+        size *= 3;
+    }
+    assert_eq!(data, &*got);
+    assert!(size > 65536);
+    assert_eq!(0, rdr.read_into_fixed(&mut [0; 10]).unwrap());
+    assert_eq!(0, rdr.read_into_fixed(&mut []).unwrap());
+}
+
+// Verifying the checksum of a whole stream catches corruption that the
+// per-chunk checksums can't, e.g., a chunk that was replaced wholesale.
+#[test]
+fn read_frame_decoder_decompress_verify() {
+    use snap::{crc32, read, write};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let crc = crc32::crc32c(data);
+    let compressed = write_frame_press(data);
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    assert_eq!(data, &*rdr.decompress_verify(crc).unwrap());
+    // Nothing is left, which is the checksum of no bytes.
+    assert!(rdr.decompress_verify(0).unwrap().is_empty());
+
+    // Swap the first block for a different one. Every chunk is still valid.
+    let mut corrupt = data.to_vec();
+    corrupt[0] ^= 0xFF;
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for block in corrupt.chunks(65536) {
+        wtr.write_block(block).unwrap();
+    }
+    let compressed = wtr.into_inner().unwrap();
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let err = rdr.decompress_verify(crc).unwrap_err();
+    let err = unwrap_snap_err(err);
+    let want = Error::Checksum { expected: crc, got: crc32::crc32c(&corrupt) };
+    assert_eq!(want, err);
+}
+
+// A limited decoder succeeds on streams up to the limit, but fails instead of
+// stopping early on streams that exceed it.
+#[test]
+fn read_frame_decoder_take_limited() {
+    use snap::read;
+    use std::io::Read;
+
+    let data = &include_bytes!("../data/alice29.txt")[..];
+    let compressed = write_frame_press(data);
+    let len = data.len() as u64;
+
+    for max in [len, len + 1] {
+        let rdr = read::FrameDecoder::new(&compressed[..]);
+        let mut lim = rdr.take_limited(max);
+        let mut got = vec![];
+        lim.read_to_end(&mut got).unwrap();
+        assert_eq!(data, &*got);
+        assert_eq!(max - len, lim.remaining());
+    }
+
+    let rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut lim = rdr.take_limited(len - 1);
+    let mut got = vec![];
+    let err = lim.read_to_end(&mut got).unwrap_err();
+    assert_eq!(&data[..data.len() - 1], &*got);
+    assert_eq!(0, lim.remaining());
+    assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("too many bytes"));
+
+    let compressed = write_frame_press(b"");
+    let rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![];
+    rdr.take_limited(0).read_to_end(&mut got).unwrap();
+    assert!(got.is_empty());
+}
+
+// A decoder with small buffers decodes streams of small blocks, and rejects
+// chunks that don't fit instead of panicking.
+#[test]
+fn read_frame_decoder_with_capacity() {
+    use snap::{read, write};
+    use std::io::Read;
+
+    let data = include_bytes!("../data/html");
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for block in data.chunks(4096) {
+        wtr.write_block(block).unwrap();
+    }
+    let compressed = wtr.into_inner().unwrap();
+    let mut rdr = read::FrameDecoder::with_capacity(&compressed[..], 4096);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(&data[..], &*got);
+
+    // A compressed chunk whose decompressed bytes are too big.
+    let compressed = write_frame_press(&[0; 65536]);
+    let mut rdr = read::FrameDecoder::with_capacity(&compressed[..], 4096);
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(
+        Error::UnsupportedChunkLength { len: 65536, header: false },
+        err
+    );
+
+    // An uncompressed chunk that doesn't fit in the compressed buffer.
+    let data = include_bytes!("../data/fireworks.jpeg");
+    let compressed = write_frame_press(&data[..65536]);
+    let mut rdr = read::FrameDecoder::with_capacity(&compressed[..], 4096);
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(
+        Error::UnsupportedChunkLength { len: 65540, header: false },
+        err
+    );
+
+    // Capacities bigger than the maximum block size are capped.
+    let data = include_bytes!("../data/html");
+    let compressed = write_frame_press(data);
+    let mut rdr = read::FrameDecoder::with_capacity(&compressed[..], 1 << 20);
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(&data[..], &*got);
+}
+
+// FrameDecoder's BufRead impl should work across chunk boundaries.
+#[test]
+fn read_frame_decoder_buf_read() {
+    use snap::{read, write};
+    use std::io::{BufRead, Write};
+
+    let data = &include_bytes!("../data/alice29.txt")[..];
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    for piece in data.chunks(1000) {
+        wtr.write_all(piece).unwrap();
+        wtr.flush_block().unwrap();
+    }
+    let compressed = wtr.into_inner().unwrap();
+
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![];
+    let mut lines = 0;
+    while rdr.read_until(b'\n', &mut got).unwrap() > 0 {
+        lines += 1;
+    }
+    assert_eq!(data, &*got);
+    assert_eq!(data.split_inclusive(|&b| b == b'\n').count(), lines);
+
+    let rdr = read::FrameDecoder::new(&compressed[..]);
+    let got: Vec<String> = rdr.lines().map(|r| r.unwrap()).collect();
+    let want: Vec<&str> = std::str::from_utf8(data).unwrap().lines().collect();
+    assert_eq!(want, got);
+}
+
+#[test]
+fn read_frame_decoder_peek_chunk_decompressed_len() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(&data[..100_000]).unwrap();
+    wtr.flush_block().unwrap();
+    wtr.write_all(&data[100_000..100_010]).unwrap();
+    let compressed = wtr.into_inner().unwrap();
+
+    let mut rdr = read::FrameDecoder::new(&compressed[..]);
+    let mut got = vec![];
+    for expected in [65536, 100_000 - 65536, 10] {
+        assert_eq!(Some(expected), rdr.peek_chunk_decompressed_len().unwrap());
+        // Peeking twice doesn't advance the decoder.
+        assert_eq!(Some(expected), rdr.peek_chunk_decompressed_len().unwrap());
+
+        let mut buf = vec![0; expected];
+        rdr.read_exact(&mut buf[..5]).unwrap();
+        assert_eq!(
+            Some(expected - 5),
+            rdr.peek_chunk_decompressed_len().unwrap()
+        );
+        rdr.read_exact(&mut buf[5..]).unwrap();
+        got.extend_from_slice(&buf);
+    }
+    assert_eq!(None, rdr.peek_chunk_decompressed_len().unwrap());
+    assert_eq!(0, rdr.read(&mut [0; 1]).unwrap());
+    assert_eq!(&data[..100_010], &*got);
+
+    // Peeking only needs the headers at the start of a chunk, not its body.
+    let mut rdr = read::FrameDecoder::new(&compressed[..30]);
+    assert_eq!(Some(65536), rdr.peek_chunk_decompressed_len().unwrap());
+    rdr.read(&mut [0; 1]).unwrap_err();
+}
+
+#[test]
+fn qc_validate_matches_decompress() {
+    fn p(bytes: Vec<u8>) -> TestResult {
+        let n = match decompress_len(&bytes) {
+            Ok(n) if n <= 1 << 20 => n,
+            _ => return TestResult::discard(),
+        };
+        let mut buf = vec![0; n];
+        let expected = Decoder::new().decompress(&bytes, &mut buf);
+        TestResult::from_bool(expected == Decoder::new().validate(&bytes))
+    }
+    QuickCheck::new()
+        .gen(StdGen::new(rand::thread_rng(), 10_000))
+        .tests(1_000)
+        .quickcheck(p as fn(_) -> _);
+}
+
+#[test]
+fn read_frame_decoder_truncated_chunk() {
+    use snap::read;
+    use std::io::Read;
+
+    fn read_err(bytes: &[u8]) -> (std::io::ErrorKind, String) {
+        let err = read::FrameDecoder::new(bytes)
+            .read_to_end(&mut vec![])
+            .unwrap_err();
+        (err.kind(), err.to_string())
+    }
+
+    fn truncated(len: usize, got: usize) -> (std::io::ErrorKind, String) {
+        let msg = format!(
+            "snappy: corrupt input (chunk truncated; \
+             expected {} bytes but got {})",
+            len, got
+        );
+        (std::io::ErrorKind::UnexpectedEof, msg)
+    }
+
+    // An uncompressed chunk declaring 10 bytes (4 for the checksum and 6 of
+    // data), truncated in its data and in its checksum.
+    let compressed = write_frame_press(b"sNaPpY");
+    assert_eq!(b'\x01', compressed[10]);
+    assert_eq!(
+        truncated(10, 7),
+        read_err(&compressed[..compressed.len() - 3])
+    );
+    assert_eq!(truncated(10, 2), read_err(&compressed[..16]));
+
+    // A compressed chunk truncated in its data.
+    let compressed = write_frame_press(&[b'a'; 1000]);
+    assert_eq!(b'\x00', compressed[10]);
+    let len = compressed.len() - 14;
+    assert_eq!(
+        truncated(len, len - 1),
+        read_err(&compressed[..compressed.len() - 1])
+    );
+}
+
+#[test]
+fn test_short_input() {
+    // Regression test for https://github.com/BurntSushi/rust-snappy/issues/42
+    use snap::read;
+    use std::io::Read;
+
+    let err =
+        read::FrameDecoder::new(&b"123"[..]).read_to_end(&mut Vec::new());
+    assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+#[cfg(feature = "cpp")]
+fn qc_cpp_decompresses_rust() {
+    fn p(bytes: Vec<u8>) -> bool {
+        let comp_rust = press(&bytes);
+        let decomp_cpp = depress_cpp(&comp_rust);
+        bytes == decomp_cpp
+    }
+    QuickCheck::new()
+        .gen(StdGen::new(rand::thread_rng(), 10_000))
+        .tests(10_000)
+        .quickcheck(p as fn(_) -> _);
+}
+
+#[test]
+#[cfg(feature = "cpp")]
+fn qc_rust_decompresses_cpp() {
+    fn p(bytes: Vec<u8>) -> bool {
+        let comp_cpp = press_cpp(&bytes);
+        let decomp_rust = depress(&comp_cpp);
+        bytes == decomp_rust
+    }
+    QuickCheck::new()
+        .gen(StdGen::new(rand::thread_rng(), 10_000))
+        .tests(10_000)
+        .quickcheck(p as fn(_) -> _);
+}
+
+// Regression tests.
+
+// See: https://github.com/BurntSushi/rust-snappy/issues/3
+#[cfg(target_pointer_width = "32")]
+testerrored!(
+    err_lit_len_overflow1,
     &b"\x11\x00\x00\xfc\xfe\xff\xff\xff"[..],
     Error::Literal { len: std::u32::MAX as u64, src_len: 0, dst_len: 16 }
 );
@@ -588,6 +3053,40 @@ testerrored!(
     Error::Literal { len: std::u32::MAX as u64 + 1, src_len: 0, dst_len: 16 }
 );
 
+// The same literals on 64-bit targets, where `s + len` and `d + len` can't
+// wrap either. They must be reported as clean errors.
+#[cfg(target_pointer_width = "64")]
+testerrored!(
+    err_lit_len_overflow1_64,
+    &b"\x11\x00\x00\xfc\xfe\xff\xff\xff"[..],
+    Error::Literal { len: u32::MAX as u64, src_len: 0, dst_len: 16 }
+);
+#[cfg(target_pointer_width = "64")]
+testerrored!(
+    err_lit_len_overflow2_64,
+    &b"\x11\x00\x00\xfc\xff\xff\xff\xff"[..],
+    Error::Literal { len: u32::MAX as u64 + 1, src_len: 0, dst_len: 16 }
+);
+#[cfg(target_pointer_width = "64")]
+testerrored!(
+    err_lit_len_overflow3_64,
+    &b"\x11\x00\x00\xfc\xfe\xff\xff\xffabc"[..],
+    Error::Literal { len: u32::MAX as u64, src_len: 3, dst_len: 16 }
+);
+
+// A literal claiming u32::MAX bytes is rejected the same way when
+// decompressing to a writer.
+#[test]
+fn err_lit_len_overflow_to_writer() {
+    let input = &b"\x11\x00\x00\xfc\xfe\xff\xff\xffabc"[..];
+    let err = Decoder::new().decompress_to_writer(input, vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert_eq!(
+        Error::Literal { len: u32::MAX as u64, src_len: 3, dst_len: 16 },
+        err
+    );
+}
+
 // Helper functions.
 
 fn press(bytes: &[u8]) -> Vec<u8> {
@@ -598,6 +3097,37 @@ fn depress(bytes: &[u8]) -> Vec<u8> {
     Decoder::new().decompress_vec(bytes).unwrap()
 }
 
+// Returns the Snappy error wrapped by an I/O error.
+fn unwrap_snap_err(err: std::io::Error) -> Error {
+    *err.into_inner().unwrap().downcast::<Error>().unwrap()
+}
+
+// Decompresses bytes produced by `Encoder::compress_with_dict` using only a
+// standard decoder. Copies into the dictionary behave as if the decompressed
+// bytes were preceded by the dictionary, so we splice a literal containing the
+// dictionary in front of the compressed operations and fix up the header.
+fn depress_with_dict_spliced(dict: &[u8], bytes: &[u8]) -> Vec<u8> {
+    fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+        while n >= 0x80 {
+            out.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+
+    let n = decompress_len(bytes).unwrap();
+    let header_len = bytes.iter().position(|&b| b < 0x80).unwrap() + 1;
+    assert!(!dict.is_empty() && dict.len() <= 1 << 16);
+
+    let mut spliced = vec![];
+    write_varint(&mut spliced, (dict.len() + n) as u64);
+    spliced.push(61 << 2);
+    spliced.extend_from_slice(&((dict.len() - 1) as u16).to_le_bytes());
+    spliced.extend_from_slice(dict);
+    spliced.extend_from_slice(&bytes[header_len..]);
+    depress(&spliced)[dict.len()..].to_vec()
+}
+
 fn write_frame_press(bytes: &[u8]) -> Vec<u8> {
     use snap::write;
     use std::io::Write;
@@ -616,6 +3146,19 @@ fn read_frame_depress(bytes: &[u8]) -> Vec<u8> {
     buf
 }
 
+fn write_frame_depress(bytes: &[u8]) -> Vec<u8> {
+    use snap::write;
+    use std::io::Write;
+
+    // Write in small odd sized pieces so that chunks are split across writes.
+    let mut wtr = write::FrameDecoder::new(vec![]);
+    for piece in bytes.chunks(1001) {
+        wtr.write_all(piece).unwrap();
+    }
+    assert!(bytes.is_empty() || wtr.is_stream_complete());
+    wtr.into_inner()
+}
+
 fn read_frame_press(bytes: &[u8]) -> Vec<u8> {
     use snap::read;
     use std::io::Read;
@@ -642,3 +3185,622 @@ fn depress_cpp(bytes: &[u8]) -> Vec<u8> {
     buf.truncate(n);
     buf
 }
+
+// Raw Snappy data fed to a frame decoder is reported with an error that
+// points at the raw decoder.
+#[test]
+fn frame_decoder_raw_input() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    let data = include_bytes!("../data/html");
+    for input in [&b"hello"[..], b"abcd", &data[..]] {
+        let comp = press(input);
+
+        let mut got = vec![];
+        let mut rdr = read::FrameDecoder::new(&*comp);
+        let err = rdr.read_to_end(&mut got).unwrap_err();
+        let err = unwrap_snap_err(err);
+        assert_eq!(Error::StreamHeader { byte: comp[0] }, err);
+        assert!(err.to_string().contains("use raw::Decoder"));
+
+        let mut wtr = write::FrameDecoder::new(vec![]);
+        let err = wtr.write_all(&comp).unwrap_err();
+        let err = unwrap_snap_err(err);
+        assert_eq!(Error::StreamHeader { byte: comp[0] }, err);
+    }
+}
+
+// Restoring a checkpoint and rewinding the underlying writer to match it
+// produces the same stream as if the bytes written since were never written.
+#[test]
+fn write_frame_encoder_checkpoint() {
+    use snap::write;
+    use std::io::Write;
+
+    let data = include_bytes!("../data/html");
+    let (a, b, c) = (&data[..1000], &data[1000..], &data[..5000]);
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(a).unwrap();
+    let cp = wtr.checkpoint();
+    assert_eq!(a, cp.buffered());
+    assert_eq!(0, cp.written());
+    // Enough to force chunks out to the underlying writer.
+    wtr.write_all(b).unwrap();
+    assert!(!wtr.get_ref().is_empty());
+
+    wtr.restore(cp);
+    wtr.get_mut().truncate(0);
+    wtr.write_all(c).unwrap();
+    let got = wtr.into_inner().unwrap();
+
+    let mut want = write::FrameEncoder::new(vec![]);
+    want.write_all(a).unwrap();
+    want.write_all(c).unwrap();
+    let want = want.into_inner().unwrap();
+    assert_eq!(want, got);
+    assert_eq!([a, c].concat(), read_frame_depress(&got));
+
+    // A checkpoint taken after chunks were written records their length, and
+    // restoring it keeps the stream identifier from being written twice.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.write_all(b).unwrap();
+    let cp = wtr.checkpoint();
+    let written = wtr.get_ref().len();
+    assert_eq!(written as u64, cp.written());
+    wtr.write_all(c).unwrap();
+    wtr.flush().unwrap();
+    wtr.restore(cp);
+    wtr.get_mut().truncate(written);
+    wtr.write_all(a).unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!([b, a].concat(), read_frame_depress(&got));
+}
+
+// Concatenated raw blocks decompress to the concatenation of their inputs,
+// and a block cut short is an error.
+#[test]
+fn read_raw_concat_decoder() {
+    use snap::read;
+    use std::io::Read;
+
+    let data = include_bytes!("../data/html");
+    let inputs = [&data[..], b"", b"hello hello hello", &data[..5000]];
+    let mut comp = vec![];
+    for input in &inputs {
+        comp.extend_from_slice(&Encoder::new().compress_vec(input).unwrap());
+    }
+
+    let mut got = vec![];
+    let mut rdr = read::RawConcatDecoder::new(&*comp);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(inputs.concat(), got);
+
+    // Reading one byte at a time exercises blocks straddling reads of the
+    // underlying reader.
+    let mut got = vec![];
+    let mut rdr = read::RawConcatDecoder::new(
+        std::io::BufReader::with_capacity(1, &*comp),
+    );
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(inputs.concat(), got);
+
+    let mut got = vec![];
+    let mut rdr = read::RawConcatDecoder::new(&comp[..comp.len() - 1]);
+    rdr.read_to_end(&mut got).unwrap_err();
+
+    // A block longer than any conforming encoder produces, made of one byte
+    // literals with 4 byte lengths, followed by another block.
+    let mut comp = vec![20];
+    for &b in b"abcdefghijklmnopqrst" {
+        comp.extend_from_slice(&[63 << 2, 0, 0, 0, 0, b]);
+    }
+    comp.extend_from_slice(&press(b"hello"));
+    let mut got = vec![];
+    let mut rdr = read::RawConcatDecoder::new(&*comp);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(&b"abcdefghijklmnopqrsthello"[..], &got[..]);
+
+    let mut got = vec![];
+    let mut rdr = read::RawConcatDecoder::new(&b""[..]);
+    assert_eq!(0, rdr.read_to_end(&mut got).unwrap());
+}
+
+// A header claiming a huge block is an error once the bytes run out, without
+// allocating room for the whole block first.
+#[test]
+fn read_raw_concat_decoder_huge_header() {
+    use snap::read;
+    use std::io::{self, Read};
+
+    // Records the size of the biggest buffer it's asked to fill, which is
+    // how much room the decoder made for compressed bytes.
+    struct MaxRead<'a> {
+        rdr: &'a [u8],
+        max: usize,
+    }
+
+    impl<'a> Read for MaxRead<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.max = std::cmp::max(self.max, buf.len());
+            self.rdr.read(buf)
+        }
+    }
+
+    // A header claiming u32::MAX bytes, followed by a literal.
+    let comp = b"\xFF\xFF\xFF\xFF\x0F\x10hello";
+    let mut rdr = read::RawConcatDecoder::new(MaxRead { rdr: comp, max: 0 });
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(
+        Error::HeaderMismatch { expected_len: u32::MAX as u64, got_len: 5 },
+        unwrap_snap_err(err),
+    );
+    assert!(rdr.get_ref().max <= 1 << 17, "{}", rdr.get_ref().max);
+}
+
+#[test]
+fn decompress_prefix() {
+    let mut comp = press(b"hello hello hello");
+    let len = comp.len();
+    comp.extend_from_slice(b"trailing");
+    let mut buf = vec![0; 100];
+    let (consumed, n) =
+        Decoder::new().decompress_prefix(&comp, &mut buf).unwrap();
+    assert_eq!((len, 17), (consumed, n));
+    assert_eq!(b"hello hello hello", &buf[..n]);
+
+    // The block must still be complete.
+    Decoder::new().decompress_prefix(&comp[..len - 1], &mut buf).unwrap_err();
+}
+
+// With a minimum chunk total, every data chunk is followed by padding that
+// brings it up to the minimum, and the stream still decompresses.
+#[test]
+fn write_frame_encoder_min_chunk_total() {
+    use snap::write;
+    use std::io::Write;
+
+    // Returns the type and total length of every chunk after the stream
+    // identifier.
+    fn chunks(mut stream: &[u8]) -> Vec<(u8, usize)> {
+        let mut chunks = vec![];
+        stream = &stream[10..];
+        while !stream.is_empty() {
+            let len = stream[1] as usize
+                | (stream[2] as usize) << 8
+                | (stream[3] as usize) << 16;
+            chunks.push((stream[0], 4 + len));
+            stream = &stream[4 + len..];
+        }
+        chunks
+    }
+
+    let data = include_bytes!("../data/html");
+    let records: &[&[u8]] = &[b"a", b"hello hello hello", &data[..2000]];
+    for &min in &[100, 1000, 60_000] {
+        let mut wtr = write::FrameEncoder::new(vec![]);
+        wtr.set_min_chunk_total(min);
+        for record in records {
+            wtr.write_all(record).unwrap();
+            wtr.flush_block().unwrap();
+        }
+        let got = wtr.into_inner().unwrap();
+        assert_eq!(records.concat(), read_frame_depress(&got));
+
+        let chunks = chunks(&got);
+        let mut i = 0;
+        let mut data_chunks = 0;
+        while i < chunks.len() {
+            let (ty, len) = chunks[i];
+            assert!(ty == 0x00 || ty == 0x01);
+            let mut total = len;
+            i += 1;
+            while i < chunks.len() && chunks[i].0 == 0xFE {
+                total += chunks[i].1;
+                i += 1;
+            }
+            if len >= min {
+                assert_eq!(len, total);
+            } else {
+                assert!(total >= min && total < min + 4, "{} {}", min, total);
+            }
+            data_chunks += 1;
+        }
+        assert_eq!(records.len(), data_chunks);
+    }
+
+    // Chunks already at the minimum get no padding.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_min_chunk_total(10);
+    wtr.write_all(&data[..2000]).unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(write_frame_press(&data[..2000]), got);
+
+    // Huge minimums are capped at the size of the largest data chunk.
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_min_chunk_total(usize::MAX);
+    wtr.write_all(b"a").unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(b"a", &*read_frame_depress(&got));
+    assert!(got.len() < 80_000, "{}", got.len());
+}
+
+// The payload of a compressed chunk in the frame format is a raw block.
+#[test]
+fn decompress_frame_payload() {
+    use snap::crc32;
+
+    let data = &include_bytes!("../data/html")[..50_000];
+    let stream = write_frame_press(data);
+    let chunk = &stream[10..];
+    assert_eq!(0x00, chunk[0]);
+    let len = chunk[1] as usize
+        | (chunk[2] as usize) << 8
+        | (chunk[3] as usize) << 16;
+    let crc = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+    let payload = &chunk[8..4 + len];
+    assert_eq!(press(data), payload);
+
+    let mut buf = vec![0; decompress_len(payload).unwrap()];
+    let n =
+        Decoder::new().decompress_frame_payload(payload, &mut buf).unwrap();
+    assert_eq!(data, &buf[..n]);
+    assert_eq!(crc, crc32::crc32c_masked(&buf[..n]));
+}
+
+// A stream written with a nonstandard checksum mask can only be read with
+// the same mask.
+#[test]
+fn crc_mask_constant() {
+    use snap::{read, write};
+    use std::io::{Read, Write};
+
+    let data = &include_bytes!("../data/html")[..];
+    let delta = 0x12345678;
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.set_crc_mask_constant(delta);
+    wtr.write_all(data).unwrap();
+    let comp = wtr.into_inner().unwrap();
+
+    let mut rdr = read::FrameEncoder::new(data);
+    rdr.set_crc_mask_constant(delta);
+    let mut comp2 = vec![];
+    rdr.read_to_end(&mut comp2).unwrap();
+    assert_eq!(comp, comp2);
+
+    let mut got = vec![];
+    let mut rdr = read::FrameDecoder::new(&*comp);
+    rdr.set_crc_mask_constant(delta);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+
+    let mut wtr = write::FrameDecoder::new(vec![]);
+    wtr.set_crc_mask_constant(delta);
+    wtr.write_all(&comp).unwrap();
+    assert_eq!(data, &**wtr.get_ref());
+
+    let mut rdr = read::FrameDecoder::new(&*comp);
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert!(matches!(err, Error::Checksum { .. }));
+}
+
+// The sans-io frame decoder produces the same bytes regardless of how its
+// input is split, and keeps incomplete chunks until they're completed.
+#[test]
+fn frame_decoder_push_pull() {
+    use snap::frame;
+
+    let data = &include_bytes!("../data/html")[..];
+    let comp = write_frame_press(&[data, data].concat());
+    for &size in &[1, 3, 7, 1000, 65536, comp.len()] {
+        let mut dec = frame::Decoder::new();
+        let mut got = vec![];
+        for piece in comp.chunks(size) {
+            dec.push(piece);
+            let before = got.len();
+            let n = dec.pull(&mut got).unwrap();
+            assert_eq!(before + n, got.len());
+        }
+        assert_eq!(0, dec.pending_input());
+        assert_eq!([data, data].concat(), got);
+    }
+
+    // A truncated stream leaves pending input behind.
+    let mut dec = frame::Decoder::new();
+    dec.push(&comp[..comp.len() - 1]);
+    let mut got = vec![];
+    dec.pull(&mut got).unwrap();
+    assert!(dec.pending_input() > 0);
+
+    // Corruption is reported.
+    let mut dec = frame::Decoder::new();
+    dec.push(b"\x00\x01\x00\x00");
+    let err = dec.pull(&mut vec![]).unwrap_err();
+    assert_eq!(Error::StreamHeader { byte: 0 }, err);
+}
+
+// The sans-io frame encoder produces the same stream as write::FrameEncoder,
+// regardless of how its input is split.
+#[test]
+fn frame_encoder_push_pull() {
+    use snap::{frame, read};
+    use std::io::Read;
+
+    let data = &include_bytes!("../data/html")[..];
+    let input = [data, data].concat();
+    let want = write_frame_press(&input);
+    for &size in &[1, 1000, 65536, 70000, input.len()] {
+        let mut enc = frame::Encoder::new();
+        let mut got = vec![];
+        for piece in input.chunks(size) {
+            enc.push(piece);
+            let before = got.len();
+            let n = enc.pull(&mut got);
+            assert_eq!(before + n, got.len());
+        }
+        enc.finish(&mut got);
+        assert_eq!(want, got);
+
+        let mut dec = vec![];
+        read::FrameDecoder::new(&*got).read_to_end(&mut dec).unwrap();
+        assert_eq!(input, dec);
+    }
+
+    // Nothing is emitted for an empty stream.
+    let mut got = vec![];
+    assert_eq!(0, frame::Encoder::new().finish(&mut got));
+    assert!(got.is_empty());
+}
+
+// Folding with carry-less multiplication agrees with the bitwise
+// algorithm at every length around its block sizes and thresholds.
+#[test]
+fn crc32c_pclmulqdq() {
+    use snap::crc32::{Crc32c, CrcAlgo};
+
+    let data = &include_bytes!("../data/fireworks.jpeg")[..];
+    for len in (0..600).chain([4096, 65535, 65536, 65537]) {
+        for start in [0, 1, 15] {
+            let data = &data[start..start + len];
+            let mut want = Crc32c::with_algorithm(CrcAlgo::Bitwise);
+            want.update(data);
+            let mut got = Crc32c::with_algorithm(CrcAlgo::Pclmulqdq);
+            got.update(data);
+            assert_eq!(want.finalize(), got.finalize(), "{} bytes", len);
+        }
+    }
+}
+
+// Shrinking a decoder's buffers keeps everything that's buffered, and they
+// grow back as needed to decode more chunks.
+#[test]
+fn read_frame_decoder_shrink_buffers() {
+    use snap::read;
+    use std::io::{BufRead, Read};
+
+    let data = &include_bytes!("../data/html_x_4")[..];
+    let comp = write_frame_press(data);
+
+    let mut rdr = read::FrameDecoder::new(&*comp);
+    let (src, dst) = rdr.buffer_sizes();
+    assert!(src > 65536 && dst == 65536);
+    rdr.shrink_buffers();
+    assert_eq!((10, 0), rdr.buffer_sizes());
+
+    // Shrink with decompressed bytes pending.
+    let mut got = vec![0; 1000];
+    rdr.read_exact(&mut got).unwrap();
+    rdr.shrink_buffers();
+    let pending = rdr.fill_buf().unwrap().len();
+    assert_eq!(65536 - 1000, pending);
+    assert_eq!((10, pending), rdr.buffer_sizes());
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+    assert_eq!((src, dst), rdr.buffer_sizes());
+
+    // Shrink in the middle of reading a chunk, which is resumed after.
+    struct BlockOnce<'a> {
+        data: &'a [u8],
+        block_at: Option<usize>,
+    }
+
+    impl<'a> Read for BlockOnce<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut n = std::cmp::min(buf.len(), self.data.len());
+            if let Some(at) = self.block_at {
+                if at == 0 {
+                    self.block_at = None;
+                    return Err(std::io::ErrorKind::WouldBlock.into());
+                }
+                n = std::cmp::min(n, at);
+                self.block_at = Some(at - n);
+            }
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    let rdr = BlockOnce { data: &comp, block_at: Some(1000) };
+    let mut rdr = read::FrameDecoder::new(rdr);
+    let mut got = vec![];
+    let err = rdr.read_to_end(&mut got).unwrap_err();
+    assert_eq!(std::io::ErrorKind::WouldBlock, err.kind());
+    rdr.shrink_buffers();
+    assert!(rdr.buffer_sizes().0 > 1000);
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!(data, &*got);
+
+    // A decoder with small buffers still rejects big chunks after shrinking.
+    let mut rdr = read::FrameDecoder::with_capacity(&*comp, 1000);
+    rdr.shrink_buffers();
+    let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+    let err = unwrap_snap_err(err);
+    assert!(matches!(err, Error::UnsupportedChunkLength { .. }));
+}
+
+// Records produced on demand by a closure make it into the stream intact,
+// with one chunk for each buffer the closure fills.
+#[test]
+fn write_frame_encoder_compress_from() {
+    use snap::write;
+    use std::io;
+
+    let records: Vec<String> =
+        (0..1000).map(|i| format!("record {} of 1000\n", i)).collect();
+    let mut next = 0;
+    let mut calls = 0;
+    let comp = write::FrameEncoder::compress_from(vec![], |buf| {
+        calls += 1;
+        // Fill buffers of at most 1000 bytes, for several chunks.
+        while next < records.len() && buf.len() + records[next].len() <= 1000 {
+            buf.extend_from_slice(records[next].as_bytes());
+            next += 1;
+        }
+        Ok(next < records.len())
+    })
+    .unwrap();
+    assert!(calls > 1);
+
+    let got: Vec<String> = snap::read::FrameDecoder::new(&*comp)
+        .lines()
+        .map(|line| line.unwrap() + "\n")
+        .collect();
+    assert_eq!(records, got);
+    // The stream identifier followed by one chunk per call.
+    let mut chunks = 0;
+    let mut rest = &comp[10..];
+    while !rest.is_empty() {
+        let len = rest[1] as usize
+            | (rest[2] as usize) << 8
+            | (rest[3] as usize) << 16;
+        rest = &rest[4 + len..];
+        chunks += 1;
+    }
+    assert_eq!(calls, chunks);
+
+    // Errors from the closure are returned.
+    let err = write::FrameEncoder::compress_from(vec![], |_| {
+        Err(io::Error::new(io::ErrorKind::Other, "boom"))
+    })
+    .unwrap_err();
+    assert_eq!("boom", err.to_string());
+}
+
+// The checked maximum compressed length agrees with max_compress_len
+// wherever the latter doesn't signal an error with 0.
+#[test]
+fn checked_max_compress_len() {
+    use snap::raw::{checked_max_compress_len, max_compress_len};
+
+    for len in [0, 1, 1000, 1 << 16, 1 << 20, 3_681_400_000] {
+        assert_eq!(Some(max_compress_len(len)), checked_max_compress_len(len));
+        assert_ne!(0, max_compress_len(len));
+    }
+    // The input is small enough, but its maximum compressed size isn't.
+    assert_eq!(None, checked_max_compress_len(u32::MAX as usize));
+    assert_eq!(0, max_compress_len(u32::MAX as usize));
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn checked_max_compress_len_too_big() {
+    use snap::raw::{checked_max_compress_len, max_compress_len};
+
+    let len = u32::MAX as usize + 1;
+    assert_eq!(None, checked_max_compress_len(len));
+    assert_eq!(0, max_compress_len(len));
+}
+
+// A decoder counts each kind of chunk it reads, along with the bytes and
+// checksums involved.
+#[test]
+fn read_frame_decoder_stats() {
+    use snap::{crc32, read};
+    use std::io::Read;
+
+    fn chunk(ty: u8, body: &[u8]) -> Vec<u8> {
+        let len = body.len() as u32;
+        let mut chunk =
+            vec![ty, len as u8, (len >> 8) as u8, (len >> 16) as u8];
+        chunk.extend_from_slice(body);
+        chunk
+    }
+
+    let text = &include_bytes!("../data/html")[..1000];
+    let raw = b"not worth compressing";
+    let compressed = write_frame_press(text);
+    let mut uncompressed = crc32::crc32c_masked(raw).to_le_bytes().to_vec();
+    uncompressed.extend_from_slice(raw);
+
+    let mut stream = compressed.clone();
+    stream.extend_from_slice(&chunk(0x01, &uncompressed));
+    stream.extend_from_slice(&chunk(0xFE, &[0; 100]));
+    stream.extend_from_slice(&chunk(0x80, b"metadata"));
+    stream.extend_from_slice(&chunk(0xFE, &[]));
+    // A second stream, concatenated to the first.
+    stream.extend_from_slice(&compressed);
+
+    let mut rdr = read::FrameDecoder::new(&*stream);
+    assert_eq!(read::FrameStats::default(), rdr.stats());
+    let mut got = vec![];
+    rdr.read_to_end(&mut got).unwrap();
+    assert_eq!([text, &raw[..], text].concat(), got);
+
+    let stats = rdr.stats();
+    assert_eq!(2, stats.compressed_chunks());
+    assert_eq!(1, stats.uncompressed_chunks());
+    assert_eq!(2, stats.padding_chunks());
+    assert_eq!(1, stats.skippable_chunks());
+    assert_eq!(stream.len() as u64, stats.compressed_bytes());
+    assert_eq!(got.len() as u64, stats.decompressed_bytes());
+    assert_eq!(3, stats.checksums());
+
+    // A checksum that doesn't match is counted as verified, but the chunk
+    // isn't counted as read.
+    let mut bad = compressed.clone();
+    let last = bad.len() - 1;
+    bad[last] ^= 1;
+    let mut rdr = read::FrameDecoder::new(&*bad);
+    rdr.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(1, rdr.stats().checksums());
+    assert_eq!(0, rdr.stats().compressed_chunks());
+}
+
+// maybe_flush writes the buffered bytes as a chunk only when asked to.
+#[test]
+fn write_frame_encoder_maybe_flush() {
+    use snap::write;
+    use std::io::Write;
+
+    let mut wtr = write::FrameEncoder::new(vec![]);
+    wtr.maybe_flush(|n| {
+        assert_eq!(0, n);
+        true
+    })
+    .unwrap();
+    assert!(wtr.get_ref().is_empty());
+
+    wtr.write_all(b"hello").unwrap();
+    wtr.maybe_flush(|n| {
+        assert_eq!(5, n);
+        false
+    })
+    .unwrap();
+    assert!(wtr.get_ref().is_empty());
+
+    wtr.maybe_flush(|n| n > 0).unwrap();
+    assert_eq!(write_frame_press(b"hello"), *wtr.get_ref());
+    wtr.maybe_flush(|n| {
+        assert_eq!(0, n);
+        true
+    })
+    .unwrap();
+
+    wtr.write_all(b", world").unwrap();
+    let got = wtr.into_inner().unwrap();
+    assert_eq!(b"hello, world", &*read_frame_depress(&got));
+}